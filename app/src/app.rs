@@ -2,16 +2,14 @@
 
 use itertools::Itertools;
 
-use serde::{Deserialize, Serialize};
-
 use dioxus::prelude::*;
 use wasm_bindgen::prelude::*;
 
-use reqwest;
+use reqwest::StatusCode;
 
 use snowy_model::TeamView;
 
-use crate::error::Error;
+use crate::{auth, error::Error};
 
 #[wasm_bindgen]
 extern "C" {
@@ -22,17 +20,32 @@ extern "C" {
     async fn invoke(cmd: &str, args: JsValue) -> JsValue;
 }
 
-#[derive(Serialize, Deserialize)]
-struct FetchTeamStateArgs<'a> {
-    api_base_url: &'a str,
-    team_id: &'a str,
+/// Random lowercase hex string of `len` characters.
+fn random_hex(len: usize) -> String {
+    (0..len)
+        .map(|_| format!("{:x}", (js_sys::Math::random() * 16.0) as u8))
+        .collect()
+}
+
+/// W3C `traceparent` header starting a new sampled trace for an API call.
+fn traceparent() -> String {
+    format!("00-{}-{}-01", random_hex(32), random_hex(16))
 }
 
 async fn fetch_team_state(api_base_url: &str, team_id: &str) -> Result<TeamView, Error> {
-    Ok(reqwest::get(&format!("{}/team/{}", api_base_url, team_id))
-        .await?
-        .json::<TeamView>()
-        .await?)
+    let access_token = auth::access_token().ok_or(Error::SignedOut)?;
+    let response = reqwest::Client::new()
+        .get(format!("{}/team/{}", api_base_url, team_id))
+        .header("traceparent", traceparent())
+        .bearer_auth(access_token)
+        .send()
+        .await?;
+    if response.status() == StatusCode::UNAUTHORIZED {
+        auth::sign_out();
+        return Err(Error::SignedOut);
+    }
+
+    Ok(response.error_for_status()?.json::<TeamView>().await?)
 }
 
 pub fn App() -> Element {
@@ -59,6 +72,17 @@ pub fn App() -> Element {
                 }
             }
 
+            if let Some(Err(Error::SignedOut)) = &*team_state.read() {
+                div { class: "row",
+                    div { class: "col",
+                        match auth::LOGIN_URL {
+                            Some(url) => rsx! { a { class: "btn", href: url, "Sign in" } },
+                            None => rsx! { p { "Sign in to see your team." } },
+                        }
+                    }
+                }
+            }
+
             div { class: "row",
                 div { class: "card",
                     div { class: "row",
@@ -75,7 +99,7 @@ pub fn App() -> Element {
                                         None => rsx! { p { "Max: ---" } },
                                     }
                                 },
-                                Some(Err(_)) => rsx! { p { {format!("Error getting temperatures")} } },
+                                Some(Err(_)) => rsx! { p { "Error getting temperatures" } },
                                 None => rsx! { p { "Loading..." } },
                             }
                         }
@@ -91,7 +115,7 @@ pub fn App() -> Element {
                                         p { {format!("{:?}: {}", weather_code, count)} }
                                     }
                                 },
-                                Some(Err(_)) => rsx! { p { {format!("Error getting weather conditions")} } },
+                                Some(Err(_)) => rsx! { p { "Error getting weather conditions" } },
                                 None => rsx! { p { "Loading..." } },
                             }
                         }
@@ -0,0 +1,41 @@
+use wasm_bindgen::JsValue;
+use web_sys::window;
+
+const ACCESS_TOKEN_KEY: &str = "snowy.access_token";
+
+/// Identity provider page signing users in and redirecting back to the app
+/// with `#access_token=...`. A public URL, set at build time.
+pub(crate) const LOGIN_URL: Option<&str> = option_env!("SNOWY_LOGIN_URL");
+
+/// Bearer token of the signed-in user, if any.
+///
+/// A token handed over in the URL fragment after signing in is moved to
+/// session storage and removed from the address bar.
+pub(crate) fn access_token() -> Option<String> {
+    let window = window()?;
+    let storage = window.session_storage().ok()??;
+
+    let location = window.location();
+    let fragment = location.hash().unwrap_or_default();
+    let redirected = fragment
+        .trim_start_matches('#')
+        .split('&')
+        .find_map(|parameter| parameter.strip_prefix("access_token="))
+        .filter(|token| !token.is_empty());
+    if let Some(token) = redirected {
+        let _ = storage.set_item(ACCESS_TOKEN_KEY, token);
+        if let (Ok(history), Ok(path)) = (window.history(), location.pathname()) {
+            let _ = history.replace_state_with_url(&JsValue::NULL, "", Some(&path));
+        }
+        return Some(token.to_string());
+    }
+
+    storage.get_item(ACCESS_TOKEN_KEY).ok()?
+}
+
+/// Forgets the token, e.g. once the server has rejected it.
+pub(crate) fn sign_out() {
+    if let Some(storage) = window().and_then(|window| window.session_storage().ok()?) {
+        let _ = storage.remove_item(ACCESS_TOKEN_KEY);
+    }
+}
@@ -4,6 +4,8 @@ pub(crate) enum Error {
     SerDe(#[from] serde_wasm_bindgen::Error),
     #[error("HTTP Error: {0}")]
     Http(#[from] reqwest::Error),
+    #[error("Not signed in")]
+    SignedOut,
 }
 
 impl serde::Serialize for Error {
@@ -1,5 +1,8 @@
 pub mod team;
 pub mod weather;
 
-pub use team::{Member, MemberId, Team, TeamId, TeamView};
-pub use weather::{CelsiusTemperature, WeatherCode, WeatherForecast};
+pub use team::{
+    ForecastRecord, Member, MemberId, Membership, Team, TeamId, TeamSummary, TeamSummaryPage,
+    TeamView, TemperatureExtreme,
+};
+pub use weather::{CelsiusTemperature, WeatherCategory, WeatherCode, WeatherForecast};
@@ -1,10 +1,14 @@
 use std::collections::HashMap;
 
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
-use crate::{CelsiusTemperature, WeatherCode, WeatherForecast};
+use crate::{
+    weather::WindSpeed, CelsiusTemperature, WeatherCategory, WeatherCode, WeatherForecast,
+};
 
 #[derive(Serialize, Default, Deserialize, Debug, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 #[serde(transparent)]
 pub struct MemberId(String);
 
@@ -12,24 +16,46 @@ impl MemberId {
     pub fn new(id: String) -> Self {
         Self(id)
     }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
 }
 
 #[derive(Serialize, Default, Deserialize, Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct Member {
     pub id: MemberId,
     pub email: String,
+    #[serde(default)]
+    pub roles: Vec<String>,
 }
 
 impl Member {
     pub fn new(id: MemberId, email: String) -> Self {
-        Self { id, email }
+        Self {
+            id,
+            email,
+            roles: vec![],
+        }
+    }
+
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|r| r == role)
     }
 }
 
 #[derive(Serialize, Default, Deserialize, Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 #[serde(transparent)]
 pub struct TeamId(String);
 
+impl TeamId {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
 impl From<String> for TeamId {
     fn from(id: String) -> Self {
         Self(id)
@@ -43,6 +69,7 @@ impl From<&str> for TeamId {
 }
 
 #[derive(Serialize, Default, Deserialize, Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct Team {
     pub id: TeamId,
     pub name: String,
@@ -51,13 +78,88 @@ pub struct Team {
 
 // for queries
 #[derive(Debug, Default, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct TeamView {
     pub id: TeamId,
     pub name: String,
     pub members: Vec<Member>,
     pub forecasts: HashMap<MemberId, WeatherForecast>,
+    /// Forecasts tracked over the team's lifetime, replacements included;
+    /// `forecasts` only holds the latest one per member.
     pub total_forecasts_tracked: u64,
     pub avg_minimum_temperature: Option<CelsiusTemperature>,
     pub avg_maximum_temperature: Option<CelsiusTemperature>,
     pub weather_condition_distribution: HashMap<WeatherCode, i32>,
+    #[serde(default)]
+    pub median_minimum_temperature: Option<CelsiusTemperature>,
+    #[serde(default)]
+    pub median_maximum_temperature: Option<CelsiusTemperature>,
+    /// The coldest minimum temperature among the current forecasts.
+    #[serde(default)]
+    pub lowest_minimum_temperature: Option<TemperatureExtreme>,
+    /// The warmest maximum temperature among the current forecasts.
+    #[serde(default)]
+    pub highest_maximum_temperature: Option<TemperatureExtreme>,
+    /// Averaged over the forecasts that include apparent temperatures.
+    #[serde(default)]
+    pub avg_minimum_apparent_temperature: Option<CelsiusTemperature>,
+    #[serde(default)]
+    pub avg_maximum_apparent_temperature: Option<CelsiusTemperature>,
+    /// In km/h, averaged over the forecasts that include wind speeds.
+    #[serde(default)]
+    pub avg_maximum_wind_speed: Option<WindSpeed>,
+    /// Average of [`WeatherForecast::comfort_index`] over the current forecasts.
+    #[serde(default)]
+    pub avg_comfort_index: Option<f32>,
+    #[serde(default)]
+    pub weather_category_distribution: HashMap<WeatherCategory, i32>,
+}
+
+/// A temperature reached by one member's forecast.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct TemperatureExtreme {
+    pub member_id: MemberId,
+    pub temperature: CelsiusTemperature,
+}
+
+/// A team as listed by `GET /api/teams`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct TeamSummary {
+    pub id: TeamId,
+    pub name: String,
+    pub member_count: u32,
+    pub last_activity_at: DateTime<Utc>,
+}
+
+/// One page of team summaries, along with the total number of matches.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct TeamSummaryPage {
+    pub teams: Vec<TeamSummary>,
+    pub total: u64,
+    pub offset: u64,
+    pub limit: u64,
+}
+
+/// A member's place in one team, as indexed for `GET /api/members/<id>`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Membership {
+    pub team_id: TeamId,
+    pub team_name: String,
+    pub member_id: MemberId,
+    pub email: String,
+    pub roles: Vec<String>,
+    pub latest_forecast: Option<WeatherForecast>,
+}
+
+/// A forecast as recorded in the forecast history, for charting trends.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ForecastRecord {
+    pub member_id: MemberId,
+    pub forecast: WeatherForecast,
+    pub tracked_at: DateTime<Utc>,
 }
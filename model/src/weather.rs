@@ -2,9 +2,11 @@ use chrono::{NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Default, Deserialize, Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct CelsiusTemperature(pub f32);
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub enum WindSpeedUnit {
     #[serde(rename = "m/s")]
     MetersPerSecond,
@@ -14,13 +16,48 @@ pub enum WindSpeedUnit {
     MilesPerHour,
 }
 
+impl WindSpeedUnit {
+    /// The unit's symbol, as serialized.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WindSpeedUnit::MetersPerSecond => "m/s",
+            WindSpeedUnit::KilometersPerHour => "km/h",
+            WindSpeedUnit::MilesPerHour => "mph",
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct WindSpeed {
     value: f32,
     unit: WindSpeedUnit,
 }
 
+impl WindSpeed {
+    pub fn new(value: f32, unit: WindSpeedUnit) -> Self {
+        Self { value, unit }
+    }
+
+    pub fn value(&self) -> f32 {
+        self.value
+    }
+
+    pub fn unit(&self) -> &WindSpeedUnit {
+        &self.unit
+    }
+
+    pub fn to_kilometers_per_hour(&self) -> f32 {
+        match self.unit {
+            WindSpeedUnit::MetersPerSecond => self.value * 3.6,
+            WindSpeedUnit::KilometersPerHour => self.value,
+            WindSpeedUnit::MilesPerHour => self.value * 1.609_344,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub enum WindDirection {
     North,
     NorthEast,
@@ -36,6 +73,7 @@ pub enum WindDirection {
 /// Uses the WMO weather code
 /// https://open-meteo.com/en/docs for more information
 #[derive(Serialize, Default, Deserialize, Debug, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub enum WeatherCode {
     #[default]
     ClearSky = 0,
@@ -68,7 +106,38 @@ pub enum WeatherCode {
     HeavyHailThunderstorm = 99,
 }
 
+/// Coarse grouping of weather codes, for summaries.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub enum WeatherCategory {
+    Clear,
+    Cloudy,
+    Rain,
+    Snow,
+    Storm,
+}
+
+impl WeatherCode {
+    pub fn category(&self) -> WeatherCategory {
+        use WeatherCode::*;
+
+        match self {
+            ClearSky | MainlyClear => WeatherCategory::Clear,
+            PartlyCloudy | Overcast | Fog | DepositingRimeFog => WeatherCategory::Cloudy,
+            LightDrizzle | ModerateDrizzle | DenseDrizzle | LightFreezingDrizzle
+            | DenseFreezingDrizzle | LightRain | ModerateRain | HeavyRain | LightFreezingRain
+            | HeavyFreezingRain | LightRainShowers | ModerateRainShowers | HeavyRainShowers => {
+                WeatherCategory::Rain
+            }
+            LightSnow | ModerateSnow | HeavySnow | SnowGrains | LightSnowShowers
+            | HeavySnowShowers => WeatherCategory::Snow,
+            Thunderstorm | LightHailThunderstorm | HeavyHailThunderstorm => WeatherCategory::Storm,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct WeatherForecast {
     pub date: NaiveDate,
     pub minimum_temperature: CelsiusTemperature,
@@ -94,3 +163,37 @@ impl Default for WeatherForecast {
         }
     }
 }
+
+impl WeatherForecast {
+    /// How pleasant the day is expected to be, from 0 (miserable) to 100.
+    ///
+    /// Starts from 100 and deducts 4 points per degree the mean apparent
+    /// temperature (or the mean temperature, when unknown) is away from 21°C,
+    /// 1 point per km/h of wind above 20 km/h, and a fixed amount for clouds
+    /// (5), rain (20), snow (25) or storms (40).
+    pub fn comfort_index(&self) -> f32 {
+        let (minimum, maximum) = match (
+            &self.minimum_apparent_temperature,
+            &self.maximum_apparent_temperature,
+        ) {
+            (Some(minimum), Some(maximum)) => (minimum.0, maximum.0),
+            _ => (self.minimum_temperature.0, self.maximum_temperature.0),
+        };
+        let temperature_penalty = ((minimum + maximum) / 2.0 - 21.0).abs() * 4.0;
+
+        let wind_penalty = self
+            .maximum_wind_speed
+            .as_ref()
+            .map_or(0.0, |w| (w.to_kilometers_per_hour() - 20.0).max(0.0));
+
+        let weather_penalty = match self.weather_code.as_ref().map(WeatherCode::category) {
+            None | Some(WeatherCategory::Clear) => 0.0,
+            Some(WeatherCategory::Cloudy) => 5.0,
+            Some(WeatherCategory::Rain) => 20.0,
+            Some(WeatherCategory::Snow) => 25.0,
+            Some(WeatherCategory::Storm) => 40.0,
+        };
+
+        (100.0 - temperature_penalty - wind_penalty - weather_penalty).clamp(0.0, 100.0)
+    }
+}
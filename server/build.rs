@@ -0,0 +1,7 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Generating the gRPC service requires `protoc`, so only do it when the
+    // `grpc` feature is enabled.
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/snowy.proto")?;
+    Ok(())
+}
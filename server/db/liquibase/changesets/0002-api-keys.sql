@@ -0,0 +1,20 @@
+--liquibase formatted sql
+
+--changeset snowy:2
+--comment: API keys scoped to a team and a set of commands
+CREATE TABLE api_keys
+(
+    id         uuid                                NOT NULL,
+    team_id    text                                NOT NULL,
+    name       text                                NOT NULL,
+    key_hash   text                                NOT NULL,
+    scopes     text[]                              NOT NULL,
+    created_at timestamptz DEFAULT now()           NOT NULL,
+    revoked_at timestamptz,
+    PRIMARY KEY (id)
+);
+
+CREATE UNIQUE INDEX api_keys_key_hash_idx ON api_keys (key_hash);
+CREATE INDEX api_keys_team_id_idx ON api_keys (team_id);
+
+--rollback DROP TABLE api_keys;
@@ -0,0 +1,50 @@
+--liquibase formatted sql
+
+--changeset snowy:3
+--comment: team listing projection
+CREATE TABLE team_summary
+(
+    team_id          text                          NOT NULL,
+    name             text        DEFAULT ''        NOT NULL,
+    member_count     integer     DEFAULT 0         NOT NULL,
+    last_activity_at timestamptz DEFAULT now()     NOT NULL,
+    PRIMARY KEY (team_id)
+);
+
+CREATE TABLE team_summary_members
+(
+    team_id   text NOT NULL REFERENCES team_summary (team_id) ON DELETE CASCADE,
+    member_id text NOT NULL,
+    email     text NOT NULL,
+    PRIMARY KEY (team_id, member_id)
+);
+
+CREATE INDEX team_summary_members_email_idx ON team_summary_members (lower(email));
+
+-- backfill teams recorded before the projection existed
+INSERT INTO team_summary (team_id)
+SELECT DISTINCT aggregate_id FROM events WHERE aggregate_type = 'team';
+
+INSERT INTO team_summary_members (team_id, member_id, email)
+SELECT DISTINCT ON (added.aggregate_id, added.payload -> 'MemberAdded' ->> 'member_id')
+       added.aggregate_id,
+       added.payload -> 'MemberAdded' ->> 'member_id',
+       added.payload -> 'MemberAdded' ->> 'email'
+FROM events added
+WHERE added.aggregate_type = 'team'
+  AND added.event_type = 'member-added'
+  AND NOT EXISTS (SELECT 1
+                  FROM events removed
+                  WHERE removed.aggregate_type = 'team'
+                    AND removed.aggregate_id = added.aggregate_id
+                    AND removed.event_type = 'member-removed'
+                    AND removed.payload -> 'MemberRemoved' ->> 'member_id' =
+                        added.payload -> 'MemberAdded' ->> 'member_id'
+                    AND removed.sequence > added.sequence)
+ORDER BY added.aggregate_id, added.payload -> 'MemberAdded' ->> 'member_id', added.sequence DESC;
+
+UPDATE team_summary s
+SET member_count = (SELECT count(*) FROM team_summary_members m WHERE m.team_id = s.team_id);
+
+--rollback DROP TABLE team_summary_members;
+--rollback DROP TABLE team_summary;
\ No newline at end of file
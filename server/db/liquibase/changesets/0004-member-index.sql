@@ -0,0 +1,55 @@
+--liquibase formatted sql
+
+--changeset snowy:4
+--comment: teams each member belongs to, with their latest forecast
+CREATE TABLE member_index
+(
+    member_id       text                      NOT NULL,
+    team_id         text                      NOT NULL,
+    email           text                      NOT NULL,
+    roles           text[]      DEFAULT '{}'  NOT NULL,
+    latest_forecast json,
+    updated_at      timestamptz DEFAULT now() NOT NULL,
+    PRIMARY KEY (member_id, team_id)
+);
+
+CREATE INDEX member_index_email_idx ON member_index (lower(email));
+CREATE INDEX member_index_team_id_idx ON member_index (team_id);
+
+-- backfill memberships known to the team listing projection, with the roles
+-- and the latest forecast recorded since each member was last added
+INSERT INTO member_index (member_id, team_id, email, roles, latest_forecast)
+SELECT m.member_id,
+       m.team_id,
+       m.email,
+       ARRAY(SELECT latest.role
+             FROM (SELECT DISTINCT ON (change.value ->> 'role') change.value ->> 'role' AS role,
+                                                               e.event_type,
+                                                               e.sequence
+                   FROM events e,
+                        LATERAL (SELECT coalesce(e.payload -> 'RoleGranted', e.payload -> 'RoleRevoked') AS value) change
+                   WHERE e.aggregate_type = 'team'
+                     AND e.aggregate_id = m.team_id
+                     AND e.event_type IN ('role-granted', 'role-revoked')
+                     AND e.sequence > added.sequence
+                     AND change.value ->> 'member_id' = m.member_id
+                   ORDER BY change.value ->> 'role', e.sequence DESC) latest
+             WHERE latest.event_type = 'role-granted'
+             ORDER BY latest.sequence),
+       (SELECT e.payload -> 'ForecastTracked' -> 'forecasts' -> m.member_id
+        FROM events e
+        WHERE e.aggregate_type = 'team'
+          AND e.aggregate_id = m.team_id
+          AND e.event_type = 'forecast-tracked'
+          AND e.sequence > added.sequence
+        ORDER BY e.sequence DESC
+        LIMIT 1)
+FROM team_summary_members m,
+     LATERAL (SELECT max(e.sequence) AS sequence
+              FROM events e
+              WHERE e.aggregate_type = 'team'
+                AND e.aggregate_id = m.team_id
+                AND e.event_type = 'member-added'
+                AND e.payload -> 'MemberAdded' ->> 'member_id' = m.member_id) added;
+
+--rollback DROP TABLE member_index;
\ No newline at end of file
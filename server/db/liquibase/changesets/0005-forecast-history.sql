@@ -0,0 +1,29 @@
+--liquibase formatted sql
+
+--changeset snowy:5
+--comment: every tracked forecast, for charting trends
+CREATE TABLE forecast_history
+(
+    id                           bigserial                 NOT NULL,
+    team_id                      text                      NOT NULL,
+    sequence                     bigint                    NOT NULL,
+    member_id                    text                      NOT NULL,
+    date                         date                      NOT NULL,
+    minimum_temperature          real                      NOT NULL,
+    maximum_temperature          real                      NOT NULL,
+    minimum_apparent_temperature real,
+    maximum_apparent_temperature real,
+    maximum_wind_speed           real,
+    wind_speed_unit              text,
+    dominant_wind_direction      text,
+    weather_code                 integer,
+    forecast                     json                      NOT NULL,
+    tracked_at                   timestamptz DEFAULT now() NOT NULL,
+    PRIMARY KEY (id),
+    UNIQUE (team_id, sequence)
+);
+
+CREATE INDEX forecast_history_team_date_idx ON forecast_history (team_id, date);
+CREATE INDEX forecast_history_team_member_date_idx ON forecast_history (team_id, member_id, date);
+
+--rollback DROP TABLE forecast_history;
\ No newline at end of file
@@ -0,0 +1,39 @@
+--liquibase formatted sql
+
+--changeset snowy:6 splitStatements:false
+--comment: outbox of team events, written by a trigger in the same transaction as the event
+CREATE TABLE outbox
+(
+    id             bigserial                 NOT NULL,
+    aggregate_type text                      NOT NULL,
+    aggregate_id   text                      NOT NULL,
+    sequence       bigint                    NOT NULL,
+    event_type     text                      NOT NULL,
+    payload        json                      NOT NULL,
+    metadata       json                      NOT NULL,
+    recorded_at    timestamptz DEFAULT now() NOT NULL,
+    processed_at   timestamptz,
+    PRIMARY KEY (id),
+    UNIQUE (aggregate_type, aggregate_id, sequence)
+);
+
+CREATE INDEX outbox_unprocessed_idx ON outbox (id) WHERE processed_at IS NULL;
+
+CREATE FUNCTION events_to_outbox() RETURNS trigger AS
+$$
+BEGIN
+    INSERT INTO outbox (aggregate_type, aggregate_id, sequence, event_type, payload, metadata)
+    VALUES (NEW.aggregate_type, NEW.aggregate_id, NEW.sequence, NEW.event_type, NEW.payload, NEW.metadata);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER events_outbox
+    AFTER INSERT
+    ON events
+    FOR EACH ROW
+EXECUTE FUNCTION events_to_outbox();
+
+--rollback DROP TRIGGER events_outbox ON events;
+--rollback DROP FUNCTION events_to_outbox();
+--rollback DROP TABLE outbox;
\ No newline at end of file
@@ -0,0 +1,50 @@
+--liquibase formatted sql
+
+--changeset snowy:7
+--comment: webhook subscriptions per team and their deliveries
+CREATE TABLE webhook_subscriptions
+(
+    id          uuid                      NOT NULL,
+    team_id     text                      NOT NULL,
+    url         text                      NOT NULL,
+    secret      text                      NOT NULL,
+    event_types text[]                    NOT NULL,
+    created_at  timestamptz DEFAULT now() NOT NULL,
+    disabled_at timestamptz,
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX webhook_subscriptions_team_idx ON webhook_subscriptions (team_id) WHERE disabled_at IS NULL;
+
+CREATE TABLE webhook_deliveries
+(
+    id               uuid        DEFAULT gen_random_uuid() NOT NULL,
+    subscription_id  uuid                                  NOT NULL REFERENCES webhook_subscriptions (id) ON DELETE CASCADE,
+    outbox_id        bigint                                NOT NULL REFERENCES outbox (id),
+    status           text        DEFAULT 'pending'         NOT NULL CHECK (status IN ('pending', 'delivered', 'dead')),
+    attempts         integer     DEFAULT 0                 NOT NULL,
+    next_attempt_at  timestamptz DEFAULT now()             NOT NULL,
+    last_status_code integer,
+    last_error       text,
+    created_at       timestamptz DEFAULT now()             NOT NULL,
+    delivered_at     timestamptz,
+    PRIMARY KEY (id),
+    UNIQUE (subscription_id, outbox_id)
+);
+
+CREATE INDEX webhook_deliveries_due_idx ON webhook_deliveries (next_attempt_at) WHERE status = 'pending';
+
+CREATE TABLE webhook_attempts
+(
+    delivery_id  uuid                      NOT NULL REFERENCES webhook_deliveries (id) ON DELETE CASCADE,
+    attempt      integer                   NOT NULL,
+    attempted_at timestamptz DEFAULT now() NOT NULL,
+    status_code  integer,
+    error        text,
+    duration_ms  bigint                    NOT NULL,
+    PRIMARY KEY (delivery_id, attempt)
+);
+
+--rollback DROP TABLE webhook_attempts;
+--rollback DROP TABLE webhook_deliveries;
+--rollback DROP TABLE webhook_subscriptions;
\ No newline at end of file
@@ -0,0 +1,97 @@
+--liquibase formatted sql
+
+--changeset snowy:8 splitStatements:false
+--comment: global ordering of events, notifications on insert and projection checkpoints
+CREATE SEQUENCE events_global_position_seq;
+
+ALTER TABLE events
+    ADD COLUMN global_position bigint,
+    ADD COLUMN recorded_at     timestamptz DEFAULT now() NOT NULL;
+
+WITH ordered AS (SELECT aggregate_type,
+                        aggregate_id,
+                        sequence,
+                        row_number() OVER (ORDER BY aggregate_type, aggregate_id, sequence) AS position
+                 FROM events)
+UPDATE events e
+SET global_position = o.position
+FROM ordered o
+WHERE e.aggregate_type = o.aggregate_type
+  AND e.aggregate_id = o.aggregate_id
+  AND e.sequence = o.sequence;
+
+SELECT setval('events_global_position_seq', COALESCE(max(global_position), 0) + 1, false)
+FROM events;
+
+ALTER TABLE events
+    ALTER COLUMN global_position SET NOT NULL;
+ALTER SEQUENCE events_global_position_seq OWNED BY events.global_position;
+CREATE UNIQUE INDEX events_global_position_idx ON events (global_position);
+
+-- Concurrent transactions take positions without waiting on each other, so a
+-- position may become visible after later ones, or never when its transaction
+-- rolls back. The projection runner waits at such gaps until every
+-- transaction that could fill them has ended. The transaction id is assigned
+-- before the position, so that a transaction holding a position appears in
+-- the snapshots taken once a later position is visible.
+CREATE FUNCTION events_assign_global_position() RETURNS trigger AS
+$$
+BEGIN
+    PERFORM pg_current_xact_id();
+    NEW.global_position := nextval('events_global_position_seq');
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER events_global_position
+    BEFORE INSERT
+    ON events
+    FOR EACH ROW
+EXECUTE FUNCTION events_assign_global_position();
+
+CREATE FUNCTION events_notify() RETURNS trigger AS
+$$
+BEGIN
+    PERFORM pg_notify('events', '');
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER events_notify
+    AFTER INSERT
+    ON events
+    FOR EACH STATEMENT
+EXECUTE FUNCTION events_notify();
+
+-- Sequence of the latest event reflected in each team view.
+ALTER TABLE team_query
+    ADD COLUMN sequence bigint;
+
+UPDATE team_query t
+SET sequence = (SELECT max(e.sequence) FROM events e WHERE e.aggregate_type = 'team' AND e.aggregate_id = t.view_id);
+
+CREATE TABLE projection_checkpoints
+(
+    projection      text                      NOT NULL,
+    global_position bigint      DEFAULT 0     NOT NULL,
+    updated_at      timestamptz DEFAULT now() NOT NULL,
+    PRIMARY KEY (projection)
+);
+
+-- The existing views were built inline and are already up to date.
+INSERT INTO projection_checkpoints (projection, global_position)
+SELECT name, COALESCE((SELECT max(global_position) FROM events), 0)
+FROM unnest(ARRAY ['team', 'team_summary', 'member_index']) AS name;
+
+-- The forecast history is rebuilt from the first event, adding the forecasts
+-- tracked before it existed; those already in it are kept.
+INSERT INTO projection_checkpoints (projection)
+VALUES ('forecast_history');
+
+--rollback DROP TABLE projection_checkpoints;
+--rollback ALTER TABLE team_query DROP COLUMN sequence;
+--rollback DROP TRIGGER events_notify ON events;
+--rollback DROP FUNCTION events_notify();
+--rollback DROP TRIGGER events_global_position ON events;
+--rollback DROP FUNCTION events_assign_global_position();
+--rollback ALTER TABLE events DROP COLUMN global_position, DROP COLUMN recorded_at;
\ No newline at end of file
@@ -0,0 +1,10 @@
+--liquibase formatted sql
+
+--changeset snowy:9
+--comment: pause and error state of projections
+ALTER TABLE projection_checkpoints
+    ADD COLUMN paused     boolean DEFAULT false NOT NULL,
+    ADD COLUMN error      text,
+    ADD COLUMN errored_at timestamptz;
+
+--rollback ALTER TABLE projection_checkpoints DROP COLUMN paused, DROP COLUMN error, DROP COLUMN errored_at;
\ No newline at end of file
@@ -0,0 +1,30 @@
+--liquibase formatted sql
+
+--changeset snowy:10
+--comment: events projections failed to apply, retried with backoff
+CREATE TABLE projection_dead_letters
+(
+    id              bigserial                     NOT NULL,
+    projection      text                          NOT NULL REFERENCES projection_checkpoints (projection) ON DELETE CASCADE,
+    aggregate_type  text                          NOT NULL,
+    aggregate_id    text                          NOT NULL,
+    global_position bigint                        NOT NULL,
+    sequence        bigint                        NOT NULL,
+    payload         jsonb                         NOT NULL,
+    error           text                          NOT NULL,
+    attempts        integer     DEFAULT 1         NOT NULL,
+    status          text        DEFAULT 'pending' NOT NULL CHECK (status IN ('pending', 'dead', 'replayed')),
+    created_at      timestamptz DEFAULT now()     NOT NULL,
+    last_attempt_at timestamptz DEFAULT now()     NOT NULL,
+    next_attempt_at timestamptz DEFAULT now()     NOT NULL,
+    resolved_at     timestamptz,
+    PRIMARY KEY (id)
+);
+
+-- The later events of an aggregate wait behind its unresolved dead letter.
+CREATE UNIQUE INDEX projection_dead_letters_open_idx
+    ON projection_dead_letters (projection, aggregate_type, aggregate_id) WHERE status <> 'replayed';
+CREATE INDEX projection_dead_letters_due_idx
+    ON projection_dead_letters (projection, next_attempt_at) WHERE status = 'pending';
+
+--rollback DROP TABLE projection_dead_letters;
\ No newline at end of file
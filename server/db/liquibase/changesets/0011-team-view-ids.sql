@@ -0,0 +1,9 @@
+--liquibase formatted sql
+
+--changeset snowy:11
+--comment: backfill the identifier of team views
+-- Views were stored without their identifier.
+UPDATE team_query
+SET payload = jsonb_set(payload::jsonb, '{id}', to_jsonb(view_id))::json;
+
+--rollback empty
\ No newline at end of file
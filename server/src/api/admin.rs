@@ -0,0 +1,250 @@
+use rocket::{
+    get, post,
+    serde::json::{json, Value},
+    FromForm, State,
+};
+use utoipa::IntoParams;
+
+use crate::projections::{DeadLetterStatus, ProjectionAdmin};
+
+use super::{auth::admin::Admin, error::Error};
+
+const DEFAULT_DEAD_LETTER_LIMIT: i64 = 50;
+const MAX_DEAD_LETTER_LIMIT: i64 = 500;
+
+/// Query string of `GET /api/admin/projections/<name>/dead-letters`.
+#[derive(FromForm, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DeadLetterListParams {
+    /// Only dead letters in this state.
+    status: Option<DeadLetterStatus>,
+    /// Maximum number of dead letters to return, at most 500.
+    limit: Option<i64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/projections",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Every projection with its checkpoint, lag and error state", body = [ProjectionStatus]),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+        (status = 403, description = "Administration is disabled", content_type = "application/problem+json", body = Problem)
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/api/admin/projections")]
+pub async fn list_projections(
+    projections: &State<ProjectionAdmin>,
+    _admin: Admin,
+) -> Result<Value, Error> {
+    Ok(json!(projections.list().await?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/projections/{name}/pause",
+    tag = "admin",
+    params(
+        ("name" = String, Path, description = "Projection name")
+    ),
+    responses(
+        (status = 200, description = "The paused projection; a batch in progress is completed", body = ProjectionStatus),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+        (status = 403, description = "Administration is disabled", content_type = "application/problem+json", body = Problem),
+        (status = 404, description = "No projection with this name", body = ErrorBody)
+    ),
+    security(("admin_token" = []))
+)]
+#[post("/api/admin/projections/<name>/pause")]
+pub async fn pause_projection(
+    projections: &State<ProjectionAdmin>,
+    _admin: Admin,
+    name: &str,
+) -> Result<Option<Value>, Error> {
+    let status = projections.set_paused(name, true).await?;
+    Ok(status.map(|status| json!(status)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/projections/{name}/resume",
+    tag = "admin",
+    params(
+        ("name" = String, Path, description = "Projection name")
+    ),
+    responses(
+        (status = 200, description = "The resumed projection", body = ProjectionStatus),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+        (status = 403, description = "Administration is disabled", content_type = "application/problem+json", body = Problem),
+        (status = 404, description = "No projection with this name", body = ErrorBody)
+    ),
+    security(("admin_token" = []))
+)]
+#[post("/api/admin/projections/<name>/resume")]
+pub async fn resume_projection(
+    projections: &State<ProjectionAdmin>,
+    _admin: Admin,
+    name: &str,
+) -> Result<Option<Value>, Error> {
+    let status = projections.set_paused(name, false).await?;
+    Ok(status.map(|status| json!(status)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/projections/{name}/reset",
+    tag = "admin",
+    params(
+        ("name" = String, Path, description = "Projection name")
+    ),
+    responses(
+        (status = 200, description = "The projection, whose view was cleared and is rebuilt from the first event unless paused", body = ProjectionStatus),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+        (status = 403, description = "Administration is disabled", content_type = "application/problem+json", body = Problem),
+        (status = 404, description = "No projection with this name", body = ErrorBody),
+        (status = 409, description = "The projection's view guards read access and cannot be reset", body = ErrorBody)
+    ),
+    security(("admin_token" = []))
+)]
+#[post("/api/admin/projections/<name>/reset")]
+pub async fn reset_projection(
+    projections: &State<ProjectionAdmin>,
+    _admin: Admin,
+    name: &str,
+) -> Result<Option<Value>, Error> {
+    let status = projections.reset(name).await?;
+    Ok(status.map(|status| json!(status)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/projections/{name}/dead-letters",
+    tag = "admin",
+    params(
+        ("name" = String, Path, description = "Projection name"),
+        DeadLetterListParams
+    ),
+    responses(
+        (status = 200, description = "The latest dead letters, newest first", body = [DeadLetter]),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+        (status = 403, description = "Administration is disabled", content_type = "application/problem+json", body = Problem),
+        (status = 404, description = "No projection with this name", body = ErrorBody)
+    ),
+    security(("admin_token" = []))
+)]
+#[get("/api/admin/projections/<name>/dead-letters?<params..>")]
+pub async fn dead_letters_handler(
+    projections: &State<ProjectionAdmin>,
+    _admin: Admin,
+    name: &str,
+    params: DeadLetterListParams,
+) -> Result<Option<Value>, Error> {
+    let letters = projections
+        .dead_letters(
+            name,
+            params.status,
+            params
+                .limit
+                .unwrap_or(DEFAULT_DEAD_LETTER_LIMIT)
+                .clamp(1, MAX_DEAD_LETTER_LIMIT),
+        )
+        .await?;
+    Ok(letters.map(|letters| json!(letters)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/projections/{name}/dead-letters/{id}/replay",
+    tag = "admin",
+    params(
+        ("name" = String, Path, description = "Projection name"),
+        ("id" = i64, Path, description = "Dead letter identifier")
+    ),
+    responses(
+        (status = 200, description = "The dead letter after the replay: replayed, or failing again", body = DeadLetter),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+        (status = 403, description = "Administration is disabled", content_type = "application/problem+json", body = Problem),
+        (status = 404, description = "No dead letter with this identifier left to replay", body = ErrorBody)
+    ),
+    security(("admin_token" = []))
+)]
+#[post("/api/admin/projections/<name>/dead-letters/<id>/replay")]
+pub async fn replay_dead_letter(
+    projections: &State<ProjectionAdmin>,
+    _admin: Admin,
+    name: &str,
+    id: i64,
+) -> Result<Option<Value>, Error> {
+    let letter = projections.replay(name, id).await?;
+    Ok(letter.map(|letter| json!(letter)))
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::{
+        http::{Header, Status},
+        local::asynchronous::Client,
+        routes, tokio,
+    };
+
+    use super::*;
+    use crate::{
+        api::cqrs::projections,
+        api::{auth::admin::ADMIN_TOKEN_HEADER, config::Config},
+        testing,
+    };
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL, see SNOWY_TEST_DATABASE_URL"]
+    async fn test_projection_administration() {
+        let pool = testing::database().await;
+        let config = Config {
+            admin_token: Some("s3cret".to_string()),
+            ..Config::default()
+        };
+        let rocket = rocket::build()
+            .mount("/", routes![list_projections, reset_projection])
+            .manage(ProjectionAdmin::new(
+                pool,
+                projections(None),
+                config.projections.clone(),
+            ))
+            .manage(config);
+        let client = Client::tracked(rocket).await.unwrap();
+        let token = || Header::new(ADMIN_TOKEN_HEADER, "s3cret");
+
+        let response = client.get("/api/admin/projections").dispatch().await;
+        assert_eq!(response.status(), Status::Unauthorized);
+
+        let response = client
+            .get("/api/admin/projections")
+            .header(token())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let statuses: Value = response.into_json().await.unwrap();
+        assert_eq!(statuses.as_array().unwrap().len(), 4);
+
+        let response = client
+            .post("/api/admin/projections/team/reset")
+            .header(token())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Conflict);
+
+        let response = client
+            .post("/api/admin/projections/member_index/reset")
+            .header(token())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+
+        let response = client
+            .post("/api/admin/projections/unknown/reset")
+            .header(token())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::NotFound);
+    }
+}
@@ -0,0 +1,98 @@
+use rocket::{
+    http::Status,
+    request::{FromRequest, Outcome},
+    Request,
+};
+use sha2::{Digest, Sha256};
+
+use crate::api::{config::Config, error::Error};
+
+pub(crate) const ADMIN_TOKEN_HEADER: &str = "X-Admin-Token";
+
+/// An operator presenting the configured `admin_token`.
+pub(crate) struct Admin;
+
+/// Compares digests rather than the tokens, so that the time taken reveals
+/// nothing about the expected token.
+fn token_matches(expected: &str, presented: &str) -> bool {
+    Sha256::digest(expected.as_bytes()) == Sha256::digest(presented.as_bytes())
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Admin {
+    type Error = Error;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(expected) = request
+            .rocket()
+            .state::<Config>()
+            .and_then(|config| config.admin_token.as_deref())
+        else {
+            return Outcome::Error((
+                Status::Forbidden,
+                Error::Forbidden("Administration is disabled".to_string()),
+            ));
+        };
+
+        match request.headers().get_one(ADMIN_TOKEN_HEADER) {
+            Some(presented) if token_matches(expected, presented) => Outcome::Success(Admin),
+            Some(_) => Outcome::Error((
+                Status::Unauthorized,
+                Error::Unauthorized("Invalid admin token".to_string()),
+            )),
+            None => Outcome::Error((
+                Status::Unauthorized,
+                Error::Unauthorized("Missing admin token".to_string()),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::{get, http::Header, local::blocking::Client, routes};
+
+    use super::*;
+
+    #[get("/admin")]
+    fn admin_only(_admin: Admin) {}
+
+    fn client(admin_token: Option<&str>) -> Client {
+        let config = Config {
+            admin_token: admin_token.map(str::to_string),
+            ..Config::default()
+        };
+        Client::tracked(
+            rocket::build()
+                .mount("/", routes![admin_only])
+                .manage(config),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_token_matches() {
+        assert!(token_matches("s3cret", "s3cret"));
+        assert!(!token_matches("s3cret", "s3cre"));
+        assert!(!token_matches("s3cret", ""));
+    }
+
+    fn status(client: &Client, token: Option<&str>) -> Status {
+        let mut request = client.get("/admin");
+        if let Some(token) = token {
+            request = request.header(Header::new(ADMIN_TOKEN_HEADER, token.to_string()));
+        }
+        request.dispatch().status()
+    }
+
+    #[test]
+    fn test_admin_guard() {
+        let enabled = client(Some("s3cret"));
+        assert_eq!(status(&enabled, Some("s3cret")), Status::Ok);
+        assert_eq!(status(&enabled, Some("s3cre")), Status::Unauthorized);
+        assert_eq!(status(&enabled, None), Status::Unauthorized);
+
+        let disabled = client(None);
+        assert_eq!(status(&disabled, Some("s3cret")), Status::Forbidden);
+    }
+}
@@ -0,0 +1,210 @@
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+use crate::{api::error::Error, domain::commands::TeamCommand};
+
+pub(crate) const API_KEY_HEADER: &str = "X-Api-Key";
+const API_KEY_PREFIX: &str = "snowy";
+
+/// Scope granting read access to the team view.
+pub(crate) const READ_SCOPE: &str = "read";
+
+/// Scope granting management of the team's webhook subscriptions.
+pub(crate) const WEBHOOKS_SCOPE: &str = "webhooks";
+
+/// Every scope an API key can be granted: `read`, `webhooks` and one per
+/// command type.
+pub(crate) fn all_scopes() -> Vec<String> {
+    [READ_SCOPE, WEBHOOKS_SCOPE]
+        .into_iter()
+        .chain(TeamCommand::COMMAND_TYPES.iter().copied())
+        .map(String::from)
+        .collect()
+}
+
+/// An API key as stored in the `api_keys` table, without its secret.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub(crate) struct ApiKey {
+    pub(crate) id: Uuid,
+    pub(crate) team_id: String,
+    pub(crate) name: String,
+    pub(crate) scopes: Vec<String>,
+    pub(crate) created_at: DateTime<Utc>,
+    pub(crate) revoked_at: Option<DateTime<Utc>>,
+}
+
+impl ApiKey {
+    /// Checks that the key belongs to `team_id` and has been granted `scope`.
+    pub(crate) fn authorize(&self, team_id: &str, scope: &str) -> Result<(), Error> {
+        if self.team_id != team_id {
+            return Err(Error::Forbidden(format!(
+                "API key '{}' is not valid for team '{}'",
+                self.id, team_id
+            )));
+        }
+        if !self.scopes.iter().any(|s| s == scope) {
+            return Err(Error::Forbidden(format!(
+                "API key '{}' lacks the '{}' scope",
+                self.id, scope
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// A freshly issued key; `secret` is only ever shown at this point.
+pub(crate) struct IssuedApiKey {
+    pub(crate) key: ApiKey,
+    pub(crate) secret: String,
+}
+
+fn generate_secret(id: &Uuid) -> String {
+    format!(
+        "{}_{}_{}{}",
+        API_KEY_PREFIX,
+        id.simple(),
+        Uuid::new_v4().simple(),
+        Uuid::new_v4().simple()
+    )
+}
+
+fn hash_secret(secret: &str) -> String {
+    hex::encode(Sha256::digest(secret.as_bytes()))
+}
+
+pub(crate) struct ApiKeyRepository {
+    pool: Pool<Postgres>,
+}
+
+impl ApiKeyRepository {
+    pub(crate) fn new(pool: Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+
+    pub(crate) async fn issue(
+        &self,
+        team_id: &str,
+        name: &str,
+        scopes: Vec<String>,
+    ) -> Result<IssuedApiKey, Error> {
+        let known_scopes = all_scopes();
+        if let Some(unknown) = scopes.iter().find(|s| !known_scopes.contains(s)) {
+            return Err(Error::BadRequest(format!("Unknown scope '{}'", unknown)));
+        }
+
+        let id = Uuid::new_v4();
+        let secret = generate_secret(&id);
+
+        let key = sqlx::query_as::<_, ApiKey>(
+            "INSERT INTO api_keys (id, team_id, name, key_hash, scopes)
+             VALUES ($1, $2, $3, $4, $5)
+             RETURNING id, team_id, name, scopes, created_at, revoked_at",
+        )
+        .bind(id)
+        .bind(team_id)
+        .bind(name)
+        .bind(hash_secret(&secret))
+        .bind(scopes)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(IssuedApiKey { key, secret })
+    }
+
+    /// Revokes a key, returning `false` if it does not exist or was already revoked.
+    pub(crate) async fn revoke(&self, id: Uuid) -> Result<bool, Error> {
+        let result = sqlx::query(
+            "UPDATE api_keys SET revoked_at = now() WHERE id = $1 AND revoked_at IS NULL",
+        )
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub(crate) async fn list(&self, team_id: &str) -> Result<Vec<ApiKey>, Error> {
+        Ok(sqlx::query_as::<_, ApiKey>(
+            "SELECT id, team_id, name, scopes, created_at, revoked_at
+             FROM api_keys WHERE team_id = $1 ORDER BY created_at",
+        )
+        .bind(team_id)
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    /// Looks up the active key matching a presented secret.
+    pub(crate) async fn find_active(&self, secret: &str) -> Result<Option<ApiKey>, Error> {
+        Ok(sqlx::query_as::<_, ApiKey>(
+            "SELECT id, team_id, name, scopes, created_at, revoked_at
+             FROM api_keys WHERE key_hash = $1 AND revoked_at IS NULL",
+        )
+        .bind(hash_secret(secret))
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn api_key(team_id: &str, scopes: &[&str]) -> ApiKey {
+        ApiKey {
+            id: Uuid::new_v4(),
+            team_id: team_id.to_string(),
+            name: "test".to_string(),
+            scopes: scopes.iter().map(|s| s.to_string()).collect(),
+            created_at: Utc::now(),
+            revoked_at: None,
+        }
+    }
+
+    #[test]
+    fn test_generated_secrets_are_unique_and_hashed() {
+        let id = Uuid::new_v4();
+        let secret = generate_secret(&id);
+        let other = generate_secret(&id);
+
+        assert!(secret.starts_with(&format!("snowy_{}_", id.simple())));
+        assert_ne!(secret, other);
+        assert_eq!(hash_secret(&secret), hash_secret(&secret));
+        assert_ne!(hash_secret(&secret), hash_secret(&other));
+        assert_eq!(hash_secret(&secret).len(), 64);
+    }
+
+    #[test]
+    fn test_authorize() {
+        let key = api_key("team-1", &["read", "add-member"]);
+
+        assert!(key.authorize("team-1", "read").is_ok());
+        assert!(key.authorize("team-1", "add-member").is_ok());
+        assert!(matches!(
+            key.authorize("team-1", "track-member-forecast"),
+            Err(Error::Forbidden(_))
+        ));
+        assert!(matches!(
+            key.authorize("team-2", "read"),
+            Err(Error::Forbidden(_))
+        ));
+    }
+
+    #[test]
+    fn test_all_scopes() {
+        assert_eq!(
+            all_scopes(),
+            vec![
+                "read",
+                "webhooks",
+                "add-member",
+                "track-member-forecast",
+                "remove-member",
+                "grant-role",
+                "revoke-role",
+                "set-policy",
+                "remove-policy"
+            ]
+        );
+    }
+}
@@ -0,0 +1,389 @@
+use std::{
+    str::FromStr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use jsonwebtoken::{
+    decode, decode_header,
+    jwk::{AlgorithmParameters, EllipticCurve, Jwk, JwkSet},
+    Algorithm, DecodingKey, Validation,
+};
+use reqwest::Client;
+use serde::Deserialize;
+use tokio::sync::RwLock;
+use tracing::{error, info};
+
+use crate::api::{config::JwtConfig, error::Error};
+
+/// Shortest delay between two fetches of a remote JWKS for unknown key ids.
+const MIN_REFETCH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Timeout of each request fetching a remote JWKS.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Claims required from bearer tokens.
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct Claims {
+    pub(crate) email: String,
+    /// When the token expires, in seconds since the Unix epoch.
+    pub(crate) exp: i64,
+    /// Tokens whose email the issuer did not verify are rejected.
+    #[serde(default)]
+    pub(crate) email_verified: Option<bool>,
+}
+
+/// Validates bearer tokens against the configured JWKS.
+pub(crate) struct JwtValidator {
+    config: JwtConfig,
+    jwks: RwLock<JwkSet>,
+    /// When the JWKS was last fetched, or a refetch started.
+    last_fetch: Mutex<Instant>,
+    client: Client,
+}
+
+impl JwtValidator {
+    pub(crate) async fn load(config: JwtConfig) -> Result<Self, Error> {
+        let client = client()?;
+        let jwks = fetch_jwks(&client, &config.jwks).await?;
+        info!(source = %config.jwks, keys = jwks.keys.len(), "loaded JWKS");
+        Ok(Self::with_jwks(config, jwks, client))
+    }
+
+    fn with_jwks(config: JwtConfig, jwks: JwkSet, client: Client) -> Self {
+        Self {
+            config,
+            jwks: RwLock::new(jwks),
+            last_fetch: Mutex::new(Instant::now()),
+            client,
+        }
+    }
+
+    fn is_remote(&self) -> bool {
+        self.config.jwks.starts_with("http://") || self.config.jwks.starts_with("https://")
+    }
+
+    pub(crate) async fn validate(&self, token: &str) -> Result<Claims, Error> {
+        let header = decode_header(token).map_err(unauthorized)?;
+        let kid = header
+            .kid
+            .ok_or_else(|| Error::Unauthorized("Token has no key id".to_string()))?;
+
+        let mut key = self.decoding_key(&kid).await?;
+        if key.is_none() && self.is_remote() {
+            // keys may have been rotated since we last fetched them
+            key = self.refetch(&kid).await?;
+        }
+        let (key, algorithm) =
+            key.ok_or_else(|| Error::Unauthorized(format!("Unknown key id '{}'", kid)))?;
+        // The key decides the algorithm, never the token.
+        if header.alg != algorithm {
+            return Err(Error::Unauthorized(format!(
+                "Token is signed with {:?}, key '{}' with {:?}",
+                header.alg, kid, algorithm
+            )));
+        }
+
+        let mut validation = Validation::new(algorithm);
+        validation.set_required_spec_claims(&["exp"]);
+        if let Some(issuer) = &self.config.issuer {
+            validation.set_issuer(&[issuer]);
+        }
+        match &self.config.audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+
+        let claims = decode::<Claims>(token, &key, &validation)
+            .map_err(unauthorized)?
+            .claims;
+        if claims.email_verified == Some(false) {
+            return Err(Error::Unauthorized(
+                "Token email is not verified".to_string(),
+            ));
+        }
+        Ok(claims)
+    }
+
+    async fn decoding_key(&self, kid: &str) -> Result<Option<(DecodingKey, Algorithm)>, Error> {
+        let jwks = self.jwks.read().await;
+        let Some(jwk) = jwks.find(kid) else {
+            return Ok(None);
+        };
+        let algorithm = key_algorithm(jwk).ok_or_else(|| {
+            Error::Unauthorized(format!("Key '{}' has no usable signing algorithm", kid))
+        })?;
+        let key = DecodingKey::from_jwk(jwk).map_err(unauthorized)?;
+        Ok(Some((key, algorithm)))
+    }
+
+    /// Fetches the JWKS again, at most once per [`MIN_REFETCH_INTERVAL`].
+    /// Requests for unknown key ids made meanwhile are rejected rather than
+    /// waiting for the fetch.
+    async fn refetch(&self, kid: &str) -> Result<Option<(DecodingKey, Algorithm)>, Error> {
+        {
+            let mut last_fetch = self.last_fetch.lock().expect("JWKS fetch lock poisoned");
+            if last_fetch.elapsed() < MIN_REFETCH_INTERVAL {
+                return Ok(None);
+            }
+            *last_fetch = Instant::now();
+        }
+
+        let jwks = fetch_jwks(&self.client, &self.config.jwks)
+            .await
+            .inspect_err(|e| {
+                error!(error = %e, source = %self.config.jwks, "failed to refetch the JWKS");
+            })?;
+        info!(source = %self.config.jwks, keys = jwks.keys.len(), "refetched JWKS");
+        *self.jwks.write().await = jwks;
+        self.decoding_key(kid).await
+    }
+}
+
+/// The algorithm tokens signed with `jwk` must use: its `alg`, or the one
+/// its key type implies. Shared secrets must name their algorithm.
+fn key_algorithm(jwk: &Jwk) -> Option<Algorithm> {
+    if let Some(algorithm) = jwk.common.key_algorithm {
+        return Algorithm::from_str(&algorithm.to_string()).ok();
+    }
+    match &jwk.algorithm {
+        AlgorithmParameters::RSA(_) => Some(Algorithm::RS256),
+        AlgorithmParameters::EllipticCurve(parameters) => match parameters.curve {
+            EllipticCurve::P256 => Some(Algorithm::ES256),
+            EllipticCurve::P384 => Some(Algorithm::ES384),
+            _ => None,
+        },
+        AlgorithmParameters::OctetKeyPair(_) => Some(Algorithm::EdDSA),
+        AlgorithmParameters::OctetKey(_) => None,
+    }
+}
+
+fn unauthorized(e: jsonwebtoken::errors::Error) -> Error {
+    Error::Unauthorized(format!("Invalid token: {}", e))
+}
+
+fn client() -> Result<Client, Error> {
+    Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .build()
+        .map_err(|e| Error::KeySet(format!("Failed to build the JWKS client: {}", e)))
+}
+
+async fn fetch_jwks(client: &Client, source: &str) -> Result<JwkSet, Error> {
+    let body = if source.starts_with("http://") || source.starts_with("https://") {
+        client
+            .get(source)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| Error::KeySet(format!("Failed to fetch JWKS: {}", e)))?
+            .text()
+            .await
+            .map_err(|e| Error::KeySet(format!("Failed to fetch JWKS: {}", e)))?
+    } else {
+        tokio::fs::read_to_string(source)
+            .await
+            .map_err(|e| Error::KeySet(format!("Failed to read JWKS: {}", e)))?
+    };
+
+    serde_json::from_str(&body).map_err(|e| Error::KeySet(format!("Invalid JWKS: {}", e)))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+    use serde_json::json;
+
+    use super::*;
+
+    const SECRET: &[u8] = b"secret-key-for-tests";
+    const ISSUER: &str = "https://issuer.example.com";
+    const AUDIENCE: &str = "snowy";
+
+    pub(crate) fn validator() -> JwtValidator {
+        let jwks = serde_json::from_value(json!({
+            "keys": [{
+                "kty": "oct",
+                "kid": "test-key",
+                "alg": "HS256",
+                "k": "c2VjcmV0LWtleS1mb3ItdGVzdHM"
+            }]
+        }))
+        .unwrap();
+        let config = JwtConfig {
+            jwks: "jwks.json".to_string(),
+            issuer: Some(ISSUER.to_string()),
+            audience: Some(AUDIENCE.to_string()),
+        };
+        JwtValidator::with_jwks(config, jwks, client().unwrap())
+    }
+
+    /// Mints a token signed with the test key.
+    pub(crate) fn mint(claims: serde_json::Value) -> String {
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = Some("test-key".to_string());
+        encode(&header, &claims, &EncodingKey::from_secret(SECRET)).unwrap()
+    }
+
+    /// Mints a token accepted by [`validator`] for `email`.
+    pub(crate) fn token(email: &str) -> String {
+        mint(json!({
+            "email": email,
+            "iss": ISSUER,
+            "aud": AUDIENCE,
+            "exp": expires_in(300),
+        }))
+    }
+
+    fn expires_in(seconds: i64) -> i64 {
+        chrono::Utc::now().timestamp() + seconds
+    }
+
+    #[tokio::test]
+    async fn test_valid_token() {
+        let token = mint(json!({
+            "sub": "user-1",
+            "email": "test@example.com",
+            "email_verified": true,
+            "iss": ISSUER,
+            "aud": AUDIENCE,
+            "exp": expires_in(300),
+        }));
+
+        let claims = validator().validate(&token).await.unwrap();
+
+        assert_eq!(claims.email, "test@example.com");
+    }
+
+    #[tokio::test]
+    async fn test_rejected_tokens() {
+        let validator = validator();
+
+        let expired = mint(json!({
+            "email": "test@example.com",
+            "iss": ISSUER,
+            "aud": AUDIENCE,
+            "exp": expires_in(-300),
+        }));
+        let wrong_issuer = mint(json!({
+            "email": "test@example.com",
+            "iss": "https://other.example.com",
+            "aud": AUDIENCE,
+            "exp": expires_in(300),
+        }));
+        let wrong_audience = mint(json!({
+            "email": "test@example.com",
+            "iss": ISSUER,
+            "aud": "another-client",
+            "exp": expires_in(300),
+        }));
+        let unverified_email = mint(json!({
+            "email": "test@example.com",
+            "email_verified": false,
+            "iss": ISSUER,
+            "aud": AUDIENCE,
+            "exp": expires_in(300),
+        }));
+        let missing_email = mint(json!({
+            "iss": ISSUER,
+            "aud": AUDIENCE,
+            "exp": expires_in(300),
+        }));
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = Some("test-key".to_string());
+        let wrong_signature = encode(
+            &header,
+            &json!({
+                "email": "test@example.com",
+                "iss": ISSUER,
+                "aud": AUDIENCE,
+                "exp": expires_in(300),
+            }),
+            &EncodingKey::from_secret(b"another-secret"),
+        )
+        .unwrap();
+
+        // Signed with the key's secret, but under another algorithm.
+        let mut header = Header::new(Algorithm::HS384);
+        header.kid = Some("test-key".to_string());
+        let wrong_algorithm = encode(
+            &header,
+            &json!({
+                "email": "test@example.com",
+                "iss": ISSUER,
+                "aud": AUDIENCE,
+                "exp": expires_in(300),
+            }),
+            &EncodingKey::from_secret(SECRET),
+        )
+        .unwrap();
+
+        for token in [
+            expired,
+            wrong_issuer,
+            wrong_audience,
+            unverified_email,
+            missing_email,
+            wrong_signature,
+            wrong_algorithm,
+        ] {
+            assert!(matches!(
+                validator.validate(&token).await,
+                Err(Error::Unauthorized(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_key_algorithm() {
+        let key = |jwk: serde_json::Value| key_algorithm(&serde_json::from_value(jwk).unwrap());
+
+        assert_eq!(
+            key(json!({ "kty": "RSA", "n": "AQAB", "e": "AQAB" })),
+            Some(Algorithm::RS256)
+        );
+        assert_eq!(
+            key(json!({ "kty": "RSA", "alg": "PS256", "n": "AQAB", "e": "AQAB" })),
+            Some(Algorithm::PS256)
+        );
+        assert_eq!(
+            key(json!({ "kty": "EC", "crv": "P-384", "x": "AA", "y": "AA" })),
+            Some(Algorithm::ES384)
+        );
+        assert_eq!(key(json!({ "kty": "oct", "k": "AA" })), None);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_key_ids_refetch_at_most_once_per_interval() {
+        let mut validator = validator();
+        // Nothing listens there: fetching fails.
+        validator.config.jwks = "http://127.0.0.1:9/jwks.json".to_string();
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = Some("rotated-key".to_string());
+        let token = encode(
+            &header,
+            &json!({
+                "email": "test@example.com",
+                "iss": ISSUER,
+                "aud": AUDIENCE,
+                "exp": expires_in(300),
+            }),
+            &EncodingKey::from_secret(SECRET),
+        )
+        .unwrap();
+
+        // Fetched when loaded, so not fetched again yet.
+        assert!(matches!(
+            validator.validate(&token).await,
+            Err(Error::Unauthorized(_))
+        ));
+
+        *validator.last_fetch.lock().unwrap() -= MIN_REFETCH_INTERVAL;
+        let refetched = validator.validate(&token).await;
+        assert!(matches!(refetched, Err(Error::KeySet(_))));
+        assert_eq!(
+            refetched.unwrap_err().status(),
+            rocket::http::Status::ServiceUnavailable
+        );
+    }
+}
@@ -0,0 +1,380 @@
+pub(crate) mod admin;
+pub(crate) mod api_key;
+pub(crate) mod jwt;
+
+use std::sync::Arc;
+
+use chrono::Utc;
+use rocket::{
+    request::{FromRequest, Outcome},
+    Request,
+};
+use snowy_model::Member;
+
+use crate::{
+    domain::{
+        aggregates::Team,
+        commands::TeamCommand,
+        policies::{Actor, ADMIN_ROLE},
+    },
+    queries::team_summary::Visibility,
+};
+
+use self::{
+    api_key::{ApiKey, ApiKeyRepository, API_KEY_HEADER, READ_SCOPE, WEBHOOKS_SCOPE},
+    jwt::{Claims, JwtValidator},
+};
+use super::{cqrs::CqrsPlumbing, error::Error};
+
+/// The authenticated caller of an endpoint: a service holding an API key,
+/// or a person presenting a bearer token.
+#[derive(Clone)]
+pub(crate) enum Principal {
+    ApiKey(ApiKey),
+    Bearer(Claims),
+}
+
+impl Principal {
+    /// Authorizes `command` against `team_id`, returning the actor to record
+    /// in the event metadata. Members are further checked against the team's
+    /// policies when the command is executed.
+    pub(crate) async fn authorize_command(
+        &self,
+        cqrs: &CqrsPlumbing,
+        team_id: &str,
+        command: &TeamCommand,
+    ) -> Result<Actor, Error> {
+        self.authorize_scope(team_id, command)?;
+        match self {
+            Principal::ApiKey(key) => Ok(Actor::Service(key.id.to_string())),
+            Principal::Bearer(_) => self.actor(&cqrs.load_team(team_id).await?, team_id),
+        }
+    }
+
+    /// Checks that an API key was granted the scope of `command`; members are
+    /// left to the team's policies.
+    pub(crate) fn authorize_scope(
+        &self,
+        team_id: &str,
+        command: &TeamCommand,
+    ) -> Result<(), Error> {
+        match self {
+            Principal::ApiKey(key) => key.authorize(team_id, command.command_type()),
+            Principal::Bearer(_) => Ok(()),
+        }
+    }
+
+    /// The actor the principal issues commands to `team` as.
+    pub(crate) fn actor(&self, team: &Team, team_id: &str) -> Result<Actor, Error> {
+        match self {
+            Principal::ApiKey(key) => Ok(Actor::Service(key.id.to_string())),
+            Principal::Bearer(claims) => {
+                let member = find_member(&team.members, claims, team_id)?;
+                Ok(Actor::Member(member.id.clone()))
+            }
+        }
+    }
+
+    /// Authorizes reading the view of `team_id`, whose members are `members`.
+    pub(crate) fn authorize_read(&self, team_id: &str, members: &[Member]) -> Result<(), Error> {
+        match self {
+            Principal::ApiKey(key) => key.authorize(team_id, READ_SCOPE),
+            Principal::Bearer(claims) => find_member(members, claims, team_id).map(|_| ()),
+        }
+    }
+
+    /// Authorizes managing the webhooks of `team_id`: API keys need the
+    /// `webhooks` scope and members the admin role.
+    pub(crate) async fn authorize_webhooks(
+        &self,
+        cqrs: &CqrsPlumbing,
+        team_id: &str,
+    ) -> Result<(), Error> {
+        match self {
+            Principal::ApiKey(key) => key.authorize(team_id, WEBHOOKS_SCOPE),
+            Principal::Bearer(claims) => {
+                let team = cqrs.load_team(team_id).await?;
+                let member = find_member(&team.members, claims, team_id)?;
+                if member.roles.iter().any(|role| role == ADMIN_ROLE) {
+                    Ok(())
+                } else {
+                    Err(Error::Forbidden(format!(
+                        "'{}' is not an admin of team '{}'",
+                        claims.email, team_id
+                    )))
+                }
+            }
+        }
+    }
+
+    /// The teams the principal may list: its own team for API keys with the
+    /// read scope, and every team the person belongs to for bearer tokens.
+    pub(crate) fn visibility(&self) -> Result<Visibility, Error> {
+        match self {
+            Principal::ApiKey(key) => {
+                key.authorize(&key.team_id, READ_SCOPE)?;
+                Ok(Visibility::Team(key.team_id.clone()))
+            }
+            Principal::Bearer(claims) => Ok(Visibility::Member(claims.email.clone())),
+        }
+    }
+}
+
+fn find_member<'m>(
+    members: &'m [Member],
+    claims: &Claims,
+    team_id: &str,
+) -> Result<&'m Member, Error> {
+    members
+        .iter()
+        .find(|m| m.email.eq_ignore_ascii_case(&claims.email))
+        .ok_or_else(|| {
+            Error::Forbidden(format!(
+                "'{}' is not a member of team '{}'",
+                claims.email, team_id
+            ))
+        })
+}
+
+/// A principal with the API key it presented, for connections that outlive
+/// the request authenticating them and must check its credentials again.
+pub(crate) struct Session {
+    pub(crate) principal: Principal,
+    api_key: Option<String>,
+}
+
+impl Session {
+    /// Fails once the API key was revoked or the bearer token expired.
+    pub(crate) async fn reauthenticate(&self, api_keys: &ApiKeyRepository) -> Result<(), Error> {
+        match &self.principal {
+            Principal::ApiKey(_) => {
+                let secret = self.api_key.as_deref().unwrap_or_default();
+                match api_keys.find_active(secret).await? {
+                    Some(_) => Ok(()),
+                    None => Err(Error::Unauthorized("API key was revoked".to_string())),
+                }
+            }
+            Principal::Bearer(claims) if claims.exp <= Utc::now().timestamp() => {
+                Err(Error::Unauthorized("Token has expired".to_string()))
+            }
+            Principal::Bearer(_) => Ok(()),
+        }
+    }
+}
+
+/// Credentials presented with a request, in the `X-Api-Key` and
+/// `Authorization` headers or the gRPC metadata of the same names.
+pub(crate) struct Credentials<'c> {
+    pub(crate) api_key: Option<&'c str>,
+    pub(crate) authorization: Option<&'c str>,
+}
+
+impl<'c> Credentials<'c> {
+    fn of(request: &'c Request<'_>) -> Self {
+        Credentials {
+            api_key: request.headers().get_one(API_KEY_HEADER),
+            authorization: request.headers().get_one("Authorization"),
+        }
+    }
+
+    /// Authenticates the caller, keeping its API key to authenticate it
+    /// again later.
+    pub(crate) async fn session(
+        &self,
+        api_keys: Option<&ApiKeyRepository>,
+        jwt_validator: Option<&JwtValidator>,
+    ) -> Result<Session, Error> {
+        Ok(Session {
+            principal: self.authenticate(api_keys, jwt_validator).await?,
+            api_key: self.api_key.map(str::to_string),
+        })
+    }
+
+    /// Authenticates the caller by its API key when it presents one, and by
+    /// its bearer token otherwise.
+    pub(crate) async fn authenticate(
+        &self,
+        api_keys: Option<&ApiKeyRepository>,
+        jwt_validator: Option<&JwtValidator>,
+    ) -> Result<Principal, Error> {
+        if let Some(secret) = self.api_key {
+            let api_keys = api_keys
+                .ok_or_else(|| Error::Unauthorized("API keys are not configured".to_string()))?;
+            return api_keys
+                .find_active(secret)
+                .await?
+                .map(Principal::ApiKey)
+                .ok_or_else(|| Error::Unauthorized("Invalid API key".to_string()));
+        }
+
+        let Some(token) = self
+            .authorization
+            .and_then(|value| value.strip_prefix("Bearer "))
+        else {
+            return Err(Error::Unauthorized("Missing credentials".to_string()));
+        };
+        let Some(validator) = jwt_validator else {
+            return Err(Error::Unauthorized(
+                "Bearer tokens are not accepted".to_string(),
+            ));
+        };
+        Ok(Principal::Bearer(validator.validate(token).await?))
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Session {
+    type Error = Error;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let rocket = request.rocket();
+        let jwt_validator = rocket
+            .state::<Option<Arc<JwtValidator>>>()
+            .and_then(|validator| validator.as_deref());
+        match Credentials::of(request)
+            .session(rocket.state::<ApiKeyRepository>(), jwt_validator)
+            .await
+        {
+            Ok(session) => Outcome::Success(session),
+            Err(e) => Outcome::Error((e.status(), e)),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Principal {
+    type Error = Error;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Session::from_request(request)
+            .await
+            .map(|session| session.principal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::tokio;
+    use snowy_model::MemberId;
+
+    use super::*;
+    use crate::testing;
+
+    fn member(id: &str) -> Member {
+        Member::new(MemberId::new(id.to_string()), format!("{}@example.com", id))
+    }
+    #[test]
+    fn test_find_member_by_email_claim() {
+        let members = vec![member("alice"), member("bob")];
+        let claims = Claims {
+            email: "Bob@Example.com".to_string(),
+            exp: i64::MAX,
+            email_verified: None,
+        };
+        let stranger = Claims {
+            email: "mallory@example.com".to_string(),
+            exp: i64::MAX,
+            email_verified: None,
+        };
+
+        assert_eq!(
+            find_member(&members, &claims, "team-1").unwrap().id,
+            MemberId::new("bob".to_string())
+        );
+        assert!(matches!(
+            find_member(&members, &stranger, "team-1"),
+            Err(Error::Forbidden(_))
+        ));
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL, see SNOWY_TEST_DATABASE_URL"]
+    async fn test_credentials_authenticate() {
+        let api_keys = ApiKeyRepository::new(testing::database().await);
+        let issued = api_keys
+            .issue("team-1", "test", vec![READ_SCOPE.to_string()])
+            .await
+            .unwrap();
+        let validator = jwt::tests::validator();
+        let bearer = format!("Bearer {}", jwt::tests::token("alice@example.com"));
+        let authenticate = |api_key, authorization| {
+            let credentials = Credentials {
+                api_key,
+                authorization,
+            };
+            let api_keys = &api_keys;
+            let validator = &validator;
+            async move {
+                credentials
+                    .authenticate(Some(api_keys), Some(validator))
+                    .await
+            }
+        };
+
+        // The API key is preferred when both are presented.
+        for authorization in [None, Some(bearer.as_str())] {
+            match authenticate(Some(issued.secret.as_str()), authorization).await {
+                Ok(Principal::ApiKey(key)) => assert_eq!(key.team_id, "team-1"),
+                _ => panic!("expected the API key"),
+            }
+        }
+        match authenticate(None, Some(bearer.as_str())).await {
+            Ok(Principal::Bearer(claims)) => assert_eq!(claims.email, "alice@example.com"),
+            _ => panic!("expected the bearer token"),
+        }
+        for (api_key, authorization) in [
+            (Some("snowy_unknown"), None),
+            (None, Some("Basic YWxpY2U6c2VjcmV0")),
+            (None, None),
+        ] {
+            assert!(matches!(
+                authenticate(api_key, authorization).await,
+                Err(Error::Unauthorized(_))
+            ));
+        }
+
+        let credentials = Credentials {
+            api_key: None,
+            authorization: Some(&bearer),
+        };
+        assert!(matches!(
+            credentials.authenticate(Some(&api_keys), None).await,
+            Err(Error::Unauthorized(message)) if message == "Bearer tokens are not accepted"
+        ));
+    }
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL, see SNOWY_TEST_DATABASE_URL"]
+    async fn test_sessions_reauthenticate() {
+        let api_keys = ApiKeyRepository::new(testing::database().await);
+        let issued = api_keys
+            .issue("team-1", "test", vec![READ_SCOPE.to_string()])
+            .await
+            .unwrap();
+        let credentials = Credentials {
+            api_key: Some(&issued.secret),
+            authorization: None,
+        };
+        let session = credentials.session(Some(&api_keys), None).await.unwrap();
+        session.reauthenticate(&api_keys).await.unwrap();
+        api_keys.revoke(issued.key.id).await.unwrap();
+        assert!(matches!(
+            session.reauthenticate(&api_keys).await,
+            Err(Error::Unauthorized(_))
+        ));
+
+        let bearer = |exp| Session {
+            principal: Principal::Bearer(Claims {
+                email: "alice@example.com".to_string(),
+                exp,
+                email_verified: None,
+            }),
+            api_key: None,
+        };
+        let now = Utc::now().timestamp();
+        bearer(now + 60).reauthenticate(&api_keys).await.unwrap();
+        assert!(matches!(
+            bearer(now - 1).reauthenticate(&api_keys).await,
+            Err(Error::Unauthorized(_))
+        ));
+    }
+}
@@ -0,0 +1,388 @@
+use std::{collections::HashMap, time::Instant};
+
+use rocket::{http::Status, post, serde::json::Json, State};
+use serde::Serialize;
+use tracing::{info_span, Instrument};
+use utoipa::ToSchema;
+
+use crate::domain::{aggregates::Team, commands::TeamCommand};
+
+use super::{
+    auth::Principal,
+    cqrs::{BatchFailure, CqrsPlumbing},
+    error::Error,
+    metrics::{error_outcome, Metrics},
+    request_id::RequestContext,
+};
+
+const MAX_BATCH_SIZE: usize = 500;
+
+/// Outcome of one command of a batch.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum CommandStatus {
+    /// Executed; its events were committed.
+    Committed,
+    /// Executed, but nothing was committed as another command failed.
+    RolledBack,
+    Failed,
+    /// Not executed as an earlier command failed.
+    Skipped,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct CommandResult {
+    pub(crate) index: usize,
+    pub(crate) command_type: String,
+    pub(crate) status: CommandStatus,
+    /// Events the command produced.
+    pub(crate) events: Option<usize>,
+    pub(crate) error: Option<String>,
+}
+
+/// Body of `POST /api/team/<team_id>/batch` responses.
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct BatchReport {
+    /// Whether the events of every command were committed.
+    pub(crate) committed: bool,
+    /// Index of the command that failed, if any.
+    pub(crate) failed_index: Option<usize>,
+    pub(crate) error: Option<String>,
+    pub(crate) results: Vec<CommandResult>,
+}
+
+impl BatchReport {
+    fn new(command_types: &[&str], outcome: &Result<Vec<usize>, BatchFailure>) -> Self {
+        let (failed_index, error) = match outcome {
+            Ok(_) => (None, None),
+            Err(failure) => (failure.index, Some(failure.error.message())),
+        };
+        let results = command_types
+            .iter()
+            .enumerate()
+            .map(|(index, command_type)| {
+                let (status, events) = match outcome {
+                    Ok(produced) => (CommandStatus::Committed, produced.get(index).copied()),
+                    Err(BatchFailure {
+                        index: Some(failed),
+                        ..
+                    }) if index == *failed => (CommandStatus::Failed, None),
+                    Err(BatchFailure {
+                        index: Some(failed),
+                        ..
+                    }) if index > *failed => (CommandStatus::Skipped, None),
+                    Err(_) => (CommandStatus::RolledBack, None),
+                };
+                CommandResult {
+                    index,
+                    command_type: command_type.to_string(),
+                    status,
+                    events,
+                    error: (status == CommandStatus::Failed)
+                        .then(|| error.clone())
+                        .flatten(),
+                }
+            })
+            .collect();
+
+        BatchReport {
+            committed: outcome.is_ok(),
+            failed_index,
+            error,
+            results,
+        }
+    }
+}
+
+/// Executes commands in order as one unit: the events of every command are
+/// committed, or none are.
+#[utoipa::path(
+    post,
+    path = "/api/team/{team_id}/batch",
+    tag = "teams",
+    params(
+        ("team_id" = String, Path, description = "Team identifier")
+    ),
+    request_body(content = [TeamCommand], description = "Commands to execute in order, at most 500"),
+    responses(
+        (status = 200, description = "Every command was executed and its events committed", body = BatchReport),
+        (status = 400, description = "A command was rejected and nothing was committed; an empty or oversized batch is answered with an ErrorBody", body = BatchReport),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 403, description = "Not allowed to issue a command; nothing was committed", body = BatchReport),
+        (status = 409, description = "Concurrent modification of the team; nothing was committed", body = BatchReport)
+    ),
+    security(("api_key" = []), ("bearer" = []))
+)]
+#[post("/api/team/<team_id>/batch", data = "<commands>")]
+pub async fn batch_handler(
+    cqrs: &State<CqrsPlumbing>,
+    metrics: &State<Metrics>,
+    request: &RequestContext,
+    principal: Principal,
+    team_id: &str,
+    commands: Json<Vec<TeamCommand>>,
+) -> Result<(Status, Json<BatchReport>), Error> {
+    let commands = commands.0;
+    if commands.is_empty() || commands.len() > MAX_BATCH_SIZE {
+        return Err(Error::BadRequest(format!(
+            "Expected between 1 and {} commands",
+            MAX_BATCH_SIZE
+        )));
+    }
+    let command_types: Vec<&str> = commands.iter().map(TeamCommand::command_type).collect();
+
+    let outcome = execute_batch(cqrs, metrics, request, &principal, team_id, commands).await;
+    let status = match &outcome {
+        Ok(_) => Status::Ok,
+        Err(failure) => failure.error.status(),
+    };
+    Ok((status, Json(BatchReport::new(&command_types, &outcome))))
+}
+
+/// Executes `commands` as one batch on behalf of `principal`, recording
+/// metrics. API keys are checked against the scope of each command, and
+/// members against the team's policies while it is handled.
+pub(crate) async fn execute_batch(
+    cqrs: &CqrsPlumbing,
+    metrics: &Metrics,
+    request: &RequestContext,
+    principal: &Principal,
+    team_id: &str,
+    commands: Vec<TeamCommand>,
+) -> Result<Vec<usize>, BatchFailure> {
+    let command_types: Vec<&str> = commands.iter().map(TeamCommand::command_type).collect();
+    let started = Instant::now();
+    let outcome = authorize_and_execute(cqrs, request, principal, team_id, commands).await;
+    let label = match &outcome {
+        Ok(_) => "ok",
+        Err(failure) => error_outcome(&failure.error),
+    };
+    // Every command shares the outcome of the batch.
+    for command_type in &command_types {
+        metrics.observe_command(command_type, label, started.elapsed());
+    }
+    outcome
+}
+
+async fn authorize_and_execute(
+    cqrs: &CqrsPlumbing,
+    request: &RequestContext,
+    principal: &Principal,
+    team_id: &str,
+    commands: Vec<TeamCommand>,
+) -> Result<Vec<usize>, BatchFailure> {
+    for (index, command) in commands.iter().enumerate() {
+        principal
+            .authorize_scope(team_id, command)
+            .map_err(|error| BatchFailure {
+                index: Some(index),
+                error,
+            })?;
+    }
+
+    let metadata = HashMap::from([("request_id".to_string(), request.request_id.clone())]);
+    let span = info_span!(
+        parent: &request.span,
+        "batch",
+        commands = commands.len(),
+        team_id
+    );
+    let actor = |team: &Team| principal.actor(team, team_id).map(Some);
+    cqrs.execute_batch(team_id, commands, actor, metadata)
+        .instrument(span)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use cqrs_es::AggregateError;
+    use rocket::{
+        http::{ContentType, Header},
+        local::asynchronous::Client,
+        routes, tokio,
+    };
+    use snowy_model::{MemberId, WeatherForecast};
+
+    use crate::{
+        api::{
+            auth::{
+                api_key::{ApiKeyRepository, API_KEY_HEADER},
+                jwt::{self, JwtValidator},
+            },
+            cqrs::setup_cqrs,
+            request_id::RequestId,
+        },
+        domain::error::Error as DomainError,
+        testing,
+    };
+
+    use super::*;
+
+    const COMMAND_TYPES: &[&str] = &["add-member", "add-member", "grant-role"];
+
+    fn statuses(report: &BatchReport) -> Vec<CommandStatus> {
+        report.results.iter().map(|result| result.status).collect()
+    }
+
+    #[test]
+    fn test_report_of_committed_batch() {
+        let report = BatchReport::new(COMMAND_TYPES, &Ok(vec![1, 1, 1]));
+
+        assert!(report.committed);
+        assert_eq!(report.failed_index, None);
+        assert_eq!(statuses(&report), vec![CommandStatus::Committed; 3]);
+        assert_eq!(report.results[2].events, Some(1));
+    }
+
+    #[test]
+    fn test_report_identifies_failed_command() {
+        let failure = BatchFailure {
+            index: Some(1),
+            error: Error::Aggregate(AggregateError::UserError(DomainError::UnknownCommandType(
+                "launch-rockets".to_string(),
+            ))),
+        };
+        let report = BatchReport::new(COMMAND_TYPES, &Err(failure));
+
+        assert!(!report.committed);
+        assert_eq!(report.failed_index, Some(1));
+        assert_eq!(
+            statuses(&report),
+            vec![
+                CommandStatus::RolledBack,
+                CommandStatus::Failed,
+                CommandStatus::Skipped
+            ]
+        );
+        assert_eq!(
+            report.results[1].error.as_deref(),
+            Some("Unknown command type 'launch-rockets'")
+        );
+        assert_eq!(report.results[0].error, None);
+    }
+
+    #[test]
+    fn test_report_of_failed_commit() {
+        let failure = BatchFailure {
+            index: None,
+            error: Error::Aggregate(AggregateError::AggregateConflict),
+        };
+        let report = BatchReport::new(COMMAND_TYPES, &Err(failure));
+
+        assert_eq!(report.failed_index, None);
+        assert!(report.error.is_some());
+        assert_eq!(statuses(&report), vec![CommandStatus::RolledBack; 3]);
+    }
+
+    /// A client of the batch endpoint on a fresh database, with the header of
+    /// an API key of `team-1` granted `scopes`.
+    async fn client(scopes: &[&str]) -> (Client, CqrsPlumbing, Header<'static>) {
+        let pool = testing::database().await;
+        let metrics = Metrics::new().unwrap();
+        let cqrs = setup_cqrs(pool.clone(), &metrics);
+        let keys = ApiKeyRepository::new(pool);
+        let issued = keys
+            .issue(
+                "team-1",
+                "test",
+                scopes.iter().map(|scope| scope.to_string()).collect(),
+            )
+            .await
+            .unwrap();
+
+        let rocket = rocket::build()
+            .attach(RequestId)
+            .mount("/", routes![batch_handler])
+            .manage(cqrs.clone())
+            .manage(metrics)
+            .manage(keys)
+            .manage(Some(Arc::new(jwt::tests::validator())) as Option<Arc<JwtValidator>>);
+        let client = Client::tracked(rocket).await.unwrap();
+        (client, cqrs, Header::new(API_KEY_HEADER, issued.secret))
+    }
+
+    fn add_member(id: &str) -> TeamCommand {
+        TeamCommand::AddMember {
+            member_id: MemberId::new(id.to_string()),
+            email: format!("{}@example.com", id),
+        }
+    }
+
+    async fn post_batch(
+        client: &Client,
+        credentials: Header<'static>,
+        commands: &[TeamCommand],
+    ) -> (Status, serde_json::Value) {
+        let response = client
+            .post("/api/team/team-1/batch")
+            .header(credentials)
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(commands).unwrap())
+            .dispatch()
+            .await;
+        let status = response.status();
+        (status, response.into_json().await.unwrap())
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL, see SNOWY_TEST_DATABASE_URL"]
+    async fn test_batch_runs_inline_queries_once() {
+        let (client, cqrs, key) = client(&["add-member"]).await;
+
+        let (status, report) =
+            post_batch(&client, key, &[add_member("alice"), add_member("bob")]).await;
+        assert_eq!(status, Status::Ok);
+        assert_eq!(report["committed"], true);
+
+        assert_eq!(cqrs.load_team("team-1").await.unwrap().members.len(), 2);
+        let metrics = client.rocket().state::<Metrics>().unwrap();
+        assert_eq!(
+            metrics
+                .events_total
+                .with_label_values(&["member-added"])
+                .get(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL, see SNOWY_TEST_DATABASE_URL"]
+    async fn test_batch_rejected_by_scope_commits_nothing() {
+        let (client, cqrs, key) = client(&["add-member"]).await;
+        let remove_alice = TeamCommand::RemoveMember {
+            member_id: MemberId::new("alice".to_string()),
+        };
+
+        let (status, report) = post_batch(&client, key, &[add_member("alice"), remove_alice]).await;
+        assert_eq!(status, Status::Forbidden);
+        assert_eq!(report["failed_index"], 1);
+        assert_eq!(report["results"][0]["status"], "rolled_back");
+        assert!(cqrs.load_team("team-1").await.unwrap().members.is_empty());
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL, see SNOWY_TEST_DATABASE_URL"]
+    async fn test_batch_rejected_by_policy_commits_nothing() {
+        let (client, cqrs, key) = client(&["add-member"]).await;
+        post_batch(&client, key, &[add_member("alice")]).await;
+        let bearer = Header::new(
+            "Authorization",
+            format!("Bearer {}", jwt::tests::token("alice@example.com")),
+        );
+        let track_own_forecast = TeamCommand::TrackMemberForecast {
+            member_id: MemberId::new("alice".to_string()),
+            forecast: WeatherForecast::default(),
+        };
+
+        // Members may track their own forecast, but only admins add members.
+        let (status, report) =
+            post_batch(&client, bearer, &[track_own_forecast, add_member("bob")]).await;
+        assert_eq!(status, Status::Forbidden);
+        assert_eq!(report["failed_index"], 1);
+        let team = cqrs.load_team("team-1").await.unwrap();
+        assert_eq!(team.members.len(), 1);
+        assert!(team.forecasts.is_empty());
+    }
+}
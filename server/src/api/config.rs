@@ -1,4 +1,4 @@
-use std::fmt::Display;
+use std::{fmt::Display, net::SocketAddr, time::Duration};
 
 use rocket::figment::{
     providers::{Env, Serialized},
@@ -10,12 +10,264 @@ use serde::{Deserialize, Serialize};
 #[serde(crate = "rocket::serde")]
 pub(crate) struct Config {
     pub(crate) database_url: String,
+    /// Output format of the tracing subscriber.
+    pub(crate) log_format: LogFormat,
+    /// `EnvFilter` directives, overridden by `RUST_LOG` when set.
+    pub(crate) log_filter: String,
+    /// OTLP/HTTP traces endpoint, e.g. `http://localhost:4318/v1/traces`.
+    /// Only used when built with the `otel` feature.
+    pub(crate) otlp_endpoint: Option<String>,
+    pub(crate) otel_service_name: String,
+    /// Address the gRPC API listens on, e.g. `0.0.0.0:50051`, next to the
+    /// HTTP API. Only used when built with the `grpc` feature.
+    pub(crate) grpc_address: Option<SocketAddr>,
+    pub(crate) cors: CorsConfig,
+    /// Bearer token validation; bearer tokens are rejected when unset.
+    pub(crate) jwt: Option<JwtConfig>,
+    /// Token expected in the `X-Admin-Token` header by the `/api/admin`
+    /// endpoints, which are disabled when unset.
+    #[serde(skip_serializing)]
+    pub(crate) admin_token: Option<String>,
+    pub(crate) webhooks: WebhookConfig,
+    pub(crate) projections: ProjectionConfig,
+    /// Sink team events are published to by the projection runner, from the
+    /// first event; nothing is published when unset.
+    pub(crate) publishing: Option<PublishingConfig>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(crate = "rocket::serde", tag = "sink", rename_all = "snake_case")]
+pub(crate) enum PublishingConfig {
+    /// Appends one JSON object per event to a file.
+    JsonLines { path: String },
+    /// Publishes to a topic exchange, e.g. `amqp://localhost:5672/%2f`.
+    /// Requires the `amqp` feature.
+    Amqp {
+        uri: String,
+        #[serde(default = "default_exchange")]
+        exchange: String,
+    },
+}
+
+fn default_exchange() -> String {
+    "snowy.events".to_string()
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct JwtConfig {
+    /// Path to a local JWKS file or an `http(s)://` URL serving one.
+    pub(crate) jwks: String,
+    pub(crate) issuer: Option<String>,
+    /// Required with `issuer`, as issuers sign tokens for other clients too.
+    pub(crate) audience: Option<String>,
+}
+
+impl JwtConfig {
+    /// Rejects accepting any token of an issuer, whichever client it was for.
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if self.issuer.is_some() && self.audience.is_none() {
+            return Err("jwt.audience must be set when jwt.issuer is set".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(crate = "rocket::serde", default)]
+pub(crate) struct ProjectionConfig {
+    /// Whether this instance runs the projection runner; views are only
+    /// updated by instances that do.
+    pub(crate) enabled: bool,
+    /// Events read per checkpoint transaction.
+    pub(crate) batch_size: i64,
+    /// Delay between catch-ups when no events are notified, in milliseconds.
+    pub(crate) poll_interval_ms: u64,
+    /// Attempts after which a dead letter is only replayed on request.
+    pub(crate) max_attempts: u32,
+    /// Delay before the first retry of a dead letter, in seconds; doubled on
+    /// every further one.
+    pub(crate) initial_backoff_secs: u64,
+    pub(crate) max_backoff_secs: u64,
+}
+
+impl Default for ProjectionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            batch_size: 500,
+            poll_interval_ms: 5000,
+            max_attempts: 5,
+            initial_backoff_secs: 10,
+            max_backoff_secs: 3600,
+        }
+    }
+}
+
+impl ProjectionConfig {
+    /// Delay before retrying a dead letter after its `attempt`th attempt.
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        backoff(self.initial_backoff_secs, self.max_backoff_secs, attempt)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(crate = "rocket::serde", default)]
+pub(crate) struct WebhookConfig {
+    /// Whether this instance runs the webhook dispatcher.
+    pub(crate) enabled: bool,
+    /// Delay between polls of the outbox and of due deliveries, in milliseconds.
+    pub(crate) poll_interval_ms: u64,
+    /// Deliveries claimed per poll.
+    pub(crate) batch_size: i64,
+    /// Attempts after which a delivery is marked dead.
+    pub(crate) max_attempts: u32,
+    /// Delay before the first retry, in seconds; doubled on every further one.
+    pub(crate) initial_backoff_secs: u64,
+    pub(crate) max_backoff_secs: u64,
+    /// Timeout of each delivery request, in seconds.
+    pub(crate) timeout_secs: u64,
+    /// Whether webhooks may target loopback, private and link-local
+    /// addresses, as when developing locally.
+    pub(crate) allow_private_addresses: bool,
+    /// Days after which delivered and dead deliveries, and the outbox
+    /// entries they were made for, are deleted.
+    pub(crate) retention_days: u64,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            poll_interval_ms: 1000,
+            batch_size: 50,
+            max_attempts: 8,
+            initial_backoff_secs: 10,
+            max_backoff_secs: 3600,
+            timeout_secs: 10,
+            allow_private_addresses: false,
+            retention_days: 30,
+        }
+    }
+}
+
+impl WebhookConfig {
+    /// Delay before retrying a delivery after its `attempt`th attempt.
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        backoff(self.initial_backoff_secs, self.max_backoff_secs, attempt)
+    }
+}
+
+/// Delay before the retry following the `attempt`th attempt: the initial
+/// backoff, doubled on every further attempt, up to the maximum.
+fn backoff(initial_secs: u64, max_secs: u64, attempt: u32) -> Duration {
+    let factor = 2u64.saturating_pow(attempt.saturating_sub(1));
+    Duration::from_secs(initial_secs.saturating_mul(factor).min(max_secs))
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(crate = "rocket::serde", default)]
+pub(crate) struct CorsConfig {
+    /// Exact origins, `*`, or patterns with `*` wildcards such as
+    /// `http://localhost:*` or `https://*.example.com`. With
+    /// `allow_credentials`, hosts may only start with a wildcard subdomain of
+    /// a domain, as in the latter.
+    pub(crate) allowed_origins: Vec<String>,
+    pub(crate) allowed_methods: Vec<String>,
+    /// Allowed request headers; `*` echoes whatever the preflight asks for.
+    pub(crate) allowed_headers: Vec<String>,
+    pub(crate) exposed_headers: Vec<String>,
+    pub(crate) allow_credentials: bool,
+    /// How long browsers may cache preflight responses, in seconds.
+    pub(crate) max_age: Option<u64>,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            allowed_origins: vec![
+                "http://localhost:1420".to_string(), // Tauri dev server
+                "http://localhost:8080".to_string(), // dx serve
+            ],
+            allowed_methods: ["GET", "POST", "PUT", "PATCH", "DELETE", "OPTIONS"]
+                .map(String::from)
+                .to_vec(),
+            allowed_headers: [
+                "Content-Type",
+                "Authorization",
+                "X-Api-Key",
+                "X-Request-Id",
+                "Last-Event-ID",
+                "traceparent",
+                "tracestate",
+            ]
+            .map(String::from)
+            .to_vec(),
+            exposed_headers: vec!["X-Request-Id".to_string()],
+            allow_credentials: true,
+            max_age: Some(3600),
+        }
+    }
+}
+
+impl CorsConfig {
+    /// Rejects policies letting any site make credentialed requests.
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if !self.allow_credentials {
+            return Ok(());
+        }
+        match self
+            .allowed_origins
+            .iter()
+            .find(|origin| matches_any_host(origin))
+        {
+            Some(origin) => Err(format!(
+                "cors.allowed_origins must not contain \"{}\" when cors.allow_credentials is set",
+                origin
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Whether the host of an origin pattern starts with a wildcard other than a
+/// subdomain of a domain, so that sites anyone can register match it.
+fn matches_any_host(pattern: &str) -> bool {
+    let host = pattern
+        .split_once("://")
+        .map_or(pattern, |(_, authority)| authority);
+    let host = host.split(':').next().unwrap_or(host);
+    match host.strip_prefix('*') {
+        None => false,
+        Some(rest) => match rest.strip_prefix('.') {
+            Some(domain) => !domain.contains('.'),
+            None => true,
+        },
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub(crate) enum LogFormat {
+    Pretty,
+    Json,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             database_url: "postgres://localhost/snowy".to_string(),
+            log_format: LogFormat::Pretty,
+            log_filter: "info".to_string(),
+            otlp_endpoint: None,
+            otel_service_name: "snowy-server".to_string(),
+            grpc_address: None,
+            cors: CorsConfig::default(),
+            jwt: None,
+            admin_token: None,
+            webhooks: WebhookConfig::default(),
+            projections: ProjectionConfig::default(),
+            publishing: None,
         }
     }
 }
@@ -33,9 +285,103 @@ impl Display for Config {
 pub(crate) fn get_figment() -> Figment {
     rocket::Config::figment()
         .merge(Serialized::defaults(Config::default()))
-        .merge(Env::prefixed("SNOWY_").global())
+        .merge(Env::prefixed("SNOWY_").split("__").global())
 }
 
 pub(crate) fn get_config() -> Config {
-    get_figment().extract().expect("Configuration error")
+    let config: Config = get_figment().extract().expect("Configuration error");
+    let jwt = config.jwt.as_ref().map_or(Ok(()), JwtConfig::validate);
+    if let Err(e) = config.cors.validate().and(jwt) {
+        panic!("Configuration error: {}", e);
+    }
+    config
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_up_to_the_maximum() {
+        let delays: Vec<u64> = (1..=7).map(|n| backoff(10, 300, n).as_secs()).collect();
+
+        assert_eq!(delays, vec![10, 20, 40, 80, 160, 300, 300]);
+        assert_eq!(backoff(10, 300, u32::MAX).as_secs(), 300);
+        assert_eq!(backoff(10, 300, 0).as_secs(), 10);
+    }
+
+    #[test]
+    fn test_backoff_follows_each_config() {
+        let projections = ProjectionConfig {
+            initial_backoff_secs: 5,
+            max_backoff_secs: 60,
+            ..ProjectionConfig::default()
+        };
+        let webhooks = WebhookConfig {
+            initial_backoff_secs: 1,
+            max_backoff_secs: 3,
+            ..WebhookConfig::default()
+        };
+
+        assert_eq!(projections.backoff(5).as_secs(), 60);
+        assert_eq!(webhooks.backoff(2).as_secs(), 2);
+        assert_eq!(webhooks.backoff(3).as_secs(), 3);
+    }
+
+    #[test]
+    fn test_cors_rejects_any_origin_with_credentials() {
+        let mut cors = CorsConfig {
+            allowed_origins: vec!["*".to_string()],
+            ..Default::default()
+        };
+        assert!(cors.validate().is_err());
+
+        cors.allow_credentials = false;
+        assert!(cors.validate().is_ok());
+        assert!(CorsConfig::default().validate().is_ok());
+    }
+
+    #[test]
+    fn test_cors_rejects_wildcard_hosts_with_credentials() {
+        for origin in [
+            "https://*",
+            "*://*",
+            "https://*example.com",
+            "https://*.com",
+            "*:*",
+        ] {
+            let cors = CorsConfig {
+                allowed_origins: vec![origin.to_string()],
+                ..Default::default()
+            };
+            assert!(cors.validate().is_err(), "{} was allowed", origin);
+        }
+
+        for origin in [
+            "https://*.example.com",
+            "http://localhost:*",
+            "https://app.example.com",
+        ] {
+            let cors = CorsConfig {
+                allowed_origins: vec![origin.to_string()],
+                ..Default::default()
+            };
+            assert!(cors.validate().is_ok(), "{} was rejected", origin);
+        }
+    }
+
+    #[test]
+    fn test_jwt_requires_an_audience_with_an_issuer() {
+        let mut jwt = JwtConfig {
+            jwks: "jwks.json".to_string(),
+            issuer: Some("https://issuer.example.com".to_string()),
+            audience: None,
+        };
+        assert!(jwt.validate().is_err());
+
+        jwt.audience = Some("snowy".to_string());
+        assert!(jwt.validate().is_ok());
+        jwt.issuer = None;
+        assert!(jwt.validate().is_ok());
+    }
 }
@@ -1,11 +1,60 @@
 use rocket::fairing::{Fairing, Info, Kind};
-use rocket::http::Header;
+use rocket::http::{Header, Method};
 use rocket::{Request, Response};
 
-pub(crate) struct CORS;
+use super::config::CorsConfig;
+
+/// Fairing applying the configured CORS policy to every response.
+///
+/// Preflight requests are answered by the `preflight` catch-all `OPTIONS` route;
+/// this fairing only decorates responses with the matching headers.
+pub(crate) struct Cors {
+    config: CorsConfig,
+}
+
+impl Cors {
+    pub(crate) fn new(config: CorsConfig) -> Self {
+        Self { config }
+    }
+
+    fn is_allowed_origin(&self, origin: &str) -> bool {
+        self.config
+            .allowed_origins
+            .iter()
+            .any(|pattern| origin_matches(pattern, origin))
+    }
+}
+
+/// Matches an origin against an exact origin or a pattern with `*` wildcards.
+fn origin_matches(pattern: &str, origin: &str) -> bool {
+    let pattern = pattern.to_ascii_lowercase();
+    let origin = origin.to_ascii_lowercase();
+
+    if !pattern.contains('*') {
+        return pattern == origin;
+    }
+
+    let parts: Vec<&str> = pattern.split('*').collect();
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if origin.len() < first.len() + last.len()
+        || !origin.starts_with(first)
+        || !origin.ends_with(last)
+    {
+        return false;
+    }
+
+    let mut rest = &origin[first.len()..origin.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
 
 #[rocket::async_trait]
-impl Fairing for CORS {
+impl Fairing for Cors {
     fn info(&self) -> Info {
         Info {
             name: "Add CORS headers to responses",
@@ -13,16 +62,144 @@ impl Fairing for CORS {
         }
     }
 
-    async fn on_response<'r>(&self, _request: &'r Request<'_>, response: &mut Response<'r>) {
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        response.adjoin_header(Header::new("Vary", "Origin"));
+
+        let Some(origin) = request.headers().get_one("Origin") else {
+            return;
+        };
+        if !self.is_allowed_origin(origin) {
+            return;
+        }
+
         response.set_header(Header::new(
             "Access-Control-Allow-Origin",
-            "http://localhost:1420", // App host
+            origin.to_string(),
         ));
+        if self.config.allow_credentials {
+            response.set_header(Header::new("Access-Control-Allow-Credentials", "true"));
+        }
+        if !self.config.exposed_headers.is_empty() {
+            response.set_header(Header::new(
+                "Access-Control-Expose-Headers",
+                self.config.exposed_headers.join(", "),
+            ));
+        }
+
+        let is_preflight = request.method() == Method::Options
+            && request.headers().contains("Access-Control-Request-Method");
+        if !is_preflight {
+            return;
+        }
+
         response.set_header(Header::new(
             "Access-Control-Allow-Methods",
-            "POST, GET, PATCH, OPTIONS",
+            self.config.allowed_methods.join(", "),
+        ));
+        let allowed_headers = if self.config.allowed_headers.iter().any(|h| h == "*") {
+            request
+                .headers()
+                .get_one("Access-Control-Request-Headers")
+                .unwrap_or_default()
+                .to_string()
+        } else {
+            self.config.allowed_headers.join(", ")
+        };
+        response.set_header(Header::new("Access-Control-Allow-Headers", allowed_headers));
+        if let Some(max_age) = self.config.max_age {
+            response.set_header(Header::new("Access-Control-Max-Age", max_age.to_string()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::http::Status;
+    use rocket::local::blocking::Client;
+    use rocket::routes;
+
+    use super::*;
+
+    #[test]
+    fn test_origin_matches() {
+        assert!(origin_matches("*", "https://app.example.com"));
+        assert!(origin_matches(
+            "http://localhost:1420",
+            "http://localhost:1420"
+        ));
+        assert!(!origin_matches(
+            "http://localhost:1420",
+            "http://localhost:8080"
+        ));
+        assert!(origin_matches(
+            "http://localhost:*",
+            "http://localhost:8080"
         ));
-        response.set_header(Header::new("Access-Control-Allow-Headers", "*"));
-        response.set_header(Header::new("Access-Control-Allow-Credentials", "true"));
+        assert!(origin_matches(
+            "https://*.example.com",
+            "https://app.example.com"
+        ));
+        assert!(!origin_matches(
+            "https://*.example.com",
+            "https://example.com.evil.org"
+        ));
+        assert!(!origin_matches(
+            "https://*.example.com",
+            "https://example.com"
+        ));
+    }
+
+    fn client() -> Client {
+        let config = CorsConfig {
+            allowed_origins: vec!["http://localhost:*".to_string()],
+            ..Default::default()
+        };
+        let rocket = rocket::build().attach(Cors::new(config)).mount(
+            "/",
+            routes![
+                crate::api::handlers::health,
+                crate::api::handlers::preflight
+            ],
+        );
+        Client::tracked(rocket).unwrap()
+    }
+
+    #[test]
+    fn test_preflight_allowed_origin() {
+        let client = client();
+        let response = client
+            .options("/api/team/team-1")
+            .header(Header::new("Origin", "http://localhost:8080"))
+            .header(Header::new("Access-Control-Request-Method", "POST"))
+            .dispatch();
+
+        assert_eq!(response.status(), Status::NoContent);
+        let headers = response.headers();
+        assert_eq!(
+            headers.get_one("Access-Control-Allow-Origin"),
+            Some("http://localhost:8080")
+        );
+        assert!(headers
+            .get_one("Access-Control-Allow-Methods")
+            .unwrap()
+            .contains("POST"));
+        assert_eq!(headers.get_one("Access-Control-Max-Age"), Some("3600"));
+        assert_eq!(headers.get_one("Vary"), Some("Origin"));
+    }
+
+    #[test]
+    fn test_disallowed_origin() {
+        let client = client();
+        let response = client
+            .get("/health")
+            .header(Header::new("Origin", "https://evil.example.com"))
+            .dispatch();
+
+        assert_eq!(response.status(), Status::Ok);
+        assert!(response
+            .headers()
+            .get_one("Access-Control-Allow-Origin")
+            .is_none());
+        assert_eq!(response.headers().get_one("Vary"), Some("Origin"));
     }
 }
@@ -1,33 +1,221 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
-use postgres_es::{postgres_cqrs, PostgresCqrs};
+use async_trait::async_trait;
+use cqrs_es::{
+    persist::PersistedEventStore, Aggregate, AggregateError, CqrsFramework, EventEnvelope,
+    EventStore, Query,
+};
+use postgres_es::PostgresEventRepository;
 use sqlx::{Pool, Postgres};
 
 use crate::{
-    domain::{aggregates::Team, services::TeamServices},
-    queries::team::{TeamQuery, TeamQueryDyn, TeamViewRepository},
+    domain::{
+        aggregates::Team,
+        commands::{IssuedCommand, TeamCommand},
+        policies::{Actor, ACTOR_METADATA_KEY},
+        services::TeamServices,
+    },
+    projections::Projection,
+    publishing::PublishingProjection,
+    queries::{
+        forecast_history::{ForecastHistoryProjection, ForecastHistoryRepository},
+        member_index::{MemberIndexProjection, MemberIndexRepository},
+        metrics::EventMetricsQuery,
+        notifier::TeamNotifier,
+        team::{TeamQueryDyn, TeamViewProjection, TeamViewRepository},
+        team_summary::{TeamSummaryProjection, TeamSummaryRepository},
+    },
+};
+
+use super::{
+    config::PublishingConfig,
+    error::Error,
+    metrics::Metrics,
+    telemetry::{TracedEventStore, TracedQuery},
 };
 
+pub(crate) type TeamEventStore =
+    TracedEventStore<PersistedEventStore<PostgresEventRepository, Team>>;
+pub(crate) type TeamCqrs = CqrsFramework<Team, TeamEventStore>;
+
+/// Team updates buffered per stream subscriber before it is considered lagging.
+const TEAM_UPDATES_CAPACITY: usize = 256;
+
 #[derive(Clone)]
 pub(crate) struct CqrsPlumbing {
-    pub(crate) cqrs: Arc<PostgresCqrs<Team>>,
+    pub(crate) cqrs: Arc<TeamCqrs>,
+    pub(crate) store: Arc<TeamEventStore>,
     pub(crate) team_view_repository: Arc<TeamViewRepository>,
+    pub(crate) team_summary_repository: Arc<TeamSummaryRepository>,
+    pub(crate) member_index_repository: Arc<MemberIndexRepository>,
+    pub(crate) forecast_history_repository: Arc<ForecastHistoryRepository>,
+    pub(crate) notifier: TeamNotifier,
+    pub(crate) queries: InlineQueries,
 }
 
-pub(crate) async fn setup_cqrs(pool: Pool<Postgres>) -> CqrsPlumbing {
-    let team_view_repository = Arc::new(TeamViewRepository::new("team_query", pool.clone()));
+/// Queries run inline after every commit, by the framework or by a batch.
+#[derive(Clone)]
+pub(crate) struct InlineQueries(Arc<Vec<Box<TeamQueryDyn>>>);
+
+#[async_trait]
+impl Query<Team> for InlineQueries {
+    async fn dispatch(&self, aggregate_id: &str, events: &[EventEnvelope<Team>]) {
+        for query in self.0.iter() {
+            query.dispatch(aggregate_id, events).await;
+        }
+    }
+}
+
+/// Why a batch was not committed: the command at `index` failed, or the
+/// commit did when `index` is `None`.
+#[derive(Debug)]
+pub(crate) struct BatchFailure {
+    pub(crate) index: Option<usize>,
+    pub(crate) error: Error,
+}
+
+impl CqrsPlumbing {
+    /// Loads the current state of a team aggregate from the event store.
+    pub(crate) async fn load_team(&self, team_id: &str) -> Result<Team, Error> {
+        Ok(self.store.load_aggregate(team_id).await?.aggregate)
+    }
 
-    let mut team_query = TeamQuery::new(team_view_repository.clone());
-    team_query.use_error_handler(Box::new(|e| {
-        eprintln!("Team Query Error: {:?}", e);
-    }));
+    /// Executes `command` as the actor recorded in `metadata`, whose policies
+    /// the team checks while handling it.
+    pub(crate) async fn execute(
+        &self,
+        team_id: &str,
+        command: TeamCommand,
+        metadata: HashMap<String, String>,
+    ) -> Result<(), Error> {
+        let command = IssuedCommand {
+            actor: Actor::from_metadata(&metadata),
+            command,
+        };
+        Ok(self
+            .cqrs
+            .execute_with_metadata(team_id, command, metadata)
+            .await?)
+    }
+
+    /// Executes `commands` in order against a copy of the team, each seeing
+    /// the events of the previous ones, then commits the events of all of
+    /// them at once. The actor is resolved once against the loaded team and
+    /// recorded in `metadata`; the team checks its policies per command.
+    /// Returns the number of events each command produced.
+    pub(crate) async fn execute_batch(
+        &self,
+        team_id: &str,
+        commands: Vec<TeamCommand>,
+        actor: impl FnOnce(&Team) -> Result<Option<Actor>, Error>,
+        mut metadata: HashMap<String, String>,
+    ) -> Result<Vec<usize>, BatchFailure> {
+        let commit_failure = |error: AggregateError<_>| BatchFailure {
+            index: None,
+            error: error.into(),
+        };
+        let context = self
+            .store
+            .load_aggregate(team_id)
+            .await
+            .map_err(commit_failure)?;
+        let mut team = context.aggregate.clone();
+
+        // Not being allowed to act on the team at all fails the first command.
+        let actor = actor(&team).map_err(|error| BatchFailure {
+            index: Some(0),
+            error,
+        })?;
+        if let Some(actor) = &actor {
+            metadata.insert(ACTOR_METADATA_KEY.to_string(), actor.to_metadata());
+        }
+
+        let mut events = Vec::new();
+        let mut produced = Vec::with_capacity(commands.len());
+        for (index, command) in commands.into_iter().enumerate() {
+            let failure = |error: Error| BatchFailure {
+                index: Some(index),
+                error,
+            };
+            let command = IssuedCommand {
+                actor: actor.clone(),
+                command,
+            };
+            let command_events = team
+                .handle(command, &TeamServices {})
+                .await
+                .map_err(|e| failure(AggregateError::UserError(e).into()))?;
+            for event in &command_events {
+                team.apply(event.clone());
+            }
+            produced.push(command_events.len());
+            events.extend(command_events);
+        }
+
+        if !events.is_empty() {
+            let committed = self
+                .store
+                .commit(events, context, metadata)
+                .await
+                .map_err(commit_failure)?;
+            self.queries.dispatch(team_id, &committed).await;
+        }
+        Ok(produced)
+    }
+}
+
+/// Projections run asynchronously by the [`ProjectionRunner`](crate::projections::ProjectionRunner),
+/// including publishing when a sink is configured.
+pub(crate) fn projections(
+    publishing_config: Option<&PublishingConfig>,
+) -> Vec<Box<dyn Projection>> {
+    let mut projections: Vec<Box<dyn Projection>> = vec![
+        Box::new(TeamViewProjection),
+        Box::new(TeamSummaryProjection),
+        Box::new(MemberIndexProjection),
+        Box::new(ForecastHistoryProjection),
+    ];
+    if let Some(config) = publishing_config {
+        projections.push(Box::new(PublishingProjection::new(config.clone())));
+    }
+    projections
+}
+
+fn team_event_store(pool: Pool<Postgres>) -> TeamEventStore {
+    TracedEventStore::new(PersistedEventStore::new_event_store(
+        PostgresEventRepository::new(pool),
+    ))
+}
+
+pub(crate) fn setup_cqrs(pool: Pool<Postgres>, metrics: &Metrics) -> CqrsPlumbing {
+    let team_view_repository = Arc::new(TeamViewRepository::new("team_query", pool.clone()));
+    let team_summary_repository = Arc::new(TeamSummaryRepository::new(pool.clone()));
+    let member_index_repository = Arc::new(MemberIndexRepository::new(pool.clone()));
+    let forecast_history_repository = Arc::new(ForecastHistoryRepository::new(pool.clone()));
+    let notifier = TeamNotifier::new(TEAM_UPDATES_CAPACITY);
 
-    let queries: Vec<Box<TeamQueryDyn>> = vec![Box::new(team_query)];
+    // Views are maintained by the projection runner; only per-replica
+    // side effects run inline.
+    let metrics_query = EventMetricsQuery::new(metrics.events_total.clone());
+    let queries: Vec<Box<TeamQueryDyn>> =
+        vec![Box::new(TracedQuery::new("metrics", metrics_query))];
+    let queries = InlineQueries(Arc::new(queries));
 
-    let cqrs = Arc::new(postgres_cqrs(pool, queries, TeamServices {}));
+    let store = Arc::new(team_event_store(pool.clone()));
+    let cqrs = Arc::new(CqrsFramework::new(
+        team_event_store(pool),
+        vec![Box::new(queries.clone())],
+        TeamServices {},
+    ));
 
     CqrsPlumbing {
         cqrs,
+        store,
         team_view_repository,
+        team_summary_repository,
+        member_index_repository,
+        forecast_history_repository,
+        notifier,
+        queries,
     }
 }
@@ -1,25 +1,130 @@
 use std::io::Cursor;
 
 use cqrs_es::AggregateError;
-use rocket::{http::ContentType, response::Responder, Response};
+use rocket::{
+    http::{ContentType, Status},
+    response::Responder,
+    Response,
+};
+use serde::Serialize;
 use serde_json::json;
+use utoipa::ToSchema;
+
+use crate::{
+    domain::{error::Error as DomainError, policies::Denial},
+    projections::ProjectionError,
+};
 
 #[derive(Debug, thiserror::Error)]
 pub(crate) enum Error {
     #[error("Database error: {0}")]
     Database(#[from] sqlx::Error),
     #[error("Internal error: {0}")]
-    Aggregate(#[from] AggregateError<crate::domain::error::Error>),
+    Aggregate(AggregateError<DomainError>),
     #[error("Internal error: {0}")]
     View(#[from] cqrs_es::persist::PersistenceError),
+    #[error("Metrics error: {0}")]
+    Metrics(#[from] prometheus::Error),
+    #[error("Bad request: {0}")]
+    BadRequest(String),
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+    /// The keys bearer tokens are checked against could not be loaded.
+    #[error("Key set unavailable: {0}")]
+    KeySet(String),
+    #[error("Forbidden: {0}")]
+    PolicyDenied(#[from] Denial),
+    #[error("Projection error: {0}")]
+    Projection(#[from] ProjectionError),
+}
+
+impl From<AggregateError<DomainError>> for Error {
+    fn from(error: AggregateError<DomainError>) -> Self {
+        match error {
+            AggregateError::UserError(DomainError::PolicyDenied(denial)) => {
+                Error::PolicyDenied(denial)
+            }
+            error => Error::Aggregate(error),
+        }
+    }
+}
+
+impl Error {
+    pub(crate) fn status(&self) -> Status {
+        match self {
+            Error::Aggregate(AggregateError::UserError(_)) | Error::BadRequest(_) => {
+                Status::BadRequest
+            }
+            Error::Aggregate(AggregateError::AggregateConflict)
+            | Error::Projection(ProjectionError::NotResettable(_)) => Status::Conflict,
+            Error::Unauthorized(_) => Status::Unauthorized,
+            Error::Forbidden(_) | Error::PolicyDenied(_) => Status::Forbidden,
+            Error::KeySet(_) => Status::ServiceUnavailable,
+            _ => Status::InternalServerError,
+        }
+    }
+
+    /// The message shown to clients, the reason alone for rejected commands
+    /// and forbidden requests.
+    pub(crate) fn message(&self) -> String {
+        match self {
+            Error::Aggregate(AggregateError::UserError(e)) => e.to_string(),
+            Error::Forbidden(reason) => reason.clone(),
+            Error::PolicyDenied(denial) => denial.to_string(),
+            e => e.to_string(),
+        }
+    }
 }
 
 impl<'r> Responder<'r, 'static> for Error {
     fn respond_to(self, _request: &'r rocket::Request<'_>) -> rocket::response::Result<'static> {
-        let err_body = json!({ "error": format!("{:?}", &self) }).to_string();
+        let status = self.status();
+        if status == Status::Forbidden {
+            return problem(status, &self.message());
+        }
+
+        let err_body = json!(ErrorBody {
+            error: format!("{:?}", &self)
+        })
+        .to_string();
         Response::build()
+            .status(status)
             .header(ContentType::JSON)
             .sized_body(err_body.len(), Cursor::new(err_body))
             .ok()
     }
 }
+
+/// Body of error responses.
+#[derive(Serialize, ToSchema)]
+pub(crate) struct ErrorBody {
+    error: String,
+}
+
+/// RFC 7807 problem details, returned when a request is forbidden.
+#[derive(Serialize, ToSchema)]
+pub(crate) struct Problem {
+    #[serde(rename = "type")]
+    kind: String,
+    title: String,
+    status: u16,
+    detail: String,
+}
+
+/// Renders an RFC 7807 problem details response.
+fn problem(status: Status, detail: &str) -> rocket::response::Result<'static> {
+    let body = json!(Problem {
+        kind: "about:blank".to_string(),
+        title: status.reason_lossy().to_string(),
+        status: status.code,
+        detail: detail.to_string(),
+    })
+    .to_string();
+    Response::build()
+        .status(status)
+        .header(ContentType::new("application", "problem+json"))
+        .sized_body(body.len(), Cursor::new(body))
+        .ok()
+}
@@ -0,0 +1,412 @@
+use async_graphql::{
+    dataloader::DataLoader,
+    http::{GraphiQLSource, WebSocket, WebSocketProtocols, WsMessage},
+    Data, ErrorExtensions, Schema,
+};
+use async_graphql_rocket::{GraphQLRequest, GraphQLResponse};
+use rocket::{
+    futures::{future, SinkExt, StreamExt},
+    get,
+    http::{Header, Status},
+    post,
+    request::{FromRequest, Outcome},
+    response::content::RawHtml,
+    tokio, Request, Responder, State,
+};
+use rocket_ws::{
+    frame::{CloseCode, CloseFrame},
+    stream::DuplexStream,
+    Channel, Message,
+};
+
+use super::{
+    auth::Principal, cqrs::CqrsPlumbing, error::Error, metrics::Metrics, request_id::RequestContext,
+};
+
+mod schema;
+mod types;
+
+pub(crate) use schema::{MutationRoot, QueryRoot, SubscriptionRoot};
+use types::ForecastHistoryLoader;
+
+pub(crate) type SnowySchema = Schema<QueryRoot, MutationRoot, SubscriptionRoot>;
+
+const MAX_DEPTH: usize = 10;
+/// Fields a query may select in all, counting each once however many items
+/// it is resolved for.
+const MAX_COMPLEXITY: usize = 500;
+const PROTOCOL_HEADER: &str = "Sec-WebSocket-Protocol";
+
+pub(crate) fn schema(cqrs: CqrsPlumbing, metrics: Metrics) -> SnowySchema {
+    Schema::build(QueryRoot, MutationRoot, SubscriptionRoot)
+        .data(DataLoader::new(
+            ForecastHistoryLoader(cqrs.forecast_history_repository.clone()),
+            tokio::spawn,
+        ))
+        .data(cqrs)
+        .data(metrics)
+        .limit_depth(MAX_DEPTH)
+        .limit_complexity(MAX_COMPLEXITY)
+        .finish()
+}
+
+/// A GraphQL error with the message and, as the `status` extension, the
+/// status code the REST API answers with.
+pub(crate) fn graphql_error(error: impl Into<Error>) -> async_graphql::Error {
+    let error = error.into();
+    async_graphql::Error::new(error.message())
+        .extend_with(|_, extensions| extensions.set("status", error.status().code))
+}
+
+/// Executes a GraphQL query or mutation.
+#[utoipa::path(
+    post,
+    path = "/graphql",
+    tag = "graphql",
+    request_body(content = Object, description = "GraphQL request: `query`, `operationName` and `variables`"),
+    responses(
+        (status = 200, description = "GraphQL response; errors carry their HTTP status in the `status` extension", body = Object),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody)
+    ),
+    security(("api_key" = []), ("bearer" = []))
+)]
+#[post("/graphql", data = "<query>")]
+pub async fn graphql_handler(
+    schema: &State<SnowySchema>,
+    request: &RequestContext,
+    principal: Principal,
+    query: GraphQLRequest,
+) -> GraphQLResponse {
+    query
+        .data(principal)
+        .data(request.clone())
+        .execute(schema.inner())
+        .await
+}
+
+/// The GraphQL WebSocket subprotocol requested by the client.
+pub(crate) struct GraphQLProtocol(WebSocketProtocols);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for GraphQLProtocol {
+    type Error = Error;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let protocol = request
+            .headers()
+            .get(PROTOCOL_HEADER)
+            .flat_map(|protocols| protocols.split(','))
+            .find_map(|protocol| protocol.trim().parse().ok());
+        match protocol {
+            Some(protocol) => Outcome::Success(GraphQLProtocol(protocol)),
+            None => Outcome::Error((
+                Status::BadRequest,
+                Error::BadRequest(
+                    "Expected the graphql-transport-ws or graphql-ws subprotocol".to_string(),
+                ),
+            )),
+        }
+    }
+}
+
+/// The WebSocket, answering with the subprotocol it speaks.
+#[derive(Responder)]
+pub(crate) struct GraphQLChannel {
+    channel: Channel<'static>,
+    protocol: Header<'static>,
+}
+
+/// Serves GraphQL subscriptions, queries and mutations over a WebSocket,
+/// using the `graphql-transport-ws` or the legacy `graphql-ws` subprotocol.
+#[utoipa::path(
+    get,
+    path = "/graphql/ws",
+    tag = "graphql",
+    responses(
+        (status = 101, description = "Switched to the WebSocket protocol"),
+        (status = 400, description = "No supported subprotocol requested", body = ErrorBody),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody)
+    ),
+    security(("api_key" = []), ("bearer" = []))
+)]
+#[get("/graphql/ws")]
+pub fn graphql_ws_handler(
+    ws: rocket_ws::WebSocket,
+    schema: &State<SnowySchema>,
+    request: &RequestContext,
+    principal: Principal,
+    protocol: GraphQLProtocol,
+) -> GraphQLChannel {
+    let schema = schema.inner().clone();
+    let GraphQLProtocol(protocol) = protocol;
+    let header = Header::new(PROTOCOL_HEADER, protocol.sec_websocket_protocol());
+    let mut data = Data::default();
+    data.insert(principal);
+    data.insert(request.clone());
+    GraphQLChannel {
+        channel: ws.channel(move |stream| Box::pin(serve(schema, protocol, data, stream))),
+        protocol: header,
+    }
+}
+
+async fn serve(
+    schema: SnowySchema,
+    protocol: WebSocketProtocols,
+    data: Data,
+    stream: DuplexStream,
+) -> rocket_ws::result::Result<()> {
+    let (mut sink, source) = stream.split();
+    let input = source
+        .take_while(|message| future::ready(matches!(message, Ok(m) if !m.is_close())))
+        .filter_map(|message| {
+            future::ready(match message {
+                Ok(Message::Text(text)) => Some(text.into_bytes()),
+                Ok(Message::Binary(bytes)) => Some(bytes),
+                _ => None,
+            })
+        });
+    let mut output = Box::pin(WebSocket::new(schema, input, protocol).connection_data(data));
+
+    while let Some(message) = output.next().await {
+        match message {
+            WsMessage::Text(text) => sink.send(Message::Text(text)).await?,
+            WsMessage::Close(code, reason) => {
+                let frame = CloseFrame {
+                    code: CloseCode::from(code),
+                    reason: reason.into(),
+                };
+                sink.send(Message::Close(Some(frame))).await?;
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[get("/graphiql")]
+pub fn graphiql() -> RawHtml<String> {
+    RawHtml(
+        GraphiQLSource::build()
+            .endpoint("/graphql")
+            .subscription_endpoint("/graphql/ws")
+            .finish(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use async_graphql::{value, Request};
+    use cqrs_es::AggregateError;
+    use snowy_model::{CelsiusTemperature, MemberId, WeatherForecast};
+    use sqlx::{Pool, Postgres};
+    use tracing::Span;
+
+    use crate::{
+        api::{auth::jwt::Claims, cqrs::setup_cqrs},
+        domain::{commands::TeamCommand, error::Error as DomainError},
+        queries::{
+            forecast_history::ForecastHistoryProjection, notifier::TeamUpdate,
+            team::TeamViewProjection,
+        },
+        testing,
+    };
+
+    use super::*;
+
+    /// A request made by `email`, with the context the handlers add.
+    fn request(query: &str, email: &str) -> Request {
+        Request::new(query)
+            .data(Principal::Bearer(Claims {
+                email: email.to_string(),
+                exp: i64::MAX,
+                email_verified: None,
+            }))
+            .data(RequestContext {
+                request_id: "test".to_string(),
+                span: Span::none(),
+            })
+    }
+
+    async fn team_with_forecasts() -> (SnowySchema, CqrsPlumbing, Pool<Postgres>) {
+        let pool = testing::database().await;
+        let metrics = Metrics::new().unwrap();
+        let cqrs = setup_cqrs(pool.clone(), &metrics);
+        let mut commands = Vec::new();
+        for member in ["alice", "bob"] {
+            commands.push(TeamCommand::AddMember {
+                member_id: MemberId::new(member.to_string()),
+                email: format!("{}@example.com", member),
+            });
+        }
+        for (member, minimum) in [("alice", -1.0), ("bob", 2.0), ("alice", -3.0)] {
+            commands.push(TeamCommand::TrackMemberForecast {
+                member_id: MemberId::new(member.to_string()),
+                forecast: WeatherForecast {
+                    date: "2024-11-05".parse().unwrap(),
+                    minimum_temperature: CelsiusTemperature(minimum),
+                    ..Default::default()
+                },
+            });
+        }
+        for command in commands {
+            cqrs.execute("team-1", command, HashMap::new())
+                .await
+                .unwrap();
+        }
+        testing::project(&pool, &TeamViewProjection, "team-1").await;
+        testing::project(&pool, &ForecastHistoryProjection, "team-1").await;
+        (schema(cqrs.clone(), metrics), cqrs, pool)
+    }
+
+    #[rocket::tokio::test]
+    #[ignore = "requires PostgreSQL, see SNOWY_TEST_DATABASE_URL"]
+    async fn test_query_reads_members_and_their_forecast_history() {
+        let (schema, _, _) = team_with_forecasts().await;
+        let query = r#"{
+            team(id: "team-1") {
+                id
+                members {
+                    email
+                    forecastHistory { forecast { minimumTemperature } }
+                }
+                forecastHistory(to: "2024-11-05") { memberId }
+            }
+        }"#;
+
+        let response = schema.execute(request(query, "bob@example.com")).await;
+
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        let minimums = |temperatures: &[f32]| {
+            temperatures
+                .iter()
+                .map(|t| value!({"forecast": {"minimumTemperature": *t}}))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            response.data,
+            value!({
+                "team": {
+                    "id": "team-1",
+                    "members": [
+                        {"email": "alice@example.com", "forecastHistory": minimums(&[-1.0, -3.0])},
+                        {"email": "bob@example.com", "forecastHistory": minimums(&[2.0])},
+                    ],
+                    "forecastHistory": [
+                        {"memberId": "alice"}, {"memberId": "bob"}, {"memberId": "alice"},
+                    ],
+                }
+            })
+        );
+
+        let response = schema.execute(request(query, "eve@example.com")).await;
+        assert_eq!(
+            response.errors[0].message,
+            "'eve@example.com' is not a member of team 'team-1'"
+        );
+    }
+
+    #[rocket::tokio::test]
+    #[ignore = "requires PostgreSQL, see SNOWY_TEST_DATABASE_URL"]
+    async fn test_mutation_executes_the_command() {
+        let (schema, cqrs, _) = team_with_forecasts().await;
+        let command = TeamCommand::GrantRole {
+            member_id: MemberId::new("alice".to_string()),
+            role: "admin".to_string(),
+        };
+        cqrs.execute("team-1", command, HashMap::new())
+            .await
+            .unwrap();
+        let mutation = r#"mutation {
+            addMember(teamId: "team-1", memberId: "carol", email: "carol@example.com")
+        }"#;
+
+        let response = schema.execute(request(mutation, "alice@example.com")).await;
+
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        assert_eq!(response.data, value!({"addMember": true}));
+        let team = cqrs.load_team("team-1").await.unwrap();
+        assert!(team
+            .members
+            .iter()
+            .any(|member| member.email == "carol@example.com"));
+    }
+
+    #[rocket::tokio::test]
+    #[ignore = "requires PostgreSQL, see SNOWY_TEST_DATABASE_URL"]
+    async fn test_subscription_ends_when_the_member_is_removed() {
+        let (schema, cqrs, pool) = team_with_forecasts().await;
+        let subscription = r#"subscription { team(id: "team-1") { members { email } } }"#;
+        let mut updates = schema.execute_stream(request(subscription, "bob@example.com"));
+        let current = updates.next().await.unwrap();
+        assert!(current.errors.is_empty(), "{:?}", current.errors);
+
+        let command = TeamCommand::RemoveMember {
+            member_id: MemberId::new("bob".to_string()),
+        };
+        cqrs.execute("team-1", command, HashMap::new())
+            .await
+            .unwrap();
+        testing::project(&pool, &TeamViewProjection, "team-1").await;
+        cqrs.notifier.send(TeamUpdate {
+            team_id: "team-1".to_string(),
+            sequence: 6,
+        });
+
+        let removed = updates.next().await.unwrap();
+        assert_eq!(
+            removed.errors[0].message,
+            "'bob@example.com' is not a member of team 'team-1'"
+        );
+        assert!(updates.next().await.is_none());
+    }
+
+    #[rocket::tokio::test]
+    async fn test_complex_queries_are_rejected() {
+        let schema = Schema::build(QueryRoot, MutationRoot, SubscriptionRoot)
+            .limit_complexity(MAX_COMPLEXITY)
+            .finish();
+        let fields = (0..MAX_COMPLEXITY)
+            .map(|i| format!("t{}: team(id: \"{}\") {{ id }}", i, i))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let response = schema.execute(format!("{{ {} }}", fields)).await;
+
+        assert_eq!(response.errors[0].message, "Query is too complex.");
+    }
+
+    #[test]
+    fn test_schema_exposes_teams_commands_and_subscriptions() {
+        let sdl = Schema::build(QueryRoot, MutationRoot, SubscriptionRoot)
+            .finish()
+            .sdl();
+
+        for field in [
+            "team(id: String!): Team",
+            "teams(",
+            "memberships(",
+            "addMember(",
+            "trackMemberForecast(",
+            "setPolicy(",
+            "removePolicy(",
+            "forecastHistory(",
+        ] {
+            assert!(sdl.contains(field), "missing {} in\n{}", field, sdl);
+        }
+        assert!(sdl.contains("type SubscriptionRoot"));
+    }
+
+    #[test]
+    fn test_errors_carry_their_status() {
+        let error = graphql_error(Error::Aggregate(AggregateError::UserError(
+            DomainError::UnknownCommandType("launch-rockets".to_string()),
+        )));
+
+        assert_eq!(error.message, "Unknown command type 'launch-rockets'");
+        let status = error.extensions.unwrap().get("status").cloned();
+        assert_eq!(status, Some(async_graphql::Value::from(400u16)));
+    }
+}
@@ -0,0 +1,260 @@
+use async_graphql::{Context, Json, Object, Result, Subscription};
+use cqrs_es::persist::ViewRepository;
+use rocket::{
+    futures::{stream, Stream, StreamExt},
+    tokio::sync::broadcast::error::RecvError,
+};
+use snowy_model::{MemberId, TeamView, WeatherForecast};
+use tracing::error;
+
+use crate::{
+    api::{
+        auth::Principal, cqrs::CqrsPlumbing, error::Error, handlers::execute_command,
+        metrics::Metrics, request_id::RequestContext,
+    },
+    domain::{commands::TeamCommand, policies::Rule},
+    queries::{
+        member_index::MemberKey,
+        team_summary::{SortOrder, TeamSearch, TeamSort},
+    },
+};
+
+use super::{
+    graphql_error,
+    types::{Membership, Team, TeamSummaryPage},
+};
+
+const DEFAULT_PAGE_SIZE: u64 = 20;
+const MAX_PAGE_SIZE: u64 = 100;
+
+/// Loads the view of a team the principal may read.
+async fn authorized_view(
+    cqrs: &CqrsPlumbing,
+    principal: &Principal,
+    team_id: &str,
+) -> Result<Option<TeamView>, Error> {
+    let view = cqrs.team_view_repository.load(team_id).await?;
+    let members = view.as_ref().map_or(&[][..], |view| &view.members);
+    principal.authorize_read(team_id, members)?;
+    Ok(view)
+}
+
+pub(crate) struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    async fn team(&self, ctx: &Context<'_>, id: String) -> Result<Option<Team>> {
+        let view = authorized_view(ctx.data()?, ctx.data()?, &id)
+            .await
+            .map_err(graphql_error)?;
+        Ok(view.map(Team))
+    }
+
+    /// The teams visible to the caller.
+    async fn teams(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(desc = "Text matched against team names and member emails")] q: Option<String>,
+        #[graphql(default)] sort: TeamSort,
+        #[graphql(default)] order: SortOrder,
+        #[graphql(default)] offset: u64,
+        #[graphql(default_with = "DEFAULT_PAGE_SIZE", desc = "At most 100")] limit: u64,
+    ) -> Result<TeamSummaryPage> {
+        let search = TeamSearch {
+            text: q.filter(|q| !q.trim().is_empty()),
+            sort,
+            order,
+            offset,
+            limit: limit.clamp(1, MAX_PAGE_SIZE),
+            visibility: ctx
+                .data::<Principal>()?
+                .visibility()
+                .map_err(graphql_error)?,
+        };
+        let page = ctx
+            .data::<CqrsPlumbing>()?
+            .team_summary_repository
+            .search(&search)
+            .await
+            .map_err(graphql_error)?;
+        Ok(TeamSummaryPage(page))
+    }
+
+    /// A member's teams visible to the caller, looked up by identifier or
+    /// case-insensitively by email.
+    async fn memberships(
+        &self,
+        ctx: &Context<'_>,
+        member_id: Option<String>,
+        email: Option<String>,
+    ) -> Result<Vec<Membership>> {
+        let key = match (member_id, email) {
+            (Some(member_id), None) => MemberKey::Id(MemberId::new(member_id)),
+            (None, Some(email)) => MemberKey::Email(email),
+            _ => {
+                return Err(graphql_error(Error::BadRequest(
+                    "Expected either memberId or email".to_string(),
+                )))
+            }
+        };
+        let visibility = ctx
+            .data::<Principal>()?
+            .visibility()
+            .map_err(graphql_error)?;
+        let memberships = ctx
+            .data::<CqrsPlumbing>()?
+            .member_index_repository
+            .memberships(&key, &visibility)
+            .await
+            .map_err(graphql_error)?;
+        Ok(memberships.into_iter().map(Membership).collect())
+    }
+}
+
+/// Executes a command on behalf of the caller. Views are updated
+/// asynchronously; subscribe to the team to observe the outcome.
+async fn execute(ctx: &Context<'_>, team_id: &str, command: TeamCommand) -> Result<bool> {
+    execute_command(
+        ctx.data::<CqrsPlumbing>()?,
+        ctx.data::<Metrics>()?,
+        ctx.data::<RequestContext>()?,
+        ctx.data::<Principal>()?,
+        team_id,
+        command,
+    )
+    .await
+    .map_err(graphql_error)?;
+    Ok(true)
+}
+
+pub(crate) struct MutationRoot;
+
+#[Object]
+impl MutationRoot {
+    async fn add_member(
+        &self,
+        ctx: &Context<'_>,
+        team_id: String,
+        member_id: String,
+        email: String,
+    ) -> Result<bool> {
+        let member_id = MemberId::new(member_id);
+        execute(ctx, &team_id, TeamCommand::AddMember { member_id, email }).await
+    }
+
+    /// `forecast` is a JSON object, as accepted by the REST API.
+    async fn track_member_forecast(
+        &self,
+        ctx: &Context<'_>,
+        team_id: String,
+        member_id: String,
+        forecast: Json<WeatherForecast>,
+    ) -> Result<bool> {
+        let command = TeamCommand::TrackMemberForecast {
+            member_id: MemberId::new(member_id),
+            forecast: forecast.0,
+        };
+        execute(ctx, &team_id, command).await
+    }
+
+    async fn remove_member(
+        &self,
+        ctx: &Context<'_>,
+        team_id: String,
+        member_id: String,
+    ) -> Result<bool> {
+        let member_id = MemberId::new(member_id);
+        execute(ctx, &team_id, TeamCommand::RemoveMember { member_id }).await
+    }
+
+    async fn grant_role(
+        &self,
+        ctx: &Context<'_>,
+        team_id: String,
+        member_id: String,
+        role: String,
+    ) -> Result<bool> {
+        let member_id = MemberId::new(member_id);
+        execute(ctx, &team_id, TeamCommand::GrantRole { member_id, role }).await
+    }
+
+    async fn revoke_role(
+        &self,
+        ctx: &Context<'_>,
+        team_id: String,
+        member_id: String,
+        role: String,
+    ) -> Result<bool> {
+        let member_id = MemberId::new(member_id);
+        execute(ctx, &team_id, TeamCommand::RevokeRole { member_id, role }).await
+    }
+
+    /// `rule` is a JSON value, as accepted by the REST API, e.g. `"SelfOnly"`
+    /// or `{"RequireRole": {"roles": ["admin"]}}`.
+    async fn set_policy(
+        &self,
+        ctx: &Context<'_>,
+        team_id: String,
+        command: String,
+        rule: Json<Rule>,
+    ) -> Result<bool> {
+        let rule = rule.0;
+        execute(ctx, &team_id, TeamCommand::SetPolicy { command, rule }).await
+    }
+
+    async fn remove_policy(
+        &self,
+        ctx: &Context<'_>,
+        team_id: String,
+        command: String,
+    ) -> Result<bool> {
+        execute(ctx, &team_id, TeamCommand::RemovePolicy { command }).await
+    }
+}
+
+pub(crate) struct SubscriptionRoot;
+
+#[Subscription]
+impl SubscriptionRoot {
+    /// The team, then again each time it changes, fed by the same
+    /// notifications as the SSE and WebSocket endpoints. Read access is
+    /// checked on each change: the subscription ends with an error once the
+    /// caller may no longer read the team.
+    async fn team(
+        &self,
+        ctx: &Context<'_>,
+        id: String,
+    ) -> Result<impl Stream<Item = Result<Team>>> {
+        let cqrs = ctx.data::<CqrsPlumbing>()?.clone();
+        let principal = ctx.data::<Principal>()?.clone();
+        // Subscribe before reading the current state so no update is missed.
+        let updates = cqrs.notifier.subscribe();
+        let current = authorized_view(&cqrs, &principal, &id)
+            .await
+            .map_err(graphql_error)?;
+
+        let state = Some((cqrs, principal, updates, id));
+        let changes = stream::unfold(state, |state| async move {
+            let (cqrs, principal, mut updates, id) = state?;
+            loop {
+                match updates.recv().await {
+                    Ok(update) if update.team_id != id => continue,
+                    // Missed updates are folded into the latest state.
+                    Ok(_) | Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => return None,
+                }
+                match cqrs.team_view_repository.load(&id).await {
+                    Ok(Some(view)) => {
+                        return match principal.authorize_read(&id, &view.members) {
+                            Ok(()) => Some((Ok(Team(view)), Some((cqrs, principal, updates, id)))),
+                            Err(e) => Some((Err(graphql_error(e)), None)),
+                        };
+                    }
+                    Ok(None) => {}
+                    Err(e) => error!(error = ?e, team_id = %id, "failed to load the team view"),
+                }
+            }
+        });
+        Ok(stream::iter(current.map(|view| Ok(Team(view)))).chain(changes))
+    }
+}
@@ -0,0 +1,371 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use async_graphql::{
+    dataloader::{DataLoader, Loader},
+    Context, Object, Result,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use snowy_model::{self as model, MemberId};
+
+use crate::{api::variant_name, queries::forecast_history::ForecastHistoryRepository};
+
+use super::graphql_error;
+
+/// The forecast history of a team, or of one of its members, between
+/// inclusive bounds.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub(crate) struct ForecastHistoryKey {
+    team_id: String,
+    member_id: Option<MemberId>,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+}
+
+/// Reads the forecast histories requested together with one query per date
+/// range, rather than one per team or member.
+pub(crate) struct ForecastHistoryLoader(pub(crate) Arc<ForecastHistoryRepository>);
+
+impl Loader<ForecastHistoryKey> for ForecastHistoryLoader {
+    type Value = Vec<model::ForecastRecord>;
+    type Error = async_graphql::Error;
+
+    async fn load(
+        &self,
+        keys: &[ForecastHistoryKey],
+    ) -> Result<HashMap<ForecastHistoryKey, Self::Value>> {
+        let ranges: HashSet<_> = keys.iter().map(|key| (key.from, key.to)).collect();
+        let mut histories = HashMap::new();
+        for (from, to) in ranges {
+            let in_range: Vec<&ForecastHistoryKey> = keys
+                .iter()
+                .filter(|key| (key.from, key.to) == (from, to))
+                .collect();
+            let team_ids: Vec<String> = in_range
+                .iter()
+                .map(|key| key.team_id.clone())
+                .collect::<HashSet<_>>()
+                .into_iter()
+                .collect();
+            let forecasts = self
+                .0
+                .team_forecasts(&team_ids, from, to)
+                .await
+                .map_err(graphql_error)?;
+            for key in in_range {
+                let history = forecasts
+                    .get(&key.team_id)
+                    .into_iter()
+                    .flatten()
+                    .filter(|record| {
+                        key.member_id
+                            .as_ref()
+                            .is_none_or(|member_id| &record.member_id == member_id)
+                    })
+                    .cloned()
+                    .collect();
+                histories.insert(key.clone(), history);
+            }
+        }
+        Ok(histories)
+    }
+}
+
+async fn forecast_history(
+    ctx: &Context<'_>,
+    key: ForecastHistoryKey,
+) -> Result<Vec<ForecastRecord>> {
+    let records = ctx
+        .data::<DataLoader<ForecastHistoryLoader>>()?
+        .load_one(key)
+        .await?;
+    Ok(records
+        .unwrap_or_default()
+        .into_iter()
+        .map(ForecastRecord)
+        .collect())
+}
+
+/// A team, as maintained by the team view projection.
+pub(crate) struct Team(pub(crate) model::TeamView);
+
+#[Object]
+impl Team {
+    async fn id(&self) -> &str {
+        self.0.id.as_str()
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn members(&self) -> Vec<Member> {
+        self.0
+            .members
+            .iter()
+            .map(|member| Member {
+                team_id: self.0.id.as_str().to_string(),
+                forecast: self.0.forecasts.get(&member.id).cloned(),
+                member: member.clone(),
+            })
+            .collect()
+    }
+
+    /// Forecasts tracked by the team's members, ordered by date; bounds are
+    /// inclusive.
+    async fn forecast_history(
+        &self,
+        ctx: &Context<'_>,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    ) -> Result<Vec<ForecastRecord>> {
+        let key = ForecastHistoryKey {
+            team_id: self.0.id.as_str().to_string(),
+            member_id: None,
+            from,
+            to,
+        };
+        forecast_history(ctx, key).await
+    }
+
+    /// Forecasts tracked over the team's lifetime, replacements included.
+    async fn total_forecasts_tracked(&self) -> u64 {
+        self.0.total_forecasts_tracked
+    }
+
+    async fn avg_minimum_temperature(&self) -> Option<f32> {
+        self.0.avg_minimum_temperature.as_ref().map(|t| t.0)
+    }
+
+    async fn avg_maximum_temperature(&self) -> Option<f32> {
+        self.0.avg_maximum_temperature.as_ref().map(|t| t.0)
+    }
+
+    async fn median_minimum_temperature(&self) -> Option<f32> {
+        self.0.median_minimum_temperature.as_ref().map(|t| t.0)
+    }
+
+    async fn median_maximum_temperature(&self) -> Option<f32> {
+        self.0.median_maximum_temperature.as_ref().map(|t| t.0)
+    }
+
+    /// In km/h.
+    async fn avg_maximum_wind_speed(&self) -> Option<f32> {
+        self.0
+            .avg_maximum_wind_speed
+            .as_ref()
+            .map(|w| w.to_kilometers_per_hour())
+    }
+
+    async fn avg_comfort_index(&self) -> Option<f32> {
+        self.0.avg_comfort_index
+    }
+}
+
+/// A member of a team, with their latest forecast.
+pub(crate) struct Member {
+    team_id: String,
+    member: model::Member,
+    forecast: Option<model::WeatherForecast>,
+}
+
+#[Object]
+impl Member {
+    async fn id(&self) -> &str {
+        self.member.id.as_str()
+    }
+
+    async fn email(&self) -> &str {
+        &self.member.email
+    }
+
+    async fn roles(&self) -> &[String] {
+        &self.member.roles
+    }
+
+    async fn forecast(&self) -> Option<Forecast> {
+        self.forecast.clone().map(Forecast)
+    }
+
+    /// Forecasts tracked by the member in this team, ordered by date; bounds
+    /// are inclusive.
+    async fn forecast_history(
+        &self,
+        ctx: &Context<'_>,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    ) -> Result<Vec<ForecastRecord>> {
+        let key = ForecastHistoryKey {
+            team_id: self.team_id.clone(),
+            member_id: Some(self.member.id.clone()),
+            from,
+            to,
+        };
+        forecast_history(ctx, key).await
+    }
+}
+
+pub(crate) struct Forecast(model::WeatherForecast);
+
+#[Object]
+impl Forecast {
+    async fn date(&self) -> NaiveDate {
+        self.0.date
+    }
+
+    async fn minimum_temperature(&self) -> f32 {
+        self.0.minimum_temperature.0
+    }
+
+    async fn maximum_temperature(&self) -> f32 {
+        self.0.maximum_temperature.0
+    }
+
+    async fn minimum_apparent_temperature(&self) -> Option<f32> {
+        self.0.minimum_apparent_temperature.as_ref().map(|t| t.0)
+    }
+
+    async fn maximum_apparent_temperature(&self) -> Option<f32> {
+        self.0.maximum_apparent_temperature.as_ref().map(|t| t.0)
+    }
+
+    /// In km/h.
+    async fn maximum_wind_speed(&self) -> Option<f32> {
+        self.0
+            .maximum_wind_speed
+            .as_ref()
+            .map(|w| w.to_kilometers_per_hour())
+    }
+
+    /// Such as `NorthEast`.
+    async fn dominant_wind_direction(&self) -> Option<String> {
+        self.0.dominant_wind_direction.as_ref().map(variant_name)
+    }
+
+    /// The WMO weather condition, such as `LightRain`.
+    async fn weather_code(&self) -> Option<String> {
+        self.0.weather_code.as_ref().map(variant_name)
+    }
+
+    /// Such as `Rain`.
+    async fn weather_category(&self) -> Option<String> {
+        self.0
+            .weather_code
+            .as_ref()
+            .map(|code| variant_name(&code.category()))
+    }
+
+    /// From 0 (miserable) to 100.
+    async fn comfort_index(&self) -> f32 {
+        self.0.comfort_index()
+    }
+}
+
+pub(crate) struct ForecastRecord(model::ForecastRecord);
+
+#[Object]
+impl ForecastRecord {
+    async fn member_id(&self) -> &str {
+        self.0.member_id.as_str()
+    }
+
+    async fn tracked_at(&self) -> DateTime<Utc> {
+        self.0.tracked_at
+    }
+
+    async fn forecast(&self) -> Forecast {
+        Forecast(self.0.forecast.clone())
+    }
+}
+
+pub(crate) struct TeamSummary(model::TeamSummary);
+
+#[Object]
+impl TeamSummary {
+    async fn id(&self) -> &str {
+        self.0.id.as_str()
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn member_count(&self) -> u32 {
+        self.0.member_count
+    }
+
+    async fn last_activity_at(&self) -> DateTime<Utc> {
+        self.0.last_activity_at
+    }
+}
+
+pub(crate) struct TeamSummaryPage(pub(crate) model::TeamSummaryPage);
+
+#[Object]
+impl TeamSummaryPage {
+    async fn teams(&self) -> Vec<TeamSummary> {
+        self.0.teams.iter().cloned().map(TeamSummary).collect()
+    }
+
+    /// Teams matching the search, across every page.
+    async fn total(&self) -> u64 {
+        self.0.total
+    }
+
+    async fn offset(&self) -> u64 {
+        self.0.offset
+    }
+
+    async fn limit(&self) -> u64 {
+        self.0.limit
+    }
+}
+
+/// A member's place in one team.
+pub(crate) struct Membership(pub(crate) model::Membership);
+
+#[Object]
+impl Membership {
+    async fn team_id(&self) -> &str {
+        self.0.team_id.as_str()
+    }
+
+    async fn team_name(&self) -> &str {
+        &self.0.team_name
+    }
+
+    async fn member_id(&self) -> &str {
+        self.0.member_id.as_str()
+    }
+
+    async fn email(&self) -> &str {
+        &self.0.email
+    }
+
+    async fn roles(&self) -> &[String] {
+        &self.0.roles
+    }
+
+    async fn latest_forecast(&self) -> Option<Forecast> {
+        self.0.latest_forecast.clone().map(Forecast)
+    }
+
+    /// Forecasts tracked by the member in this team, ordered by date; bounds
+    /// are inclusive.
+    async fn forecast_history(
+        &self,
+        ctx: &Context<'_>,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    ) -> Result<Vec<ForecastRecord>> {
+        let key = ForecastHistoryKey {
+            team_id: self.0.team_id.as_str().to_string(),
+            member_id: Some(self.0.member_id.clone()),
+            from,
+            to,
+        };
+        forecast_history(ctx, key).await
+    }
+}
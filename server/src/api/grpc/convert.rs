@@ -0,0 +1,343 @@
+use cqrs_es::{DomainEvent, EventEnvelope};
+use serde::de::DeserializeOwned;
+use snowy_model::{
+    weather::WindSpeed, CelsiusTemperature, Member, MemberId, TeamView, TemperatureExtreme,
+    WeatherForecast,
+};
+
+use crate::{
+    api::{error::Error, variant_name},
+    domain::{aggregates::Team, commands::TeamCommand, events::TeamEvent, policies::Rule},
+};
+
+use super::proto::{self, rule, team_command, team_event};
+
+fn parse_variant<T: DeserializeOwned>(field: &str, name: String) -> Result<T, Error> {
+    serde_json::from_value(serde_json::Value::String(name))
+        .map_err(|e| Error::BadRequest(format!("Invalid {}: {}", field, e)))
+}
+
+fn required<T>(field: &str, value: Option<T>) -> Result<T, Error> {
+    value.ok_or_else(|| Error::BadRequest(format!("Missing {}", field)))
+}
+
+impl TryFrom<proto::TeamCommand> for TeamCommand {
+    type Error = Error;
+
+    fn try_from(command: proto::TeamCommand) -> Result<Self, Error> {
+        use team_command::Command;
+
+        Ok(match required("command", command.command)? {
+            Command::AddMember(c) => TeamCommand::AddMember {
+                member_id: MemberId::new(c.member_id),
+                email: c.email,
+            },
+            Command::TrackMemberForecast(c) => TeamCommand::TrackMemberForecast {
+                member_id: MemberId::new(c.member_id),
+                forecast: weather_forecast(required("forecast", c.forecast)?)?,
+            },
+            Command::RemoveMember(c) => TeamCommand::RemoveMember {
+                member_id: MemberId::new(c.member_id),
+            },
+            Command::GrantRole(c) => TeamCommand::GrantRole {
+                member_id: MemberId::new(c.member_id),
+                role: c.role,
+            },
+            Command::RevokeRole(c) => TeamCommand::RevokeRole {
+                member_id: MemberId::new(c.member_id),
+                role: c.role,
+            },
+            Command::SetPolicy(c) => TeamCommand::SetPolicy {
+                command: c.command,
+                rule: required("rule", c.rule)?.try_into()?,
+            },
+            Command::RemovePolicy(c) => TeamCommand::RemovePolicy { command: c.command },
+        })
+    }
+}
+
+impl TryFrom<proto::Rule> for Rule {
+    type Error = Error;
+
+    fn try_from(rule: proto::Rule) -> Result<Self, Error> {
+        Ok(match required("rule", rule.rule)? {
+            rule::Rule::AnyMember(_) => Rule::AnyMember,
+            rule::Rule::RequireRole(r) => Rule::RequireRole { roles: r.roles },
+            rule::Rule::SelfOnly(_) => Rule::SelfOnly,
+            rule::Rule::SelfOrRole(r) => Rule::SelfOrRole { roles: r.roles },
+            rule::Rule::Deny(_) => Rule::Deny,
+        })
+    }
+}
+
+impl From<Rule> for proto::Rule {
+    fn from(rule: Rule) -> Self {
+        let rule = match rule {
+            Rule::AnyMember => rule::Rule::AnyMember(rule::Empty {}),
+            Rule::RequireRole { roles } => rule::Rule::RequireRole(rule::Roles { roles }),
+            Rule::SelfOnly => rule::Rule::SelfOnly(rule::Empty {}),
+            Rule::SelfOrRole { roles } => rule::Rule::SelfOrRole(rule::Roles { roles }),
+            Rule::Deny => rule::Rule::Deny(rule::Empty {}),
+        };
+        proto::Rule { rule: Some(rule) }
+    }
+}
+
+/// Reads a forecast; a function rather than `TryFrom`, which would expose
+/// the crate's error type through a public model type.
+fn weather_forecast(forecast: proto::WeatherForecast) -> Result<WeatherForecast, Error> {
+    let wind_speed = match forecast.maximum_wind_speed {
+        Some(speed) => Some(WindSpeed::new(
+            speed.value,
+            parse_variant("wind speed unit", speed.unit)?,
+        )),
+        None => None,
+    };
+    Ok(WeatherForecast {
+        date: forecast
+            .date
+            .parse()
+            .map_err(|e| Error::BadRequest(format!("Invalid date: {}", e)))?,
+        minimum_temperature: CelsiusTemperature(forecast.minimum_temperature),
+        maximum_temperature: CelsiusTemperature(forecast.maximum_temperature),
+        minimum_apparent_temperature: forecast
+            .minimum_apparent_temperature
+            .map(CelsiusTemperature),
+        maximum_apparent_temperature: forecast
+            .maximum_apparent_temperature
+            .map(CelsiusTemperature),
+        maximum_wind_speed: wind_speed,
+        dominant_wind_direction: forecast
+            .dominant_wind_direction
+            .map(|direction| parse_variant("wind direction", direction))
+            .transpose()?,
+        weather_code: forecast
+            .weather_code
+            .map(|code| parse_variant("weather code", code))
+            .transpose()?,
+    })
+}
+
+impl From<WeatherForecast> for proto::WeatherForecast {
+    fn from(forecast: WeatherForecast) -> Self {
+        proto::WeatherForecast {
+            date: forecast.date.to_string(),
+            minimum_temperature: forecast.minimum_temperature.0,
+            maximum_temperature: forecast.maximum_temperature.0,
+            minimum_apparent_temperature: forecast.minimum_apparent_temperature.map(|t| t.0),
+            maximum_apparent_temperature: forecast.maximum_apparent_temperature.map(|t| t.0),
+            maximum_wind_speed: forecast.maximum_wind_speed.map(|speed| proto::WindSpeed {
+                value: speed.value(),
+                unit: speed.unit().as_str().to_string(),
+            }),
+            dominant_wind_direction: forecast.dominant_wind_direction.as_ref().map(variant_name),
+            weather_code: forecast.weather_code.as_ref().map(variant_name),
+        }
+    }
+}
+
+impl From<Member> for proto::Member {
+    fn from(member: Member) -> Self {
+        proto::Member {
+            id: member.id.as_str().to_string(),
+            email: member.email,
+            roles: member.roles,
+        }
+    }
+}
+
+impl From<TemperatureExtreme> for proto::TemperatureExtreme {
+    fn from(extreme: TemperatureExtreme) -> Self {
+        proto::TemperatureExtreme {
+            member_id: extreme.member_id.as_str().to_string(),
+            temperature: extreme.temperature.0,
+        }
+    }
+}
+
+impl From<TeamView> for proto::TeamView {
+    fn from(view: TeamView) -> Self {
+        proto::TeamView {
+            id: view.id.as_str().to_string(),
+            name: view.name,
+            members: view.members.into_iter().map(Into::into).collect(),
+            forecasts: view
+                .forecasts
+                .into_iter()
+                .map(|(id, forecast)| (id.as_str().to_string(), forecast.into()))
+                .collect(),
+            total_forecasts_tracked: view.total_forecasts_tracked,
+            avg_minimum_temperature: view.avg_minimum_temperature.map(|t| t.0),
+            avg_maximum_temperature: view.avg_maximum_temperature.map(|t| t.0),
+            median_minimum_temperature: view.median_minimum_temperature.map(|t| t.0),
+            median_maximum_temperature: view.median_maximum_temperature.map(|t| t.0),
+            lowest_minimum_temperature: view.lowest_minimum_temperature.map(Into::into),
+            highest_maximum_temperature: view.highest_maximum_temperature.map(Into::into),
+            avg_minimum_apparent_temperature: view.avg_minimum_apparent_temperature.map(|t| t.0),
+            avg_maximum_apparent_temperature: view.avg_maximum_apparent_temperature.map(|t| t.0),
+            avg_maximum_wind_speed: view
+                .avg_maximum_wind_speed
+                .map(|speed| speed.to_kilometers_per_hour()),
+            avg_comfort_index: view.avg_comfort_index,
+            weather_condition_distribution: view
+                .weather_condition_distribution
+                .iter()
+                .map(|(code, count)| (variant_name(code), *count))
+                .collect(),
+            weather_category_distribution: view
+                .weather_category_distribution
+                .iter()
+                .map(|(category, count)| (variant_name(category), *count))
+                .collect(),
+        }
+    }
+}
+
+impl From<TeamEvent> for proto::TeamEvent {
+    fn from(event: TeamEvent) -> Self {
+        use team_event::Event;
+
+        let event = match event {
+            TeamEvent::ForecastTracked {
+                forecasts,
+                member_id,
+            } => Event::ForecastTracked(team_event::ForecastTracked {
+                forecasts: forecasts
+                    .into_iter()
+                    .map(|(id, forecast)| (id.as_str().to_string(), forecast.into()))
+                    .collect(),
+                member_id: member_id.map(|id| id.as_str().to_string()),
+            }),
+            TeamEvent::MemberAdded { member_id, email } => {
+                Event::MemberAdded(team_event::MemberAdded {
+                    member_id: member_id.as_str().to_string(),
+                    email,
+                })
+            }
+            TeamEvent::MemberRemoved { member_id } => {
+                Event::MemberRemoved(team_event::MemberRemoved {
+                    member_id: member_id.as_str().to_string(),
+                })
+            }
+            TeamEvent::RoleGranted { member_id, role } => {
+                Event::RoleGranted(team_event::RoleGranted {
+                    member_id: member_id.as_str().to_string(),
+                    role,
+                })
+            }
+            TeamEvent::RoleRevoked { member_id, role } => {
+                Event::RoleRevoked(team_event::RoleRevoked {
+                    member_id: member_id.as_str().to_string(),
+                    role,
+                })
+            }
+            TeamEvent::PolicySet { command, rule } => Event::PolicySet(team_event::PolicySet {
+                command,
+                rule: Some(rule.into()),
+            }),
+            TeamEvent::PolicyRemoved { command } => {
+                Event::PolicyRemoved(team_event::PolicyRemoved { command })
+            }
+        };
+        proto::TeamEvent { event: Some(event) }
+    }
+}
+
+impl From<&EventEnvelope<Team>> for proto::TeamEventEnvelope {
+    fn from(envelope: &EventEnvelope<Team>) -> Self {
+        proto::TeamEventEnvelope {
+            aggregate_id: envelope.aggregate_id.clone(),
+            sequence: envelope.sequence as u64,
+            event_type: envelope.payload.event_type(),
+            event_version: envelope.payload.event_version(),
+            event: Some(envelope.payload.clone().into()),
+            metadata: envelope.metadata.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use snowy_model::{
+        weather::{WindDirection, WindSpeedUnit},
+        WeatherCode,
+    };
+
+    use super::*;
+
+    fn forecast() -> WeatherForecast {
+        WeatherForecast {
+            date: NaiveDate::from_ymd_opt(2024, 11, 5).unwrap(),
+            minimum_temperature: CelsiusTemperature(-2.5),
+            maximum_temperature: CelsiusTemperature(4.0),
+            minimum_apparent_temperature: Some(CelsiusTemperature(-6.0)),
+            maximum_apparent_temperature: None,
+            maximum_wind_speed: Some(WindSpeed::new(12.0, WindSpeedUnit::MetersPerSecond)),
+            dominant_wind_direction: Some(WindDirection::NorthEast),
+            weather_code: Some(WeatherCode::LightSnow),
+        }
+    }
+
+    #[test]
+    fn test_forecast_round_trips() {
+        let message = proto::WeatherForecast::from(forecast());
+
+        assert_eq!(message.date, "2024-11-05");
+        assert_eq!(message.weather_code.as_deref(), Some("LightSnow"));
+        assert_eq!(message.maximum_wind_speed.as_ref().unwrap().unit, "m/s");
+        assert_eq!(weather_forecast(message).unwrap(), forecast());
+    }
+
+    #[test]
+    fn test_commands_from_messages() {
+        let message = proto::TeamCommand {
+            command: Some(team_command::Command::SetPolicy(team_command::SetPolicy {
+                command: "add-member".to_string(),
+                rule: Some(
+                    Rule::RequireRole {
+                        roles: vec!["admin".to_string()],
+                    }
+                    .into(),
+                ),
+            })),
+        };
+
+        let TeamCommand::SetPolicy { command, rule } = TeamCommand::try_from(message).unwrap()
+        else {
+            panic!("expected a SetPolicy command");
+        };
+        assert_eq!(command, "add-member");
+        assert_eq!(
+            rule,
+            Rule::RequireRole {
+                roles: vec!["admin".to_string()]
+            }
+        );
+    }
+
+    #[test]
+    fn test_invalid_commands_are_bad_requests() {
+        let missing = proto::TeamCommand { command: None };
+        let bad_code = proto::TeamCommand {
+            command: Some(team_command::Command::TrackMemberForecast(
+                team_command::TrackMemberForecast {
+                    member_id: "member-1".to_string(),
+                    forecast: Some(proto::WeatherForecast {
+                        weather_code: Some("Sunny".to_string()),
+                        ..forecast().into()
+                    }),
+                },
+            )),
+        };
+
+        assert!(matches!(
+            TeamCommand::try_from(missing),
+            Err(Error::BadRequest(_))
+        ));
+        assert!(matches!(
+            TeamCommand::try_from(bad_code),
+            Err(Error::BadRequest(_))
+        ));
+    }
+}
@@ -0,0 +1,387 @@
+use std::{net::SocketAddr, pin::Pin, sync::Arc};
+
+use cqrs_es::{
+    persist::{PersistedEventRepository, ViewRepository},
+    EventEnvelope,
+};
+use postgres_es::PostgresEventRepository;
+use rocket::{
+    futures::{stream, Stream, StreamExt},
+    tokio::sync::broadcast::{self, error::RecvError},
+    Shutdown,
+};
+use sqlx::{Pool, Postgres};
+use tonic::{metadata::MetadataMap, transport::Server, Code, Request, Response, Status};
+use tracing::{error, info, info_span};
+
+use crate::{
+    domain::{aggregates::Team, commands::TeamCommand},
+    queries::{notifier::TeamUpdate, team::TeamViewRepository},
+};
+
+use super::{
+    auth::{api_key::ApiKeyRepository, jwt::JwtValidator, Credentials, Principal},
+    cqrs::CqrsPlumbing,
+    error::Error,
+    handlers::execute_command,
+    metrics::Metrics,
+    request_id::{request_id_from_header, RequestContext},
+};
+
+mod convert;
+
+pub(crate) mod proto {
+    tonic::include_proto!("snowy.v1");
+}
+
+use self::proto::team_service_server::{TeamService, TeamServiceServer};
+
+const API_KEY_METADATA: &str = "x-api-key";
+const REQUEST_ID_METADATA: &str = "x-request-id";
+
+/// The gRPC team service, sharing its plumbing with the HTTP API.
+pub(crate) struct Teams {
+    cqrs: CqrsPlumbing,
+    metrics: Metrics,
+    api_keys: ApiKeyRepository,
+    jwt_validator: Option<Arc<JwtValidator>>,
+    /// Reads the events past those a stream sent, which the store cannot.
+    events: Arc<PostgresEventRepository>,
+}
+
+impl Teams {
+    pub(crate) fn new(
+        cqrs: CqrsPlumbing,
+        metrics: Metrics,
+        pool: Pool<Postgres>,
+        jwt_validator: Option<Arc<JwtValidator>>,
+    ) -> Self {
+        Self {
+            cqrs,
+            metrics,
+            api_keys: ApiKeyRepository::new(pool.clone()),
+            jwt_validator,
+            events: Arc::new(PostgresEventRepository::new(pool)),
+        }
+    }
+
+    /// Authenticates the caller from the `x-api-key` or `authorization`
+    /// metadata, as the HTTP API does from the headers of the same name.
+    async fn authenticate(&self, metadata: &MetadataMap) -> Result<Principal, Error> {
+        let credentials = Credentials {
+            // Secrets that are not ASCII match no key.
+            api_key: metadata
+                .get(API_KEY_METADATA)
+                .map(|value| value.to_str().unwrap_or_default()),
+            authorization: metadata
+                .get("authorization")
+                .and_then(|value| value.to_str().ok()),
+        };
+        credentials
+            .authenticate(Some(&self.api_keys), self.jwt_validator.as_deref())
+            .await
+    }
+}
+
+/// Tracing context of a call, reusing the `x-request-id` metadata when
+/// well-formed.
+fn request_context(metadata: &MetadataMap, method: &str) -> RequestContext {
+    let request_id = request_id_from_header(
+        metadata
+            .get(REQUEST_ID_METADATA)
+            .and_then(|value| value.to_str().ok()),
+    );
+    let span = info_span!("grpc", request_id = %request_id, method);
+    RequestContext { request_id, span }
+}
+
+/// The gRPC status matching the HTTP status the REST API answers with.
+fn status(error: Error) -> Status {
+    let code = match error.status().code {
+        400 => Code::InvalidArgument,
+        401 => Code::Unauthenticated,
+        403 => Code::PermissionDenied,
+        404 => Code::NotFound,
+        409 => Code::Aborted,
+        503 => Code::Unavailable,
+        _ => Code::Internal,
+    };
+    Status::new(code, error.message())
+}
+
+#[tonic::async_trait]
+impl TeamService for Teams {
+    type StreamEventsStream =
+        Pin<Box<dyn Stream<Item = Result<proto::TeamEventEnvelope, Status>> + Send>>;
+
+    async fn execute_command(
+        &self,
+        request: Request<proto::ExecuteCommandRequest>,
+    ) -> Result<Response<proto::ExecuteCommandResponse>, Status> {
+        let principal = self
+            .authenticate(request.metadata())
+            .await
+            .map_err(status)?;
+        let context = request_context(request.metadata(), "ExecuteCommand");
+        let proto::ExecuteCommandRequest { team_id, command } = request.into_inner();
+        let command = TeamCommand::try_from(command.unwrap_or_default()).map_err(status)?;
+
+        execute_command(
+            &self.cqrs,
+            &self.metrics,
+            &context,
+            &principal,
+            &team_id,
+            command,
+        )
+        .await
+        .map_err(status)?;
+        Ok(Response::new(proto::ExecuteCommandResponse {}))
+    }
+
+    async fn get_team(
+        &self,
+        request: Request<proto::GetTeamRequest>,
+    ) -> Result<Response<proto::TeamView>, Status> {
+        let principal = self
+            .authenticate(request.metadata())
+            .await
+            .map_err(status)?;
+        let team_id = request.into_inner().team_id;
+
+        let view = self
+            .cqrs
+            .team_view_repository
+            .load(&team_id)
+            .await
+            .map_err(|e| status(e.into()))?;
+        let members = view.as_ref().map_or(&[][..], |view| &view.members);
+        principal
+            .authorize_read(&team_id, members)
+            .map_err(status)?;
+        view.map(|view| Response::new(view.into()))
+            .ok_or_else(|| Status::not_found(format!("Unknown team '{}'", team_id)))
+    }
+
+    async fn stream_events(
+        &self,
+        request: Request<proto::StreamEventsRequest>,
+    ) -> Result<Response<Self::StreamEventsStream>, Status> {
+        let principal = self
+            .authenticate(request.metadata())
+            .await
+            .map_err(status)?;
+        let proto::StreamEventsRequest {
+            team_id,
+            after_sequence,
+        } = request.into_inner();
+
+        let feed = EventFeed {
+            events: self.events.clone(),
+            views: self.cqrs.team_view_repository.clone(),
+            principal,
+            // Subscribe before reading the events so no update is missed.
+            updates: self.cqrs.notifier.subscribe(),
+            team_id,
+            last_sent: after_sequence,
+            stale: true,
+        };
+        feed.authorize().await.map_err(status)?;
+        let batches = stream::unfold(Some(feed), |feed| async move {
+            let mut feed = feed?;
+            match feed.next_batch().await {
+                Ok(Some(batch)) => Some((Ok(batch), Some(feed))),
+                Ok(None) => None,
+                // The stream ends with the error.
+                Err(e) => Some((Err(e), None)),
+            }
+        });
+        let events = batches.flat_map(|batch| {
+            stream::iter(match batch {
+                Ok(events) => events.into_iter().map(Ok).collect(),
+                Err(e) => vec![Err(e)],
+            })
+        });
+        Ok(Response::new(Box::pin(events)))
+    }
+}
+
+/// Reads the events of a team as the team view projection announces them,
+/// for as long as the principal may read the team.
+struct EventFeed {
+    events: Arc<PostgresEventRepository>,
+    views: Arc<TeamViewRepository>,
+    principal: Principal,
+    updates: broadcast::Receiver<TeamUpdate>,
+    team_id: String,
+    last_sent: u64,
+    /// Whether events may have been committed since the last read.
+    stale: bool,
+}
+
+impl EventFeed {
+    /// Checks that the principal may read the team, as its members are now.
+    async fn authorize(&self) -> Result<(), Error> {
+        let members = self
+            .views
+            .load(&self.team_id)
+            .await?
+            .map(|view| view.members)
+            .unwrap_or_default();
+        self.principal.authorize_read(&self.team_id, &members)
+    }
+
+    async fn events_after_last_sent(&self) -> Result<Vec<proto::TeamEventEnvelope>, Error> {
+        let events = self
+            .events
+            .get_last_events::<Team>(&self.team_id, self.last_sent as usize)
+            .await?;
+        events
+            .into_iter()
+            .map(|event| Ok((&EventEnvelope::<Team>::try_from(event)?).into()))
+            .collect()
+    }
+
+    /// The events after the last ones sent, waiting for the next update when
+    /// there are none; `None` once the notifier closes. Fails with
+    /// `PermissionDenied` once the principal may no longer read the team.
+    async fn next_batch(&mut self) -> Result<Option<Vec<proto::TeamEventEnvelope>>, Status> {
+        loop {
+            if self.stale {
+                self.stale = false;
+                self.authorize().await.map_err(status)?;
+                let batch = self.events_after_last_sent().await.map_err(status)?;
+                if let Some(last) = batch.last() {
+                    self.last_sent = last.sequence;
+                    return Ok(Some(batch));
+                }
+            }
+
+            match self.updates.recv().await {
+                Ok(update) if update.team_id == self.team_id => self.stale = true,
+                Ok(_) => {}
+                // Missed updates are caught up on by reading the events again.
+                Err(RecvError::Lagged(_)) => self.stale = true,
+                Err(RecvError::Closed) => return Ok(None),
+            }
+        }
+    }
+}
+
+/// Serves the gRPC API on `address` until the server shuts down.
+pub(crate) async fn serve(address: SocketAddr, teams: Teams, shutdown: Shutdown) {
+    info!(%address, "serving gRPC");
+    if let Err(e) = Server::builder()
+        .add_service(TeamServiceServer::new(teams))
+        .serve_with_shutdown(address, shutdown)
+        .await
+    {
+        error!(error = %e, %address, "gRPC server failed");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use cqrs_es::AggregateError;
+    use rocket::tokio;
+    use snowy_model::MemberId;
+
+    use crate::{
+        api::{auth::jwt::Claims, cqrs::setup_cqrs},
+        domain::error::Error as DomainError,
+        queries::team::TeamViewProjection,
+        testing,
+    };
+
+    use super::*;
+
+    /// A team of alice, bob and carol, and the feed of its events for bob
+    /// past the first one.
+    async fn feed() -> (EventFeed, CqrsPlumbing, Pool<Postgres>) {
+        let pool = testing::database().await;
+        let cqrs = setup_cqrs(pool.clone(), &Metrics::new().unwrap());
+        for member in ["alice", "bob", "carol"] {
+            let command = TeamCommand::AddMember {
+                member_id: MemberId::new(member.to_string()),
+                email: format!("{}@example.com", member),
+            };
+            cqrs.execute("team-1", command, HashMap::new())
+                .await
+                .unwrap();
+        }
+        testing::project(&pool, &TeamViewProjection, "team-1").await;
+        let feed = EventFeed {
+            events: Arc::new(PostgresEventRepository::new(pool.clone())),
+            views: cqrs.team_view_repository.clone(),
+            principal: Principal::Bearer(Claims {
+                email: "bob@example.com".to_string(),
+                exp: i64::MAX,
+                email_verified: None,
+            }),
+            updates: cqrs.notifier.subscribe(),
+            team_id: "team-1".to_string(),
+            last_sent: 1,
+            stale: true,
+        };
+        (feed, cqrs, pool)
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL, see SNOWY_TEST_DATABASE_URL"]
+    async fn test_event_feed_reads_past_the_last_sent_event() {
+        let (mut feed, _, _) = feed().await;
+
+        let batch = feed.next_batch().await.unwrap().unwrap();
+
+        let sequences: Vec<u64> = batch.iter().map(|event| event.sequence).collect();
+        assert_eq!(sequences, vec![2, 3]);
+        assert_eq!(feed.last_sent, 3);
+        assert!(feed.events_after_last_sent().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL, see SNOWY_TEST_DATABASE_URL"]
+    async fn test_event_feed_ends_when_the_member_is_removed() {
+        let (mut feed, cqrs, pool) = feed().await;
+        feed.next_batch().await.unwrap().unwrap();
+
+        let command = TeamCommand::RemoveMember {
+            member_id: MemberId::new("bob".to_string()),
+        };
+        cqrs.execute("team-1", command, HashMap::new())
+            .await
+            .unwrap();
+        testing::project(&pool, &TeamViewProjection, "team-1").await;
+        cqrs.notifier.send(TeamUpdate {
+            team_id: "team-1".to_string(),
+            sequence: 4,
+        });
+
+        let denied = feed.next_batch().await.unwrap_err();
+        assert_eq!(denied.code(), Code::PermissionDenied);
+    }
+
+    #[test]
+    fn test_errors_map_to_matching_status_codes() {
+        let rejected = status(Error::Aggregate(AggregateError::UserError(
+            DomainError::UnknownCommandType("launch-rockets".to_string()),
+        )));
+        assert_eq!(rejected.code(), Code::InvalidArgument);
+        assert_eq!(rejected.message(), "Unknown command type 'launch-rockets'");
+
+        assert_eq!(
+            status(Error::Unauthorized("Missing credentials".to_string())).code(),
+            Code::Unauthenticated
+        );
+        assert_eq!(
+            status(Error::Forbidden("Not a member".to_string())).code(),
+            Code::PermissionDenied
+        );
+        assert_eq!(
+            status(Error::Aggregate(AggregateError::AggregateConflict)).code(),
+            Code::Aborted
+        );
+    }
+}
@@ -1,13 +1,46 @@
+use std::{collections::HashMap, time::Instant};
+
+use chrono::NaiveDate;
+
 use rocket::{
-    catch, get, post,
+    catch, delete, get,
+    http::{ContentType, Status},
+    options, post, put,
     request::Request,
+    response::status,
     serde::json::{self, json, Value},
-    State,
+    uri, FromForm, State,
+};
+use serde::Deserialize;
+use snowy_model::{MemberId, WeatherForecast};
+use sqlx::{Pool, Postgres};
+use tracing::{info_span, Instrument};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    domain::{commands::TeamCommand, policies::ACTOR_METADATA_KEY},
+    queries::{
+        forecast_history::ForecastFilter,
+        member_index::MemberKey,
+        team_summary::{SortOrder, TeamSearch, TeamSort},
+    },
 };
 
-use super::{cqrs::CqrsPlumbing, error::Error};
+use super::{
+    auth::Principal,
+    cqrs::CqrsPlumbing,
+    error::Error,
+    metrics::{command_outcome, Metrics},
+    request_id::RequestContext,
+};
 use cqrs_es::persist::ViewRepository; // FIXME: move over
 
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "operations",
+    responses((status = 200, description = "The server is up"))
+)]
 #[get("/health")]
 pub fn health() -> Value {
     json!({
@@ -15,6 +48,31 @@ pub fn health() -> Value {
     })
 }
 
+/// Answers CORS preflight requests for every path; the `Cors` fairing adds the headers.
+#[options("/<_..>")]
+pub fn preflight() -> Status {
+    Status::NoContent
+}
+
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "operations",
+    responses(
+        (status = 200, description = "Prometheus metrics", content_type = "text/plain", body = String),
+        (status = 500, description = "Metrics could not be rendered", body = ErrorBody)
+    )
+)]
+#[get("/metrics")]
+pub fn metrics(
+    metrics: &State<Metrics>,
+    pool: &State<Pool<Postgres>>,
+) -> Result<(ContentType, String), Error> {
+    metrics.observe_db_pool(pool);
+    let content_type = ContentType::new("text", "plain").with_params(("version", "0.0.4"));
+    Ok((content_type, metrics.render()?))
+}
+
 #[catch(400)]
 pub fn bad_request(_req: &Request) -> Value {
     json!({
@@ -22,6 +80,20 @@ pub fn bad_request(_req: &Request) -> Value {
     })
 }
 
+#[catch(401)]
+pub fn unauthorized(_req: &Request) -> Value {
+    json!({
+        "error": "Unauthorized"
+    })
+}
+
+#[catch(403)]
+pub fn forbidden(_req: &Request) -> Value {
+    json!({
+        "error": "Forbidden"
+    })
+}
+
 #[catch(404)]
 pub fn not_found(_req: &Request) -> Value {
     json!({
@@ -37,25 +109,543 @@ pub fn internal_error(req: &Request) -> Value {
     })
 }
 
+/// Executes a command sent as an externally tagged envelope, e.g. `{"AddMember": {...}}`.
+/// The team view reflects it once projected, shortly after this returns.
+#[utoipa::path(
+    post,
+    path = "/api/team/{team_id}",
+    tag = "teams",
+    params(
+        ("team_id" = String, Path, description = "Team identifier")
+    ),
+    request_body = TeamCommand,
+    responses(
+        (status = 200, description = "The command was executed"),
+        (status = 400, description = "The command was rejected", body = ErrorBody),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 403, description = "Not allowed to issue the command", content_type = "application/problem+json", body = Problem),
+        (status = 409, description = "Concurrent modification of the team", body = ErrorBody)
+    ),
+    security(("api_key" = []), ("bearer" = []))
+)]
 #[post("/api/team/<team_id>", data = "<payload>")]
 pub async fn command_handler(
     cqrs: &State<CqrsPlumbing>,
+    metrics: &State<Metrics>,
+    request: &RequestContext,
+    principal: Principal,
+    team_id: &str,
+    payload: json::Json<TeamCommand>,
+) -> Result<Value, Error> {
+    execute_command(cqrs, metrics, request, &principal, team_id, payload.0).await
+}
+
+/// Body of `POST /api/teams/<team_id>/members`.
+#[derive(Deserialize, ToSchema)]
+pub struct NewMember {
+    member_id: MemberId,
+    email: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/teams/{team_id}/members",
+    tag = "teams",
+    params(
+        ("team_id" = String, Path, description = "Team identifier")
+    ),
+    request_body = NewMember,
+    responses(
+        (status = 201, description = "The member was added; the location lists their teams"),
+        (status = 400, description = "The command was rejected", body = ErrorBody),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 403, description = "Not allowed to issue the command", content_type = "application/problem+json", body = Problem),
+        (status = 409, description = "Concurrent modification of the team", body = ErrorBody)
+    ),
+    security(("api_key" = []), ("bearer" = []))
+)]
+#[post("/api/teams/<team_id>/members", data = "<payload>")]
+pub async fn add_member(
+    cqrs: &State<CqrsPlumbing>,
+    metrics: &State<Metrics>,
+    request: &RequestContext,
+    principal: Principal,
+    team_id: &str,
+    payload: json::Json<NewMember>,
+) -> Result<status::Created<Value>, Error> {
+    let NewMember { member_id, email } = payload.0;
+    let location = uri!(member_handler(member_id.as_str())).to_string();
+    let command = TeamCommand::AddMember { member_id, email };
+    let body = execute_command(cqrs, metrics, request, &principal, team_id, command).await?;
+    Ok(status::Created::new(location).body(body))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/teams/{team_id}/members/{member_id}/forecast",
+    tag = "teams",
+    params(
+        ("team_id" = String, Path, description = "Team identifier"),
+        ("member_id" = String, Path, description = "Member identifier")
+    ),
+    request_body = WeatherForecast,
+    responses(
+        (status = 200, description = "The forecast was tracked"),
+        (status = 400, description = "The command was rejected", body = ErrorBody),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 403, description = "Not allowed to issue the command", content_type = "application/problem+json", body = Problem),
+        (status = 409, description = "Concurrent modification of the team", body = ErrorBody)
+    ),
+    security(("api_key" = []), ("bearer" = []))
+)]
+#[put(
+    "/api/teams/<team_id>/members/<member_id>/forecast",
+    data = "<payload>"
+)]
+pub async fn track_member_forecast(
+    cqrs: &State<CqrsPlumbing>,
+    metrics: &State<Metrics>,
+    request: &RequestContext,
+    principal: Principal,
+    team_id: &str,
+    member_id: &str,
+    payload: json::Json<WeatherForecast>,
+) -> Result<Value, Error> {
+    let command = TeamCommand::TrackMemberForecast {
+        member_id: MemberId::new(member_id.to_string()),
+        forecast: payload.0,
+    };
+    execute_command(cqrs, metrics, request, &principal, team_id, command).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/teams/{team_id}/members/{member_id}",
+    tag = "teams",
+    params(
+        ("team_id" = String, Path, description = "Team identifier"),
+        ("member_id" = String, Path, description = "Member identifier")
+    ),
+    responses(
+        (status = 200, description = "The member was removed"),
+        (status = 400, description = "The command was rejected", body = ErrorBody),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 403, description = "Not allowed to issue the command", content_type = "application/problem+json", body = Problem),
+        (status = 409, description = "Concurrent modification of the team", body = ErrorBody)
+    ),
+    security(("api_key" = []), ("bearer" = []))
+)]
+#[delete("/api/teams/<team_id>/members/<member_id>")]
+pub async fn remove_member(
+    cqrs: &State<CqrsPlumbing>,
+    metrics: &State<Metrics>,
+    request: &RequestContext,
+    principal: Principal,
     team_id: &str,
-    payload: json::Json<crate::domain::commands::TeamCommand>,
+    member_id: &str,
 ) -> Result<Value, Error> {
-    cqrs.cqrs.execute(team_id, payload.0.clone()).await?;
+    let command = TeamCommand::RemoveMember {
+        member_id: MemberId::new(member_id.to_string()),
+    };
+    execute_command(cqrs, metrics, request, &principal, team_id, command).await
+}
+
+/// Authorizes and executes a command on behalf of `principal`, recording metrics.
+pub(crate) async fn execute_command(
+    cqrs: &CqrsPlumbing,
+    metrics: &Metrics,
+    request: &RequestContext,
+    principal: &Principal,
+    team_id: &str,
+    command: TeamCommand,
+) -> Result<Value, Error> {
+    let command_type = command.command_type();
+    let actor = principal.authorize_command(cqrs, team_id, &command).await?;
+
+    let metadata = HashMap::from([
+        ("request_id".to_string(), request.request_id.clone()),
+        (ACTOR_METADATA_KEY.to_string(), actor.to_metadata()),
+    ]);
+    let span = info_span!(
+        parent: &request.span,
+        "command",
+        command = command_type,
+        team_id
+    );
+
+    let started = Instant::now();
+    let result = cqrs
+        .execute(team_id, command, metadata)
+        .instrument(span)
+        .await;
+    metrics.observe_command(command_type, command_outcome(&result), started.elapsed());
+
+    result?;
     Ok(json!({
         "status": "ok"
     }))
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/team/{team_id}",
+    tag = "teams",
+    params(
+        ("team_id" = String, Path, description = "Team identifier")
+    ),
+    responses(
+        (status = 200, description = "The team view", body = TeamView),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 403, description = "Not allowed to read the team", content_type = "application/problem+json", body = Problem),
+        (status = 404, description = "Unknown team", body = ErrorBody)
+    ),
+    security(("api_key" = []), ("bearer" = []))
+)]
 #[get("/api/team/<team_id>")]
 pub async fn query_handler(
     cqrs: &State<CqrsPlumbing>,
+    request: &RequestContext,
+    principal: Principal,
     team_id: &str,
 ) -> Result<Option<Value>, Error> {
-    match cqrs.team_view_repository.load(team_id).await? {
-        Some(team_view) => Ok(Some(json!(team_view))),
-        None => Ok(None),
+    let span = info_span!(parent: &request.span, "query", team_id);
+    match cqrs
+        .team_view_repository
+        .load(team_id)
+        .instrument(span)
+        .await?
+    {
+        Some(team_view) => {
+            principal.authorize_read(team_id, &team_view.members)?;
+            Ok(Some(json!(team_view)))
+        }
+        None => {
+            principal.authorize_read(team_id, &[])?;
+            Ok(None)
+        }
+    }
+}
+
+const DEFAULT_PAGE_SIZE: u64 = 20;
+const MAX_PAGE_SIZE: u64 = 100;
+
+/// Query string of `GET /api/teams`.
+#[derive(FromForm, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TeamListParams {
+    /// Text matched against team names and member emails.
+    q: Option<String>,
+    sort: Option<TeamSort>,
+    order: Option<SortOrder>,
+    /// Number of teams to skip.
+    offset: Option<u64>,
+    /// Maximum number of teams to return, at most 100.
+    limit: Option<u64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/teams",
+    tag = "teams",
+    params(TeamListParams),
+    responses(
+        (status = 200, description = "The teams visible to the caller", body = TeamSummaryPage),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 403, description = "Not allowed to list teams", content_type = "application/problem+json", body = Problem)
+    ),
+    security(("api_key" = []), ("bearer" = []))
+)]
+#[get("/api/teams?<params..>")]
+pub async fn list_teams(
+    cqrs: &State<CqrsPlumbing>,
+    request: &RequestContext,
+    principal: Principal,
+    params: TeamListParams,
+) -> Result<Value, Error> {
+    let search = TeamSearch {
+        text: params.q.filter(|q| !q.trim().is_empty()),
+        sort: params.sort.unwrap_or_default(),
+        order: params.order.unwrap_or_default(),
+        offset: params.offset.unwrap_or(0),
+        limit: params
+            .limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE),
+        visibility: principal.visibility()?,
+    };
+
+    let span = info_span!(parent: &request.span, "query", query = "team_summary");
+    let page = cqrs
+        .team_summary_repository
+        .search(&search)
+        .instrument(span)
+        .await?;
+    Ok(json!(page))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/members/{member_id}",
+    tag = "members",
+    params(
+        ("member_id" = String, Path, description = "Member identifier")
+    ),
+    responses(
+        (status = 200, description = "The member's teams visible to the caller", body = [Membership]),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 404, description = "The member is in no team visible to the caller", body = ErrorBody)
+    ),
+    security(("api_key" = []), ("bearer" = []))
+)]
+#[get("/api/members/<member_id>")]
+pub async fn member_handler(
+    cqrs: &State<CqrsPlumbing>,
+    request: &RequestContext,
+    principal: Principal,
+    member_id: &str,
+) -> Result<Option<Value>, Error> {
+    let key = MemberKey::Id(MemberId::new(member_id.to_string()));
+    find_memberships(cqrs, request, principal, key).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/members",
+    tag = "members",
+    params(
+        ("email" = String, Query, description = "Member email, matched case-insensitively")
+    ),
+    responses(
+        (status = 200, description = "The member's teams visible to the caller", body = [Membership]),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 404, description = "The member is in no team visible to the caller", body = ErrorBody)
+    ),
+    security(("api_key" = []), ("bearer" = []))
+)]
+#[get("/api/members?<email>")]
+pub async fn member_by_email_handler(
+    cqrs: &State<CqrsPlumbing>,
+    request: &RequestContext,
+    principal: Principal,
+    email: &str,
+) -> Result<Option<Value>, Error> {
+    find_memberships(
+        cqrs,
+        request,
+        principal,
+        MemberKey::Email(email.to_string()),
+    )
+    .await
+}
+
+async fn find_memberships(
+    cqrs: &CqrsPlumbing,
+    request: &RequestContext,
+    principal: Principal,
+    key: MemberKey,
+) -> Result<Option<Value>, Error> {
+    let visibility = principal.visibility()?;
+    let span = info_span!(parent: &request.span, "query", query = "member_index");
+    let memberships = cqrs
+        .member_index_repository
+        .memberships(&key, &visibility)
+        .instrument(span)
+        .await?;
+
+    if memberships.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(json!(memberships)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/team/{team_id}/forecasts",
+    tag = "teams",
+    params(
+        ("team_id" = String, Path, description = "Team identifier"),
+        ("from" = Option<String>, Query, description = "First forecast date, as YYYY-MM-DD"),
+        ("to" = Option<String>, Query, description = "Last forecast date, as YYYY-MM-DD"),
+        ("member" = Option<String>, Query, description = "Only forecasts of this member")
+    ),
+    responses(
+        (status = 200, description = "Tracked forecasts, ordered by date", body = [ForecastRecord]),
+        (status = 400, description = "Malformed date", body = ErrorBody),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 403, description = "Not allowed to read the team", content_type = "application/problem+json", body = Problem)
+    ),
+    security(("api_key" = []), ("bearer" = []))
+)]
+#[get("/api/team/<team_id>/forecasts?<from>&<to>&<member>")]
+pub async fn forecasts_handler(
+    cqrs: &State<CqrsPlumbing>,
+    request: &RequestContext,
+    principal: Principal,
+    team_id: &str,
+    from: Option<&str>,
+    to: Option<&str>,
+    member: Option<&str>,
+) -> Result<Value, Error> {
+    let members = cqrs
+        .team_view_repository
+        .load(team_id)
+        .await?
+        .map(|view| view.members)
+        .unwrap_or_default();
+    principal.authorize_read(team_id, &members)?;
+
+    let filter = ForecastFilter {
+        from: from.map(parse_date).transpose()?,
+        to: to.map(parse_date).transpose()?,
+        member_id: member.map(|m| MemberId::new(m.to_string())),
+    };
+    let span = info_span!(parent: &request.span, "query", query = "forecast_history", team_id);
+    let forecasts = cqrs
+        .forecast_history_repository
+        .forecasts(team_id, &filter)
+        .instrument(span)
+        .await?;
+    Ok(json!(forecasts))
+}
+
+fn parse_date(date: &str) -> Result<NaiveDate, Error> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|_| Error::BadRequest(format!("Invalid date '{}', expected YYYY-MM-DD", date)))
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::{
+        http::{ContentType, Header, Status},
+        local::asynchronous::Client,
+        routes, tokio,
+    };
+    use snowy_model::{CelsiusTemperature, WeatherForecast};
+
+    use super::*;
+    use crate::{
+        api::{
+            auth::api_key::{ApiKeyRepository, API_KEY_HEADER},
+            cqrs::setup_cqrs,
+            request_id::RequestId,
+        },
+        testing,
+    };
+
+    /// A client of the member endpoints on a fresh database, with the header
+    /// of an API key of `team-1` granted `scopes`.
+    async fn client(scopes: &[&str]) -> (Client, CqrsPlumbing, Header<'static>) {
+        let pool = testing::database().await;
+        let metrics = Metrics::new().unwrap();
+        let cqrs = setup_cqrs(pool.clone(), &metrics);
+        let keys = ApiKeyRepository::new(pool);
+        let issued = keys
+            .issue(
+                "team-1",
+                "test",
+                scopes.iter().map(|scope| scope.to_string()).collect(),
+            )
+            .await
+            .unwrap();
+
+        let rocket = rocket::build()
+            .attach(RequestId)
+            .mount(
+                "/",
+                routes![add_member, track_member_forecast, remove_member],
+            )
+            .manage(cqrs.clone())
+            .manage(metrics)
+            .manage(keys);
+        let client = Client::tracked(rocket).await.unwrap();
+        (client, cqrs, Header::new(API_KEY_HEADER, issued.secret))
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL, see SNOWY_TEST_DATABASE_URL"]
+    async fn test_member_endpoints_issue_commands() {
+        let (client, cqrs, key) = client(TeamCommand::COMMAND_TYPES).await;
+        let forecast = WeatherForecast {
+            minimum_temperature: CelsiusTemperature(-3.0),
+            ..Default::default()
+        };
+
+        let response = client
+            .post("/api/teams/team-1/members")
+            .header(key.clone())
+            .header(ContentType::JSON)
+            .body(r#"{"member_id": "alice", "email": "alice@example.com"}"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Created);
+        assert_eq!(
+            response.headers().get_one("Location"),
+            Some("/api/members/alice")
+        );
+
+        let response = client
+            .put("/api/teams/team-1/members/alice/forecast")
+            .header(key.clone())
+            .header(ContentType::JSON)
+            .body(serde_json::to_string(&forecast).unwrap())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+
+        let team = cqrs.load_team("team-1").await.unwrap();
+        let alice = MemberId::new("alice".to_string());
+        assert_eq!(team.members.len(), 1);
+        assert_eq!(team.members[0].id, alice);
+        assert_eq!(team.members[0].email, "alice@example.com");
+        assert_eq!(team.forecasts.get(&alice), Some(&forecast));
+
+        let response = client
+            .delete("/api/teams/team-1/members/alice")
+            .header(key)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        assert!(cqrs.load_team("team-1").await.unwrap().members.is_empty());
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL, see SNOWY_TEST_DATABASE_URL"]
+    async fn test_member_endpoint_errors() {
+        let (client, _, key) = client(&["add-member"]).await;
+        let add_alice = || {
+            client
+                .post("/api/teams/team-1/members")
+                .header(ContentType::JSON)
+                .body(r#"{"member_id": "alice", "email": "alice@example.com"}"#)
+        };
+
+        let response = add_alice().dispatch().await;
+        assert_eq!(response.status(), Status::Unauthorized);
+
+        let response = add_alice().header(key.clone()).dispatch().await;
+        assert_eq!(response.status(), Status::Created);
+        let response = add_alice().header(key.clone()).dispatch().await;
+        assert_eq!(response.status(), Status::BadRequest);
+
+        let response = client
+            .delete("/api/teams/team-1/members/alice")
+            .header(key.clone())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Forbidden);
+        let problem: Value = response.into_json().await.unwrap();
+        assert!(problem["detail"]
+            .as_str()
+            .unwrap()
+            .ends_with("lacks the 'remove-member' scope"));
+
+        let response = client
+            .put("/api/teams/team-1/members/alice/forecast")
+            .header(key)
+            .header(ContentType::JSON)
+            .body(r#"{"date": "tomorrow"}"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::UnprocessableEntity);
     }
 }
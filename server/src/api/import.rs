@@ -0,0 +1,171 @@
+use rocket::{
+    data::{Data, ToByteUnit},
+    post,
+    serde::json::Json,
+    FromForm, State,
+};
+use serde::Serialize;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::import::{self, ImportFormat, ImportPlan, ImportReport};
+
+use super::{
+    auth::Principal, batch::execute_batch, cqrs::CqrsPlumbing, error::Error, metrics::Metrics,
+    request_id::RequestContext,
+};
+
+const MAX_IMPORT_MIB: u64 = 10;
+
+/// Query string of `POST /api/team/<team_id>/import`.
+#[derive(FromForm, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ImportParams {
+    /// `csv` for a roster of members, `jsonl` for forecasts.
+    format: ImportFormat,
+    /// Only report the commands the import would issue.
+    dry_run: Option<bool>,
+}
+
+/// Body of `POST /api/team/<team_id>/import` responses: the plan of a dry
+/// run, or the report of an executed import.
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(untagged)]
+pub(crate) enum ImportResponse {
+    Plan(ImportPlan),
+    Report(ImportReport),
+}
+
+/// Imports members from a CSV roster (`email`, `member_id` and `location`
+/// columns; members have no location, so it is not imported) or forecasts
+/// from JSON lines (`{"member_id": ..., "forecast": {...}}`). Invalid lines
+/// are skipped and reported with the commands that failed. Commands are
+/// executed in batches, as by the batch endpoint. The response only comes
+/// once the whole file is imported; progress is reported by the
+/// `snowy-server import` command, not by this endpoint.
+#[utoipa::path(
+    post,
+    path = "/api/team/{team_id}/import",
+    tag = "teams",
+    params(
+        ("team_id" = String, Path, description = "Team identifier"),
+        ImportParams
+    ),
+    request_body(content = String, description = "The file to import, at most 10 MiB", content_type = "text/plain"),
+    responses(
+        (status = 200, description = "An ImportPlan of the commands a dry run would issue, or the ImportReport of an import, with an error for each line not imported", body = ImportResponse),
+        (status = 400, description = "The file is too large or not UTF-8", body = ErrorBody),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody)
+    ),
+    security(("api_key" = []), ("bearer" = []))
+)]
+#[post("/api/team/<team_id>/import?<params..>", data = "<file>")]
+pub async fn import_handler(
+    cqrs: &State<CqrsPlumbing>,
+    metrics: &State<Metrics>,
+    request: &RequestContext,
+    principal: Principal,
+    team_id: &str,
+    params: ImportParams,
+    file: Data<'_>,
+) -> Result<Json<ImportResponse>, Error> {
+    let file = file
+        .open(MAX_IMPORT_MIB.mebibytes())
+        .into_string()
+        .await
+        .map_err(|e| Error::BadRequest(format!("Could not read the file: {}", e)))?;
+    if !file.is_complete() {
+        return Err(Error::BadRequest(format!(
+            "The file exceeds {} MiB",
+            MAX_IMPORT_MIB
+        )));
+    }
+
+    let plan = import::plan(params.format, &file);
+    if params.dry_run.unwrap_or(false) {
+        return Ok(Json(ImportResponse::Plan(plan)));
+    }
+    let principal = &principal;
+    let report = import::execute(
+        plan,
+        |commands| execute_batch(cqrs, metrics, request, principal, team_id, commands),
+        |_, _| {},
+    )
+    .await;
+    Ok(Json(ImportResponse::Report(report)))
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::{
+        http::{Header, Status},
+        local::asynchronous::Client,
+        routes, tokio,
+    };
+    use serde_json::Value;
+
+    use super::*;
+    use crate::{
+        api::{
+            auth::api_key::{ApiKeyRepository, API_KEY_HEADER},
+            cqrs::setup_cqrs,
+            request_id::RequestId,
+        },
+        testing,
+    };
+
+    const ROSTER: &str = "email,member_id\n\
+                          alice@example.com,alice\n\
+                          bad,bob\n\
+                          carol@example.com,carol\n";
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL, see SNOWY_TEST_DATABASE_URL"]
+    async fn test_import_executes_the_plan_in_batches() {
+        let pool = testing::database().await;
+        let metrics = Metrics::new().unwrap();
+        let cqrs = setup_cqrs(pool.clone(), &metrics);
+        let keys = ApiKeyRepository::new(pool);
+        let issued = keys
+            .issue("team-1", "test", vec!["add-member".to_string()])
+            .await
+            .unwrap();
+        let rocket = rocket::build()
+            .attach(RequestId)
+            .mount("/", routes![import_handler])
+            .manage(cqrs.clone())
+            .manage(metrics)
+            .manage(keys);
+        let client = Client::tracked(rocket).await.unwrap();
+        let import = |query: &'static str| {
+            client
+                .post(format!("/api/team/team-1/import?{}", query))
+                .header(Header::new(API_KEY_HEADER, issued.secret.clone()))
+                .body(ROSTER)
+                .dispatch()
+        };
+
+        let response = import("format=csv&dry_run=true").await;
+        assert_eq!(response.status(), Status::Ok);
+        let plan: Value = response.into_json().await.unwrap();
+        assert_eq!(plan["commands"].as_array().unwrap().len(), 2);
+        assert!(cqrs.load_team("team-1").await.unwrap().members.is_empty());
+
+        let response = import("format=csv").await;
+        assert_eq!(response.status(), Status::Ok);
+        let report: Value = response.into_json().await.unwrap();
+        assert_eq!(report["executed"], 2);
+        assert_eq!(report["errors"][0]["line"], 3);
+        assert_eq!(cqrs.load_team("team-1").await.unwrap().members.len(), 2);
+
+        // The members exist now, so adding them again is rejected per line.
+        let report: Value = import("format=csv").await.into_json().await.unwrap();
+        assert_eq!(report["executed"], 0);
+        let lines: Vec<&Value> = report["errors"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|error| &error["line"])
+            .collect();
+        assert_eq!(lines, vec![2, 3, 4]);
+    }
+}
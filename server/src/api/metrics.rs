@@ -0,0 +1,206 @@
+use std::time::{Duration, Instant};
+
+use cqrs_es::AggregateError;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::{Data, Request, Response};
+use sqlx::{Pool, Postgres};
+
+use super::error::Error;
+
+/// Prometheus metrics exposed by the server at `/metrics`.
+#[derive(Clone)]
+pub(crate) struct Metrics {
+    registry: Registry,
+    pub(crate) commands_total: IntCounterVec,
+    pub(crate) command_duration_seconds: HistogramVec,
+    pub(crate) events_total: IntCounterVec,
+    pub(crate) db_pool_connections: IntGauge,
+    pub(crate) db_pool_idle_connections: IntGauge,
+    pub(crate) http_requests_total: IntCounterVec,
+    pub(crate) http_request_duration_seconds: HistogramVec,
+}
+
+impl Metrics {
+    pub(crate) fn new() -> Result<Self, prometheus::Error> {
+        let registry = Registry::new_custom(Some("snowy".to_string()), None)?;
+
+        let commands_total = IntCounterVec::new(
+            Opts::new("commands_total", "Team commands handled"),
+            &["command", "outcome"],
+        )?;
+        let command_duration_seconds = HistogramVec::new(
+            HistogramOpts::new(
+                "command_duration_seconds",
+                "Time spent executing team commands",
+            ),
+            &["command", "outcome"],
+        )?;
+        let events_total = IntCounterVec::new(
+            Opts::new("events_total", "Team events committed"),
+            &["event_type"],
+        )?;
+        let db_pool_connections =
+            IntGauge::new("db_pool_connections", "Open database connections")?;
+        let db_pool_idle_connections =
+            IntGauge::new("db_pool_idle_connections", "Idle database connections")?;
+        let http_requests_total = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests served"),
+            &["method", "route", "status"],
+        )?;
+        let http_request_duration_seconds = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Time spent serving HTTP requests",
+            ),
+            &["method", "route"],
+        )?;
+
+        registry.register(Box::new(commands_total.clone()))?;
+        registry.register(Box::new(command_duration_seconds.clone()))?;
+        registry.register(Box::new(events_total.clone()))?;
+        registry.register(Box::new(db_pool_connections.clone()))?;
+        registry.register(Box::new(db_pool_idle_connections.clone()))?;
+        registry.register(Box::new(http_requests_total.clone()))?;
+        registry.register(Box::new(http_request_duration_seconds.clone()))?;
+
+        Ok(Self {
+            registry,
+            commands_total,
+            command_duration_seconds,
+            events_total,
+            db_pool_connections,
+            db_pool_idle_connections,
+            http_requests_total,
+            http_request_duration_seconds,
+        })
+    }
+
+    pub(crate) fn observe_command(&self, command: &str, outcome: &str, elapsed: Duration) {
+        self.commands_total
+            .with_label_values(&[command, outcome])
+            .inc();
+        self.command_duration_seconds
+            .with_label_values(&[command, outcome])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub(crate) fn observe_db_pool(&self, pool: &Pool<Postgres>) {
+        self.db_pool_connections.set(pool.size() as i64);
+        self.db_pool_idle_connections.set(pool.num_idle() as i64);
+    }
+
+    /// Renders all registered metrics in the Prometheus text exposition format.
+    pub(crate) fn render(&self) -> Result<String, prometheus::Error> {
+        let mut buffer = vec![];
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8_lossy(&buffer).into_owned())
+    }
+}
+
+/// Label describing how a command execution ended.
+pub(crate) fn command_outcome(result: &Result<(), Error>) -> &'static str {
+    match result {
+        Ok(()) => "ok",
+        Err(e) => error_outcome(e),
+    }
+}
+
+pub(crate) fn error_outcome(error: &Error) -> &'static str {
+    match error {
+        Error::Aggregate(AggregateError::UserError(_)) => "rejected",
+        Error::Aggregate(AggregateError::AggregateConflict) => "conflict",
+        Error::PolicyDenied(_) => "denied",
+        _ => "error",
+    }
+}
+
+struct RequestStart(Option<Instant>);
+
+/// Fairing recording request counts and latencies for every HTTP request.
+pub(crate) struct RequestMetrics {
+    metrics: Metrics,
+}
+
+impl RequestMetrics {
+    pub(crate) fn new(metrics: Metrics) -> Self {
+        Self { metrics }
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for RequestMetrics {
+    fn info(&self) -> Info {
+        Info {
+            name: "Collect HTTP request metrics",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _data: &mut Data<'_>) {
+        request.local_cache(|| RequestStart(Some(Instant::now())));
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let method = request.method().as_str();
+        let route = request
+            .route()
+            .map(|route| route.uri.to_string())
+            .unwrap_or_else(|| "unmatched".to_string());
+        let status = response.status().code.to_string();
+
+        self.metrics
+            .http_requests_total
+            .with_label_values(&[method, &route, &status])
+            .inc();
+
+        if let RequestStart(Some(started)) = request.local_cache(|| RequestStart(None)) {
+            self.metrics
+                .http_request_duration_seconds
+                .with_label_values(&[method, &route])
+                .observe(started.elapsed().as_secs_f64());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::policies::Denial;
+
+    use super::*;
+
+    #[test]
+    fn test_render_command_metrics() {
+        let metrics = Metrics::new().unwrap();
+
+        metrics.observe_command("add-member", "ok", Duration::from_millis(5));
+        metrics.observe_command("add-member", "ok", Duration::from_millis(7));
+        metrics.observe_command("add-member", "rejected", Duration::from_millis(1));
+
+        let rendered = metrics.render().unwrap();
+
+        assert!(rendered.contains(r#"snowy_commands_total{command="add-member",outcome="ok"} 2"#));
+        assert!(
+            rendered.contains(r#"snowy_commands_total{command="add-member",outcome="rejected"} 1"#)
+        );
+        assert!(rendered.contains(
+            r#"snowy_command_duration_seconds_count{command="add-member",outcome="ok"} 2"#
+        ));
+    }
+
+    #[test]
+    fn test_command_outcome() {
+        let rejected = Err(Error::Aggregate(AggregateError::UserError(
+            crate::domain::error::Error::MemberAlreadyExists("test@example.com".to_string()),
+        )));
+        let conflict = Err(Error::Aggregate(AggregateError::AggregateConflict));
+        let denied = Err(Error::PolicyDenied(Denial("nope".to_string())));
+
+        assert_eq!(command_outcome(&Ok(())), "ok");
+        assert_eq!(command_outcome(&rejected), "rejected");
+        assert_eq!(command_outcome(&conflict), "conflict");
+        assert_eq!(command_outcome(&denied), "denied");
+    }
+}
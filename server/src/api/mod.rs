@@ -1,7 +1,47 @@
+mod admin;
+pub(crate) mod auth;
+mod batch;
 pub(crate) mod config;
 mod cors;
-mod cqrs;
-mod db;
-mod error;
+pub(crate) mod cqrs;
+pub(crate) mod db;
+pub(crate) mod error;
+mod graphql;
+#[cfg(feature = "grpc")]
+mod grpc;
 mod handlers;
+mod import;
+pub(crate) mod metrics;
+mod openapi;
+#[cfg(feature = "otel")]
+mod otel;
+mod request_id;
 pub(crate) mod server;
+mod stream;
+pub(crate) mod telemetry;
+mod webhooks;
+mod ws;
+
+use serde::Serialize;
+
+/// Name of a model enum variant, as serialized by the REST API.
+pub(crate) fn variant_name<T: Serialize>(value: &T) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|value| value.as_str().map(String::from))
+        .expect("unit variants serialize as strings")
+}
+
+#[cfg(test)]
+mod tests {
+    use snowy_model::{weather::WindDirection, WeatherCategory, WeatherCode};
+
+    use super::*;
+
+    #[test]
+    fn test_variant_names_match_the_rest_api() {
+        assert_eq!(variant_name(&WindDirection::NorthEast), "NorthEast");
+        assert_eq!(variant_name(&WeatherCode::ClearSky), "ClearSky");
+        assert_eq!(variant_name(&WeatherCategory::Rain), "Rain");
+    }
+}
@@ -0,0 +1,289 @@
+use rocket::{
+    get,
+    response::content::{RawHtml, RawJavaScript},
+    serde::json::Json,
+};
+use snowy_model::{
+    weather::{WindDirection, WindSpeed, WindSpeedUnit},
+    CelsiusTemperature, ForecastRecord, Member, MemberId, Membership, TeamId, TeamSummary,
+    TeamSummaryPage, TeamView, TemperatureExtreme, WeatherCategory, WeatherCode, WeatherForecast,
+};
+use utoipa::{
+    openapi::{
+        security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme},
+        OpenApi as OpenApiDocument,
+    },
+    Modify, OpenApi,
+};
+
+use crate::{
+    domain::{commands::TeamCommand, policies::Rule},
+    import::{ImportFormat, ImportPlan, ImportReport, PlannedCommand, RowError},
+    projections::{DeadLetter, DeadLetterStatus, ProjectionStatus},
+    queries::team_summary::{SortOrder, TeamSort},
+    webhooks::{
+        DeliveryAttempt, DeliveryStatus, IssuedWebhookSubscription, WebhookDelivery,
+        WebhookSubscription,
+    },
+};
+
+use super::{
+    admin,
+    auth::{admin::ADMIN_TOKEN_HEADER, api_key::API_KEY_HEADER},
+    batch::{self, BatchReport, CommandResult, CommandStatus},
+    error::{ErrorBody, Problem},
+    graphql,
+    handlers::{self, NewMember},
+    import::{self, ImportResponse},
+    stream,
+    webhooks::{self, NewWebhook},
+    ws,
+};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Snowy API",
+        description = "Track the weather forecasts of team members.\n\n\
+            Commands return once their events are stored. The views, and the read access \
+            checked against the members of a team view, follow once the projection runner \
+            applied them, usually within milliseconds: a read right after a command may not \
+            reflect it yet. The team stream announces every update."
+    ),
+    paths(
+        handlers::health,
+        handlers::metrics,
+        handlers::command_handler,
+        batch::batch_handler,
+        import::import_handler,
+        handlers::add_member,
+        handlers::track_member_forecast,
+        handlers::remove_member,
+        handlers::query_handler,
+        handlers::forecasts_handler,
+        stream::stream_handler,
+        ws::ws_handler,
+        graphql::graphql_handler,
+        graphql::graphql_ws_handler,
+        handlers::list_teams,
+        handlers::member_handler,
+        handlers::member_by_email_handler,
+        webhooks::create_webhook,
+        webhooks::list_webhooks,
+        webhooks::delete_webhook,
+        webhooks::deliveries_handler,
+        admin::list_projections,
+        admin::pause_projection,
+        admin::resume_projection,
+        admin::reset_projection,
+        admin::dead_letters_handler,
+        admin::replay_dead_letter
+    ),
+    components(schemas(
+        TeamCommand,
+        BatchReport,
+        CommandResult,
+        CommandStatus,
+        ImportFormat,
+        ImportPlan,
+        PlannedCommand,
+        ImportReport,
+        ImportResponse,
+        RowError,
+        Rule,
+        NewMember,
+        TeamView,
+        TeamSummary,
+        TeamSummaryPage,
+        TeamSort,
+        SortOrder,
+        TeamId,
+        Member,
+        MemberId,
+        Membership,
+        ForecastRecord,
+        WeatherForecast,
+        CelsiusTemperature,
+        WindSpeed,
+        WindSpeedUnit,
+        WindDirection,
+        WeatherCode,
+        WeatherCategory,
+        TemperatureExtreme,
+        NewWebhook,
+        WebhookSubscription,
+        IssuedWebhookSubscription,
+        WebhookDelivery,
+        DeliveryAttempt,
+        DeliveryStatus,
+        ProjectionStatus,
+        DeadLetter,
+        DeadLetterStatus,
+        ErrorBody,
+        Problem
+    )),
+    modifiers(&SecuritySchemes),
+    tags(
+        (name = "teams", description = "Team commands and views"),
+        (name = "members", description = "The teams each member belongs to"),
+        (name = "graphql", description = "Queries, commands and subscriptions over teams, as a GraphQL schema"),
+        (name = "webhooks", description = "Signed deliveries of team events to subscriber URLs"),
+        (name = "operations", description = "Health and metrics"),
+        (name = "admin", description = "Projection checkpoints and dead letters, for operators holding the admin token")
+    )
+)]
+pub(crate) struct ApiDoc;
+
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut OpenApiDocument) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new(API_KEY_HEADER))),
+        );
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .bearer_format("JWT")
+                    .build(),
+            ),
+        );
+        components.add_security_scheme(
+            "admin_token",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new(ADMIN_TOKEN_HEADER))),
+        );
+    }
+}
+
+#[get("/openapi.json")]
+pub fn openapi_json() -> Json<OpenApiDocument> {
+    Json(ApiDoc::openapi())
+}
+
+/// Renders the specification with Redoc.
+#[get("/docs")]
+pub fn docs() -> RawHtml<&'static str> {
+    RawHtml(include_str!("redoc.html"))
+}
+
+/// Redoc 2.0.0, served by the server so the documentation needs no CDN.
+#[get("/docs/redoc.standalone.js")]
+pub fn redoc_script() -> RawJavaScript<&'static str> {
+    RawJavaScript(include_str!("redoc.standalone.js"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use rocket::{
+        http::{ContentType, Method, Status},
+        local::blocking::Client,
+        routes,
+    };
+
+    use super::*;
+
+    /// Routes serving the documentation itself.
+    const UNDOCUMENTED: &[&str] = &[
+        "/openapi.json",
+        "/docs",
+        "/docs/redoc.standalone.js",
+        "/graphiql",
+    ];
+
+    fn openapi_path(uri: &str) -> String {
+        uri.replace('<', "{").replace('>', "}")
+    }
+
+    #[test]
+    fn test_spec_matches_routes() {
+        let routes: BTreeSet<(String, String)> = crate::api::server::routes()
+            .iter()
+            .filter(|route| route.method != Method::Options)
+            .map(|route| (route.method.to_string(), openapi_path(route.uri.path())))
+            .filter(|(_, path)| !UNDOCUMENTED.contains(&path.as_str()))
+            .collect();
+
+        let documented: BTreeSet<(String, String)> = ApiDoc::openapi()
+            .paths
+            .paths
+            .iter()
+            .flat_map(|(path, item)| {
+                item.operations.keys().map(|method| {
+                    let method = serde_json::to_value(method).unwrap();
+                    (method.as_str().unwrap().to_uppercase(), path.clone())
+                })
+            })
+            .collect();
+
+        assert_eq!(
+            routes, documented,
+            "the routes mounted by the server and the OpenAPI paths have drifted apart"
+        );
+    }
+
+    #[test]
+    fn test_spec_declares_schemas_and_security() {
+        let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let components = &spec["components"];
+
+        for schema in ["TeamCommand", "TeamView", "WeatherForecast", "Problem"] {
+            assert!(
+                components["schemas"][schema].is_object(),
+                "missing schema {}",
+                schema
+            );
+        }
+        assert_eq!(
+            components["securitySchemes"]["api_key"]["name"],
+            API_KEY_HEADER
+        );
+        assert_eq!(components["securitySchemes"]["bearer"]["scheme"], "bearer");
+        assert_eq!(
+            components["securitySchemes"]["admin_token"]["name"],
+            ADMIN_TOKEN_HEADER
+        );
+    }
+
+    #[test]
+    fn test_import_responds_with_a_plan_or_a_report() {
+        let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let response = &spec["paths"]["/api/team/{team_id}/import"]["post"]["responses"]["200"];
+        assert_eq!(
+            response["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/ImportResponse"
+        );
+
+        let variants: Vec<&str> = spec["components"]["schemas"]["ImportResponse"]["oneOf"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|variant| variant["$ref"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            variants,
+            vec![
+                "#/components/schemas/ImportPlan",
+                "#/components/schemas/ImportReport"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_docs_load_the_bundled_redoc() {
+        let client =
+            Client::tracked(rocket::build().mount("/", routes![docs, redoc_script])).unwrap();
+
+        let page = client.get("/docs").dispatch().into_string().unwrap();
+        assert!(page.contains(r#"<script src="/docs/redoc.standalone.js"></script>"#));
+
+        let response = client.get("/docs/redoc.standalone.js").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::JavaScript));
+    }
+}
@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+
+use opentelemetry::{
+    global,
+    trace::{TraceError, TracerProvider as _},
+    Context, KeyValue,
+};
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{
+    propagation::TraceContextPropagator,
+    runtime,
+    trace::{Tracer, TracerProvider},
+    Resource,
+};
+use rocket::http::HeaderMap;
+use tracing::Subscriber;
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::registry::LookupSpan;
+
+use super::config::Config;
+
+/// Builds a tracer provider exporting spans over OTLP/HTTP to `endpoint`.
+pub(crate) fn tracer_provider(
+    endpoint: &str,
+    service_name: &str,
+) -> Result<TracerProvider, TraceError> {
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint)
+        .build()?;
+
+    Ok(TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new([KeyValue::new(
+            "service.name",
+            service_name.to_string(),
+        )]))
+        .build())
+}
+
+/// Sets up W3C trace context propagation and the OTLP exporter, if configured.
+pub(crate) fn init_tracer_provider(config: &Config) -> Result<Option<TracerProvider>, TraceError> {
+    let Some(endpoint) = &config.otlp_endpoint else {
+        return Ok(None);
+    };
+
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    tracer_provider(endpoint, &config.otel_service_name).map(Some)
+}
+
+/// Tracing layer turning `tracing` spans into OpenTelemetry spans.
+pub(crate) fn layer<S>(provider: &TracerProvider) -> OpenTelemetryLayer<S, Tracer>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    tracing_opentelemetry::layer().with_tracer(provider.tracer("snowy-server"))
+}
+
+/// Extracts the W3C `traceparent`/`tracestate` context from request headers.
+pub(crate) fn extract_context(headers: &HeaderMap<'_>) -> Context {
+    // The extractor of a map looks names up in lowercase.
+    let headers: HashMap<String, String> = headers
+        .iter()
+        .map(|header| {
+            (
+                header.name().as_str().to_lowercase(),
+                header.value().to_string(),
+            )
+        })
+        .collect();
+    global::get_text_map_propagator(|propagator| propagator.extract(&headers))
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::trace::{Span, Tracer, TracerProvider as _};
+    use rocket::http::Header;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+        sync::oneshot,
+    };
+
+    use super::*;
+
+    /// Minimal OTLP/HTTP receiver answering a single export request.
+    async fn otlp_receiver_stub() -> (String, oneshot::Receiver<(String, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}/v1/traces", listener.local_addr().unwrap());
+        let (tx, rx) = oneshot::channel();
+
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buffer = vec![];
+            let mut chunk = [0u8; 4096];
+            let (head, body_len) = loop {
+                let n = socket.read(&mut chunk).await.unwrap();
+                buffer.extend_from_slice(&chunk[..n]);
+                if let Some(end) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+                    let head = String::from_utf8_lossy(&buffer[..end]).to_string();
+                    let content_length = head
+                        .lines()
+                        .find_map(|line| {
+                            let (name, value) = line.split_once(':')?;
+                            name.eq_ignore_ascii_case("content-length")
+                                .then(|| value.trim().parse::<usize>().ok())?
+                        })
+                        .unwrap_or(0);
+                    break (head, end + 4 + content_length);
+                }
+            };
+            while buffer.len() < body_len {
+                let n = socket.read(&mut chunk).await.unwrap();
+                buffer.extend_from_slice(&chunk[..n]);
+            }
+            socket
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                .await
+                .unwrap();
+
+            let request_line = head.lines().next().unwrap_or_default().to_string();
+            let content_type = head
+                .lines()
+                .find_map(|line| {
+                    let (name, value) = line.split_once(':')?;
+                    name.eq_ignore_ascii_case("content-type")
+                        .then(|| value.trim().to_string())
+                })
+                .unwrap_or_default();
+            let _ = tx.send((request_line, content_type));
+        });
+
+        (endpoint, rx)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_spans_are_exported_to_otlp_receiver() {
+        let (endpoint, received) = otlp_receiver_stub().await;
+        let provider = tracer_provider(&endpoint, "snowy-server-test").unwrap();
+
+        let mut span = provider.tracer("test").start("test-span");
+        span.end();
+
+        let flushed = provider.clone();
+        tokio::task::spawn_blocking(move || flushed.force_flush())
+            .await
+            .unwrap();
+
+        let (request_line, content_type) = received.await.unwrap();
+        assert_eq!(request_line, "POST /v1/traces HTTP/1.1");
+        assert_eq!(content_type, "application/x-protobuf");
+
+        tokio::task::spawn_blocking(move || provider.shutdown())
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_extract_traceparent() {
+        use opentelemetry::trace::TraceContextExt;
+
+        global::set_text_map_propagator(TraceContextPropagator::new());
+
+        let mut headers = HeaderMap::new();
+        headers.add(Header::new(
+            "traceparent",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        ));
+
+        let context = extract_context(&headers);
+        let span_context = context.span().span_context().clone();
+
+        assert!(span_context.is_remote());
+        assert_eq!(
+            span_context.trace_id().to_string(),
+            "4bf92f3577b34da6a3ce929d0e0e4736"
+        );
+    }
+}
@@ -0,0 +1,114 @@
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Header;
+use rocket::request::{FromRequest, Outcome};
+use rocket::{Data, Request, Response};
+use tracing::{info, info_span, Span};
+
+pub(crate) const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// Per-request tracing context, available to handlers as a request guard.
+#[derive(Clone)]
+pub(crate) struct RequestContext {
+    pub(crate) request_id: String,
+    pub(crate) span: Span,
+}
+
+impl RequestContext {
+    fn new(request: &Request<'_>) -> Self {
+        let request_id = request_id_from_header(request.headers().get_one(REQUEST_ID_HEADER));
+        let span = info_span!(
+            "request",
+            request_id = %request_id,
+            method = %request.method(),
+            uri = %request.uri()
+        );
+
+        #[cfg(feature = "otel")]
+        {
+            use tracing_opentelemetry::OpenTelemetrySpanExt;
+            span.set_parent(super::otel::extract_context(request.headers()));
+        }
+
+        Self { request_id, span }
+    }
+}
+
+/// Reuses a well-formed incoming request id, or generates a new one.
+pub(crate) fn request_id_from_header(header: Option<&str>) -> String {
+    header
+        .filter(|id| !id.is_empty() && id.len() <= 128)
+        .filter(|id| id.chars().all(|c| c.is_ascii_graphic()))
+        .map(String::from)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string())
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for &'r RequestContext {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(request.local_cache(|| RequestContext::new(request)))
+    }
+}
+
+/// Fairing that assigns every request an id and echoes it in the response.
+pub(crate) struct RequestId;
+
+#[rocket::async_trait]
+impl Fairing for RequestId {
+    fn info(&self) -> Info {
+        Info {
+            name: "Assign request ids and tracing spans",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _data: &mut Data<'_>) {
+        let context = RequestContext::new(request);
+        request.local_cache(|| context);
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let context = request.local_cache(|| RequestContext::new(request));
+        context.span.in_scope(|| {
+            info!(status = response.status().code, "request completed");
+        });
+        response.set_header(Header::new(REQUEST_ID_HEADER, context.request_id.clone()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::local::blocking::Client;
+    use rocket::routes;
+
+    use super::*;
+
+    #[test]
+    fn test_request_id_from_header() {
+        assert_eq!(request_id_from_header(Some("abc-123")), "abc-123");
+        assert_ne!(request_id_from_header(Some("")), "");
+        assert_ne!(request_id_from_header(Some("has space")), "has space");
+        assert_eq!(request_id_from_header(None).len(), 36);
+    }
+
+    #[test]
+    fn test_request_id_is_propagated() {
+        let rocket = rocket::build()
+            .attach(RequestId)
+            .mount("/", routes![crate::api::handlers::health]);
+        let client = Client::tracked(rocket).unwrap();
+
+        let response = client
+            .get("/health")
+            .header(Header::new(REQUEST_ID_HEADER, "abc-123"))
+            .dispatch();
+        assert_eq!(
+            response.headers().get_one(REQUEST_ID_HEADER),
+            Some("abc-123")
+        );
+
+        let response = client.get("/health").dispatch();
+        assert!(response.headers().get_one(REQUEST_ID_HEADER).is_some());
+    }
+}
@@ -1,39 +1,155 @@
-use rocket::{catchers, routes, Build};
+use std::sync::Arc;
+
+use rocket::{catchers, fairing::AdHoc, routes, Build, Route};
 use tracing::info;
 
-use crate::api::{cors::CORS, cqrs::setup_cqrs, db::get_db_pool};
+use crate::api::{
+    auth::{api_key::ApiKeyRepository, jwt::JwtValidator},
+    cors::Cors,
+    cqrs::{projections, setup_cqrs},
+    db::get_db_pool,
+    metrics::{Metrics, RequestMetrics},
+    request_id::RequestId,
+};
+use crate::{
+    projections::{ProjectionAdmin, ProjectionRunner},
+    webhooks::{WebhookDispatcher, WebhookRepository},
+};
 
 use super::{
     config::{get_figment, Config},
     error::Error,
+    graphql,
 };
 
+/// Every route served by the API, mounted at `/`.
+pub(crate) fn routes() -> Vec<Route> {
+    routes![
+        super::handlers::health,
+        super::handlers::preflight,
+        super::handlers::metrics,
+        super::handlers::command_handler,
+        super::batch::batch_handler,
+        super::import::import_handler,
+        super::handlers::add_member,
+        super::handlers::track_member_forecast,
+        super::handlers::remove_member,
+        super::handlers::query_handler,
+        super::handlers::forecasts_handler,
+        super::stream::stream_handler,
+        super::ws::ws_handler,
+        super::graphql::graphql_handler,
+        super::graphql::graphql_ws_handler,
+        super::graphql::graphiql,
+        super::handlers::list_teams,
+        super::handlers::member_handler,
+        super::handlers::member_by_email_handler,
+        super::webhooks::create_webhook,
+        super::webhooks::list_webhooks,
+        super::webhooks::delete_webhook,
+        super::webhooks::deliveries_handler,
+        super::admin::list_projections,
+        super::admin::pause_projection,
+        super::admin::resume_projection,
+        super::admin::reset_projection,
+        super::admin::dead_letters_handler,
+        super::admin::replay_dead_letter,
+        super::openapi::openapi_json,
+        super::openapi::docs,
+        super::openapi::redoc_script
+    ]
+}
+
 pub async fn server(config: Config) -> Result<rocket::Rocket<Build>, Error> {
     info!("initializing...");
 
+    let metrics = Metrics::new()?;
     let db_pool = get_db_pool(&config.database_url).await?;
-    let cqrs = setup_cqrs(db_pool).await;
+    let cqrs = setup_cqrs(db_pool.clone(), &metrics);
+    let jwt_validator = match &config.jwt {
+        Some(jwt_config) => Some(Arc::new(JwtValidator::load(jwt_config.clone()).await?)),
+        None => None,
+    };
+    let runner = config.projections.enabled.then(|| {
+        ProjectionRunner::new(
+            db_pool.clone(),
+            projections(config.publishing.as_ref()),
+            config.projections.clone(),
+        )
+    });
+    let graphql_schema = graphql::schema(cqrs.clone(), metrics.clone());
+    let projection_admin = ProjectionAdmin::new(
+        db_pool.clone(),
+        projections(config.publishing.as_ref()),
+        config.projections.clone(),
+    );
+    let notifier = cqrs.notifier.clone();
+    let notifier_pool = db_pool.clone();
+    let dispatcher = config
+        .webhooks
+        .enabled
+        .then(|| WebhookDispatcher::new(db_pool.clone(), config.webhooks.clone()));
+    let webhook_repository =
+        WebhookRepository::new(db_pool.clone(), config.webhooks.allow_private_addresses);
+    #[cfg(feature = "grpc")]
+    let grpc = config.grpc_address.map(|address| {
+        let teams = super::grpc::Teams::new(
+            cqrs.clone(),
+            metrics.clone(),
+            db_pool.clone(),
+            jwt_validator.clone(),
+        );
+        (address, teams)
+    });
 
     let server = rocket::custom(get_figment())
-        .attach(CORS)
+        .attach(RequestId)
+        .attach(Cors::new(config.cors.clone()))
+        .attach(RequestMetrics::new(metrics.clone()))
+        .attach(AdHoc::on_liftoff("Projections", |rocket| {
+            Box::pin(async move {
+                tokio::spawn(notifier.run(notifier_pool, rocket.shutdown()));
+                if let Some(runner) = runner {
+                    tokio::spawn(runner.run(rocket.shutdown()));
+                }
+            })
+        }))
+        .attach(AdHoc::on_liftoff("Webhook dispatcher", |rocket| {
+            Box::pin(async move {
+                if let Some(dispatcher) = dispatcher {
+                    tokio::spawn(dispatcher.run(rocket.shutdown()));
+                }
+            })
+        }))
         .register(
             "/",
             catchers![
+                super::handlers::unauthorized,
+                super::handlers::forbidden,
                 super::handlers::not_found,
                 super::handlers::internal_error,
                 super::handlers::bad_request
             ],
         )
-        .mount(
-            "/",
-            routes![
-                super::handlers::health,
-                super::handlers::command_handler,
-                super::handlers::query_handler
-            ],
-        )
+        .mount("/", routes())
         .manage(config)
-        .manage(cqrs);
+        .manage(cqrs)
+        .manage(metrics)
+        .manage(ApiKeyRepository::new(db_pool.clone()))
+        .manage(webhook_repository)
+        .manage(projection_admin)
+        .manage(graphql_schema)
+        .manage(jwt_validator)
+        .manage(db_pool);
+
+    #[cfg(feature = "grpc")]
+    let server = server.attach(AdHoc::on_liftoff("gRPC server", |rocket| {
+        Box::pin(async move {
+            if let Some((address, teams)) = grpc {
+                tokio::spawn(super::grpc::serve(address, teams, rocket.shutdown()));
+            }
+        })
+    }));
 
     info!("successfully initialized!");
 
@@ -0,0 +1,281 @@
+use std::{convert::Infallible, time::Duration};
+
+use cqrs_es::persist::ViewRepository;
+use rocket::{
+    get,
+    request::{FromRequest, Outcome},
+    response::stream::{Event, EventStream},
+    tokio::{
+        select,
+        sync::broadcast::error::RecvError,
+        time::{interval_at, Instant},
+    },
+    Request, Shutdown, State,
+};
+use sqlx::{Pool, Postgres};
+use tracing::{error, info};
+
+use crate::queries::notifier::latest_sequence;
+
+use super::{
+    auth::{api_key::ApiKeyRepository, Principal, Session},
+    cqrs::CqrsPlumbing,
+    error::Error,
+};
+
+/// Interval between keep-alive comments on idle streams.
+const HEARTBEAT: Duration = Duration::from_secs(15);
+
+/// The sequence of the last event a reconnecting `EventSource` received.
+pub struct LastEventId(Option<u64>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for LastEventId {
+    type Error = Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let id = request
+            .headers()
+            .get_one("Last-Event-ID")
+            .and_then(|id| id.trim().parse().ok());
+        Outcome::Success(LastEventId(id))
+    }
+}
+
+/// Streams the team view as a `team` event, identified by the sequence of the
+/// latest event it reflects, each time the team changes.
+///
+/// The credentials are checked again at each heartbeat: the stream ends once
+/// the API key was revoked or the bearer token expired.
+#[utoipa::path(
+    get,
+    path = "/api/team/{team_id}/stream",
+    tag = "teams",
+    params(
+        ("team_id" = String, Path, description = "Team identifier"),
+        ("Last-Event-ID" = Option<u64>, Header, description = "Resume after this event sequence")
+    ),
+    responses(
+        (status = 200, description = "Server-sent `team` events carrying the team view", content_type = "text/event-stream", body = String),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 403, description = "Not allowed to read the team", content_type = "application/problem+json", body = Problem)
+    ),
+    security(("api_key" = []), ("bearer" = []))
+)]
+#[get("/api/team/<team_id>/stream")]
+pub async fn stream_handler<'r>(
+    cqrs: &'r State<CqrsPlumbing>,
+    pool: &'r State<Pool<Postgres>>,
+    session: Session,
+    last_event_id: LastEventId,
+    team_id: &'r str,
+    mut shutdown: Shutdown,
+) -> Result<EventStream![Event + 'r], Error> {
+    let members = cqrs
+        .team_view_repository
+        .load(team_id)
+        .await?
+        .map(|view| view.members)
+        .unwrap_or_default();
+    session.principal.authorize_read(team_id, &members)?;
+
+    // Subscribe before reading the current state so no update is missed.
+    let mut updates = cqrs.notifier.subscribe();
+    let mut last_sent = last_event_id.0;
+    let api_keys = ApiKeyRepository::new(pool.inner().clone());
+    let mut reauthentication = interval_at(Instant::now() + HEARTBEAT, HEARTBEAT);
+
+    Ok(EventStream! {
+        let mut sequence = latest_sequence(pool, team_id).await.unwrap_or_else(|e| {
+            error!(error = ?e, team_id, "failed to read the latest team sequence");
+            None
+        });
+
+        loop {
+            if let Some(sequence) = sequence.filter(|s| last_sent.is_none_or(|sent| *s > sent)) {
+                match team_event(cqrs, &session.principal, team_id, sequence).await {
+                    Ok(Some(event)) => {
+                        last_sent = Some(sequence);
+                        yield event;
+                    }
+                    Ok(None) => {}
+                    // Members removed from the team stop receiving it.
+                    Err(_) => break,
+                }
+            }
+
+            let update = select! {
+                update = updates.recv() => update,
+                _ = reauthentication.tick() => {
+                    match session.reauthenticate(&api_keys).await {
+                        Ok(()) => continue,
+                        Err(e) => {
+                            info!(error = %e, team_id, "ending the stream of a client no longer authenticated");
+                            break;
+                        }
+                    }
+                }
+                _ = &mut shutdown => break,
+            };
+            sequence = match update {
+                Ok(update) if update.team_id == team_id => Some(update.sequence),
+                Ok(_) => None,
+                // Missed updates are folded into the latest state.
+                Err(RecvError::Lagged(_)) => latest_sequence(pool, team_id).await.unwrap_or(None),
+                Err(RecvError::Closed) => break,
+            };
+        }
+    }
+    .heartbeat(HEARTBEAT))
+}
+
+/// The team view as a `team` event, once `principal` is still allowed to read it.
+async fn team_event(
+    cqrs: &CqrsPlumbing,
+    principal: &Principal,
+    team_id: &str,
+    sequence: u64,
+) -> Result<Option<Event>, Error> {
+    match cqrs.team_view_repository.load(team_id).await {
+        Ok(Some(view)) => {
+            principal.authorize_read(team_id, &view.members)?;
+            Ok(Some(
+                Event::json(&view).event("team").id(sequence.to_string()),
+            ))
+        }
+        Ok(None) => Ok(None),
+        Err(e) => {
+            error!(error = ?e, team_id, "failed to load the team view");
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use rocket::{
+        http::{Header, Status},
+        local::asynchronous::{Client, LocalResponse},
+        routes,
+        tokio::{self, io::AsyncReadExt, time::timeout},
+    };
+    use snowy_model::MemberId;
+
+    use super::*;
+    use crate::{
+        api::{
+            auth::jwt::{self, JwtValidator},
+            cqrs::setup_cqrs,
+            metrics::Metrics,
+            request_id::RequestId,
+        },
+        domain::commands::TeamCommand,
+        queries::{
+            notifier::{notify, TeamUpdate},
+            team::TeamViewProjection,
+        },
+        testing,
+    };
+
+    /// The next event the stream sends, skipping comments, or `None` once
+    /// it ended.
+    async fn next_event(response: &mut LocalResponse<'_>) -> Option<String> {
+        let mut received = String::new();
+        let mut buffer = [0; 4096];
+        loop {
+            let read = timeout(Duration::from_secs(5), response.read(&mut buffer))
+                .await
+                .expect("the stream neither sent an event nor ended")
+                .unwrap();
+            if read == 0 {
+                return None;
+            }
+            received.push_str(&String::from_utf8_lossy(&buffer[..read]));
+            while let Some(end) = received.find("\n\n") {
+                let event: Vec<String> = received
+                    .drain(..end + 2)
+                    .collect::<String>()
+                    .lines()
+                    .filter(|line| !line.is_empty() && !line.starts_with(':'))
+                    .map(str::to_string)
+                    .collect();
+                if !event.is_empty() {
+                    return Some(event.join("\n"));
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL, see SNOWY_TEST_DATABASE_URL"]
+    async fn test_stream_ends_when_the_member_is_removed() {
+        let pool = testing::database().await;
+        let cqrs = setup_cqrs(pool.clone(), &Metrics::new().unwrap());
+        for member in ["alice", "bob"] {
+            let command = TeamCommand::AddMember {
+                member_id: MemberId::new(member.to_string()),
+                email: format!("{}@example.com", member),
+            };
+            cqrs.execute("team-1", command, HashMap::new())
+                .await
+                .unwrap();
+        }
+
+        let validator: Option<Arc<JwtValidator>> = Some(Arc::new(jwt::tests::validator()));
+        let rocket = rocket::build()
+            .attach(RequestId)
+            .mount("/", routes![stream_handler])
+            .manage(cqrs.clone())
+            .manage(pool.clone())
+            .manage(validator);
+        let client = Client::tracked(rocket).await.unwrap();
+        tokio::spawn(
+            cqrs.notifier
+                .clone()
+                .run(pool.clone(), client.rocket().shutdown()),
+        );
+        // Announce until the notifier listens, so no update is missed.
+        let mut probe = cqrs.notifier.subscribe();
+        let update = TeamUpdate {
+            team_id: "probe".to_string(),
+            sequence: 0,
+        };
+        loop {
+            notify(&mut pool.acquire().await.unwrap(), &update)
+                .await
+                .unwrap();
+            if timeout(Duration::from_millis(100), probe.recv())
+                .await
+                .is_ok()
+            {
+                break;
+            }
+        }
+        testing::project(&pool, &TeamViewProjection, "team-1").await;
+
+        let mut response = client
+            .get("/api/team/team-1/stream")
+            .header(Header::new(
+                "Authorization",
+                format!("Bearer {}", jwt::tests::token("bob@example.com")),
+            ))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let event = next_event(&mut response).await.unwrap();
+        assert!(event.contains("event:team"), "{}", event);
+        assert!(event.contains("bob@example.com"), "{}", event);
+
+        let command = TeamCommand::RemoveMember {
+            member_id: MemberId::new("bob".to_string()),
+        };
+        cqrs.execute("team-1", command, HashMap::new())
+            .await
+            .unwrap();
+        testing::project(&pool, &TeamViewProjection, "team-1").await;
+
+        assert_eq!(next_event(&mut response).await, None);
+    }
+}
@@ -0,0 +1,169 @@
+use async_trait::async_trait;
+use std::collections::HashMap;
+
+use cqrs_es::{Aggregate, AggregateError, EventEnvelope, EventStore, Query};
+use tracing::{info_span, Instrument};
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
+
+use crate::{domain::aggregates::Team, queries::team::TeamQueryDyn};
+
+use super::config::{Config, LogFormat};
+
+/// Keeps the trace exporter alive until it is shut down.
+pub(crate) struct Telemetry {
+    #[cfg(feature = "otel")]
+    tracer_provider: Option<opentelemetry_sdk::trace::TracerProvider>,
+}
+
+impl Telemetry {
+    /// Flushes pending spans and stops the trace exporter.
+    pub(crate) async fn shutdown(self) {
+        #[cfg(feature = "otel")]
+        if let Some(provider) = self.tracer_provider {
+            // Shutting down blocks until the batch exporter has flushed, which
+            // needs the runtime the exporter runs on.
+            match tokio::task::spawn_blocking(move || provider.shutdown()).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => {
+                    tracing::error!(error = ?e, "failed to shut down the tracer provider")
+                }
+                Err(e) => tracing::error!(error = ?e, "tracer provider shutdown panicked"),
+            }
+        }
+    }
+}
+
+/// Installs the global tracing subscriber described by `config`.
+pub(crate) fn init_tracing(config: &Config) -> Telemetry {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(&config.log_filter));
+
+    let fmt_layer = match config.log_format {
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .boxed(),
+        LogFormat::Pretty => tracing_subscriber::fmt::layer().pretty().boxed(),
+    };
+
+    let registry = tracing_subscriber::registry().with(filter).with(fmt_layer);
+
+    #[cfg(feature = "otel")]
+    let (registry, tracer_provider) = {
+        let tracer_provider =
+            super::otel::init_tracer_provider(config).expect("Failed to set up OTLP exporter");
+        let otel_layer = tracer_provider.as_ref().map(super::otel::layer);
+        (registry.with(otel_layer), tracer_provider)
+    };
+
+    registry.init();
+
+    Telemetry {
+        #[cfg(feature = "otel")]
+        tracer_provider,
+    }
+}
+
+/// Event store wrapper opening spans around aggregate loads and event commits.
+pub(crate) struct TracedEventStore<S> {
+    inner: S,
+}
+
+impl<S> TracedEventStore<S> {
+    pub(crate) fn new(inner: S) -> Self {
+        Self { inner }
+    }
+}
+
+#[async_trait]
+impl<A, S> EventStore<A> for TracedEventStore<S>
+where
+    A: Aggregate + 'static,
+    S: EventStore<A> + 'static,
+    S::AC: Send,
+{
+    type AC = S::AC;
+
+    async fn load_events(
+        &self,
+        aggregate_id: &str,
+    ) -> Result<Vec<EventEnvelope<A>>, AggregateError<A::Error>> {
+        self.inner
+            .load_events(aggregate_id)
+            .instrument(info_span!(
+                "aggregate.load_events",
+                db.system = "postgresql",
+                aggregate_type = %A::aggregate_type(),
+                aggregate_id
+            ))
+            .await
+    }
+
+    async fn load_aggregate(
+        &self,
+        aggregate_id: &str,
+    ) -> Result<Self::AC, AggregateError<A::Error>> {
+        self.inner
+            .load_aggregate(aggregate_id)
+            .instrument(info_span!(
+                "aggregate.load",
+                db.system = "postgresql",
+                aggregate_type = %A::aggregate_type(),
+                aggregate_id
+            ))
+            .await
+    }
+
+    async fn commit(
+        &self,
+        events: Vec<A::Event>,
+        context: Self::AC,
+        metadata: HashMap<String, String>,
+    ) -> Result<Vec<EventEnvelope<A>>, AggregateError<A::Error>> {
+        let span = info_span!(
+            "events.commit",
+            db.system = "postgresql",
+            aggregate_type = %A::aggregate_type(),
+            events = events.len()
+        );
+        self.inner
+            .commit(events, context, metadata)
+            .instrument(span)
+            .await
+    }
+}
+
+/// Query wrapper opening a span around each dispatch.
+pub(crate) struct TracedQuery {
+    name: &'static str,
+    inner: Box<TeamQueryDyn>,
+}
+
+impl TracedQuery {
+    pub(crate) fn new<Q>(name: &'static str, query: Q) -> Self
+    where
+        Q: Query<Team> + 'static,
+    {
+        Self {
+            name,
+            inner: Box::new(query),
+        }
+    }
+}
+
+#[async_trait]
+impl Query<Team> for TracedQuery {
+    async fn dispatch(&self, aggregate_id: &str, events: &[EventEnvelope<Team>]) {
+        let span = info_span!(
+            "query.dispatch",
+            query = self.name,
+            aggregate_id,
+            events = events.len()
+        );
+        self.inner
+            .dispatch(aggregate_id, events)
+            .instrument(span)
+            .await
+    }
+}
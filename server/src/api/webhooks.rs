@@ -0,0 +1,172 @@
+use rocket::{
+    delete, get,
+    http::Status,
+    post,
+    serde::json::{self, json, Value},
+    FromForm, State,
+};
+use serde::Deserialize;
+use tracing::{info_span, Instrument};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+use crate::webhooks::{DeliveryStatus, WebhookRepository};
+
+use super::{auth::Principal, cqrs::CqrsPlumbing, error::Error, request_id::RequestContext};
+
+const DEFAULT_DELIVERY_LIMIT: i64 = 50;
+const MAX_DELIVERY_LIMIT: i64 = 500;
+
+/// Body of `POST /api/teams/<team_id>/webhooks`.
+#[derive(Deserialize, ToSchema)]
+pub struct NewWebhook {
+    url: String,
+    /// Event types to deliver, such as `member-added`; every type when empty.
+    #[serde(default)]
+    event_types: Vec<String>,
+}
+
+/// Query string of `GET /api/teams/<team_id>/webhooks/<webhook_id>/deliveries`.
+#[derive(FromForm, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DeliveryListParams {
+    /// Only deliveries in this state.
+    status: Option<DeliveryStatus>,
+    /// Maximum number of deliveries to return, at most 500.
+    limit: Option<i64>,
+}
+
+fn parse_webhook_id(webhook_id: &str) -> Result<Uuid, Error> {
+    Uuid::parse_str(webhook_id)
+        .map_err(|_| Error::BadRequest(format!("Invalid webhook id '{}'", webhook_id)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/teams/{team_id}/webhooks",
+    tag = "webhooks",
+    params(
+        ("team_id" = String, Path, description = "Team identifier")
+    ),
+    request_body = NewWebhook,
+    responses(
+        (status = 200, description = "The webhook, with the secret its deliveries are signed with", body = IssuedWebhookSubscription),
+        (status = 400, description = "Invalid or non-public URL, or unknown event type", body = ErrorBody),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 403, description = "Not allowed to manage webhooks", content_type = "application/problem+json", body = Problem)
+    ),
+    security(("api_key" = []), ("bearer" = []))
+)]
+#[post("/api/teams/<team_id>/webhooks", data = "<payload>")]
+pub async fn create_webhook(
+    cqrs: &State<CqrsPlumbing>,
+    webhooks: &State<WebhookRepository>,
+    principal: Principal,
+    team_id: &str,
+    payload: json::Json<NewWebhook>,
+) -> Result<Value, Error> {
+    principal.authorize_webhooks(cqrs, team_id).await?;
+    let NewWebhook { url, event_types } = payload.0;
+    let issued = webhooks.subscribe(team_id, &url, event_types).await?;
+    Ok(json!(issued))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/teams/{team_id}/webhooks",
+    tag = "webhooks",
+    params(
+        ("team_id" = String, Path, description = "Team identifier")
+    ),
+    responses(
+        (status = 200, description = "The team's webhooks, including disabled ones", body = [WebhookSubscription]),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 403, description = "Not allowed to manage webhooks", content_type = "application/problem+json", body = Problem)
+    ),
+    security(("api_key" = []), ("bearer" = []))
+)]
+#[get("/api/teams/<team_id>/webhooks")]
+pub async fn list_webhooks(
+    cqrs: &State<CqrsPlumbing>,
+    webhooks: &State<WebhookRepository>,
+    principal: Principal,
+    team_id: &str,
+) -> Result<Value, Error> {
+    principal.authorize_webhooks(cqrs, team_id).await?;
+    Ok(json!(webhooks.subscriptions(team_id).await?))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/teams/{team_id}/webhooks/{webhook_id}",
+    tag = "webhooks",
+    params(
+        ("team_id" = String, Path, description = "Team identifier"),
+        ("webhook_id" = Uuid, Path, description = "Webhook identifier")
+    ),
+    responses(
+        (status = 204, description = "The webhook was disabled; pending deliveries are still attempted"),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 403, description = "Not allowed to manage webhooks", content_type = "application/problem+json", body = Problem),
+        (status = 404, description = "No active webhook with this identifier", body = ErrorBody)
+    ),
+    security(("api_key" = []), ("bearer" = []))
+)]
+#[delete("/api/teams/<team_id>/webhooks/<webhook_id>")]
+pub async fn delete_webhook(
+    cqrs: &State<CqrsPlumbing>,
+    webhooks: &State<WebhookRepository>,
+    principal: Principal,
+    team_id: &str,
+    webhook_id: &str,
+) -> Result<Option<Status>, Error> {
+    principal.authorize_webhooks(cqrs, team_id).await?;
+    let disabled = webhooks
+        .disable(team_id, parse_webhook_id(webhook_id)?)
+        .await?;
+    Ok(disabled.then_some(Status::NoContent))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/teams/{team_id}/webhooks/{webhook_id}/deliveries",
+    tag = "webhooks",
+    params(
+        ("team_id" = String, Path, description = "Team identifier"),
+        ("webhook_id" = Uuid, Path, description = "Webhook identifier"),
+        DeliveryListParams
+    ),
+    responses(
+        (status = 200, description = "The latest deliveries, newest first, with every attempt", body = [WebhookDelivery]),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody),
+        (status = 403, description = "Not allowed to manage webhooks", content_type = "application/problem+json", body = Problem),
+        (status = 404, description = "No webhook with this identifier", body = ErrorBody)
+    ),
+    security(("api_key" = []), ("bearer" = []))
+)]
+#[get("/api/teams/<team_id>/webhooks/<webhook_id>/deliveries?<params..>")]
+pub async fn deliveries_handler(
+    cqrs: &State<CqrsPlumbing>,
+    webhooks: &State<WebhookRepository>,
+    request: &RequestContext,
+    principal: Principal,
+    team_id: &str,
+    webhook_id: &str,
+    params: DeliveryListParams,
+) -> Result<Option<Value>, Error> {
+    principal.authorize_webhooks(cqrs, team_id).await?;
+    let span = info_span!(parent: &request.span, "query", query = "webhook_deliveries");
+    let deliveries = webhooks
+        .deliveries(
+            team_id,
+            parse_webhook_id(webhook_id)?,
+            params.status,
+            params
+                .limit
+                .unwrap_or(DEFAULT_DELIVERY_LIMIT)
+                .clamp(1, MAX_DELIVERY_LIMIT),
+        )
+        .instrument(span)
+        .await?;
+    Ok(deliveries.map(|deliveries| json!(deliveries)))
+}
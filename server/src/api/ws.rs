@@ -0,0 +1,364 @@
+use std::collections::BTreeSet;
+
+use cqrs_es::persist::ViewRepository;
+use rocket::{
+    futures::{SinkExt, StreamExt},
+    get,
+    tokio::{select, sync::broadcast::error::RecvError},
+    State,
+};
+use rocket_ws::{
+    frame::{CloseCode, CloseFrame},
+    stream::DuplexStream,
+    Channel, Message, WebSocket,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use tracing::{error, info_span};
+
+use snowy_model::TeamView;
+
+use crate::{domain::commands::TeamCommand, queries::notifier::latest_sequence};
+
+use super::{
+    auth::{api_key::ApiKeyRepository, Session},
+    cqrs::CqrsPlumbing,
+    error::Error,
+    handlers::execute_command,
+    metrics::Metrics,
+    request_id::RequestContext,
+};
+
+/// Messages sent by WebSocket clients, as JSON text frames.
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    Subscribe {
+        team_id: String,
+    },
+    Unsubscribe {
+        team_id: String,
+    },
+    Command {
+        correlation_id: String,
+        team_id: String,
+        command: TeamCommand,
+    },
+}
+
+/// Messages sent to WebSocket clients, as JSON text frames.
+#[derive(Serialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerMessage {
+    Subscribed {
+        team_id: String,
+    },
+    Unsubscribed {
+        team_id: String,
+    },
+    /// The team view after the event with this sequence.
+    Team {
+        team_id: String,
+        sequence: Option<u64>,
+        view: Box<TeamView>,
+    },
+    /// The command with this correlation id was executed.
+    Ack {
+        correlation_id: String,
+    },
+    Error {
+        correlation_id: Option<String>,
+        status: u16,
+        message: String,
+    },
+}
+
+impl ServerMessage {
+    fn error(correlation_id: Option<String>, error: &Error) -> Self {
+        ServerMessage::Error {
+            correlation_id,
+            status: error.status().code,
+            message: error.message(),
+        }
+    }
+}
+
+impl From<ServerMessage> for Message {
+    fn from(message: ServerMessage) -> Self {
+        Message::Text(serde_json::to_string(&message).expect("server messages serialize"))
+    }
+}
+
+/// Opens a WebSocket where clients subscribe to team views and submit
+/// commands, correlating each acknowledgement or error with its command.
+///
+/// The credentials are checked again before each message is handled and each
+/// update is sent: the socket is closed with a policy violation once the API
+/// key was revoked or the bearer token expired.
+#[utoipa::path(
+    get,
+    path = "/api/ws",
+    tag = "teams",
+    responses(
+        (status = 101, description = "Switched to the WebSocket protocol"),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorBody)
+    ),
+    security(("api_key" = []), ("bearer" = []))
+)]
+#[get("/api/ws")]
+pub fn ws_handler(
+    ws: WebSocket,
+    cqrs: &State<CqrsPlumbing>,
+    metrics: &State<Metrics>,
+    pool: &State<Pool<Postgres>>,
+    request: &RequestContext,
+    session: Session,
+) -> Channel<'static> {
+    let connection = Connection {
+        cqrs: cqrs.inner().clone(),
+        metrics: metrics.inner().clone(),
+        pool: pool.inner().clone(),
+        api_keys: ApiKeyRepository::new(pool.inner().clone()),
+        request: request.clone(),
+        session,
+        subscriptions: BTreeSet::new(),
+    };
+    ws.channel(move |stream| Box::pin(connection.run(stream)))
+}
+
+struct Connection {
+    cqrs: CqrsPlumbing,
+    metrics: Metrics,
+    pool: Pool<Postgres>,
+    api_keys: ApiKeyRepository,
+    request: RequestContext,
+    session: Session,
+    subscriptions: BTreeSet<String>,
+}
+
+impl Connection {
+    async fn run(mut self, mut stream: DuplexStream) -> rocket_ws::result::Result<()> {
+        let mut updates = self.cqrs.notifier.subscribe();
+
+        loop {
+            select! {
+                message = stream.next() => {
+                    let text = match message {
+                        Some(Ok(Message::Text(text))) => text,
+                        Some(Ok(Message::Close(_))) | None => break,
+                        Some(Ok(_)) => continue,
+                        Some(Err(e)) => return Err(e),
+                    };
+                    if let Err(e) = self.session.reauthenticate(&self.api_keys).await {
+                        return close(&mut stream, &e).await;
+                    }
+                    for reply in self.handle(&text).await {
+                        stream.send(reply.into()).await?;
+                    }
+                }
+                update = updates.recv() => {
+                    let stale = match update {
+                        Ok(update) if self.subscriptions.contains(&update.team_id) => {
+                            vec![(update.team_id, Some(update.sequence))]
+                        }
+                        Ok(_) => continue,
+                        // Missed updates are folded into the latest state.
+                        Err(RecvError::Lagged(_)) => {
+                            self.subscriptions.iter().map(|id| (id.clone(), None)).collect()
+                        }
+                        Err(RecvError::Closed) => break,
+                    };
+                    if let Err(e) = self.session.reauthenticate(&self.api_keys).await {
+                        return close(&mut stream, &e).await;
+                    }
+                    for (team_id, sequence) in stale {
+                        for reply in self.team(&team_id, sequence).await {
+                            stream.send(reply.into()).await?;
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle(&mut self, text: &str) -> Vec<ServerMessage> {
+        let message = match serde_json::from_str::<ClientMessage>(text) {
+            Ok(message) => message,
+            Err(e) => {
+                let error = Error::BadRequest(format!("Invalid message: {}", e));
+                return vec![ServerMessage::error(None, &error)];
+            }
+        };
+
+        match message {
+            ClientMessage::Subscribe { team_id } => {
+                if let Err(e) = self.authorize_read(&team_id).await {
+                    return vec![ServerMessage::error(None, &e)];
+                }
+                self.subscriptions.insert(team_id.clone());
+                let current = self.team(&team_id, None).await;
+                std::iter::once(ServerMessage::Subscribed { team_id })
+                    .chain(current)
+                    .collect()
+            }
+            ClientMessage::Unsubscribe { team_id } => {
+                self.subscriptions.remove(&team_id);
+                vec![ServerMessage::Unsubscribed { team_id }]
+            }
+            ClientMessage::Command {
+                correlation_id,
+                team_id,
+                command,
+            } => {
+                let request = RequestContext {
+                    request_id: format!("{}:{}", self.request.request_id, correlation_id),
+                    span: info_span!(parent: &self.request.span, "ws.command", correlation_id),
+                };
+                let result = execute_command(
+                    &self.cqrs,
+                    &self.metrics,
+                    &request,
+                    &self.session.principal,
+                    &team_id,
+                    command,
+                )
+                .await;
+                match result {
+                    Ok(_) => vec![ServerMessage::Ack { correlation_id }],
+                    Err(e) => vec![ServerMessage::error(Some(correlation_id), &e)],
+                }
+            }
+        }
+    }
+
+    async fn authorize_read(&self, team_id: &str) -> Result<(), Error> {
+        let members = self
+            .cqrs
+            .team_view_repository
+            .load(team_id)
+            .await?
+            .map(|view| view.members)
+            .unwrap_or_default();
+        self.session.principal.authorize_read(team_id, &members)
+    }
+
+    /// The current view of a subscribed team. Members removed from the team
+    /// are told why and unsubscribed instead.
+    async fn team(&mut self, team_id: &str, sequence: Option<u64>) -> Vec<ServerMessage> {
+        let sequence = match sequence {
+            Some(sequence) => Some(sequence),
+            None => latest_sequence(&self.pool, team_id)
+                .await
+                .unwrap_or_else(|e| {
+                    error!(error = ?e, team_id, "failed to read the latest team sequence");
+                    None
+                }),
+        };
+        match self.cqrs.team_view_repository.load(team_id).await {
+            Ok(Some(view)) => match self
+                .session
+                .principal
+                .authorize_read(team_id, &view.members)
+            {
+                Ok(()) => vec![ServerMessage::Team {
+                    team_id: team_id.to_string(),
+                    sequence,
+                    view: Box::new(view),
+                }],
+                Err(e) => {
+                    self.subscriptions.remove(team_id);
+                    vec![
+                        ServerMessage::error(None, &e),
+                        ServerMessage::Unsubscribed {
+                            team_id: team_id.to_string(),
+                        },
+                    ]
+                }
+            },
+            Ok(None) => vec![],
+            Err(e) => {
+                error!(error = ?e, team_id, "failed to load the team view");
+                vec![]
+            }
+        }
+    }
+}
+
+/// Closes the socket after failing to authenticate the client again: as a
+/// policy violation when its credentials are no longer valid.
+async fn close(stream: &mut DuplexStream, error: &Error) -> rocket_ws::result::Result<()> {
+    let (code, reason) = match error {
+        Error::Unauthorized(_) => (CloseCode::Policy, error.message()),
+        e => {
+            error!(error = ?e, "failed to authenticate the WebSocket client again");
+            (CloseCode::Error, "Internal server error".to_string())
+        }
+    };
+    stream
+        .send(Message::Close(Some(CloseFrame {
+            code,
+            reason: reason.into(),
+        })))
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use cqrs_es::AggregateError;
+    use serde_json::json;
+    use snowy_model::MemberId;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_client_messages() {
+        assert_eq!(
+            serde_json::from_value::<ClientMessage>(
+                json!({"type": "subscribe", "team_id": "team-1"})
+            )
+            .unwrap(),
+            ClientMessage::Subscribe {
+                team_id: "team-1".to_string()
+            }
+        );
+
+        let message = serde_json::from_value::<ClientMessage>(json!({
+            "type": "command",
+            "correlation_id": "42",
+            "team_id": "team-1",
+            "command": {"RemoveMember": {"member_id": "member-1"}}
+        }))
+        .unwrap();
+        let ClientMessage::Command {
+            correlation_id,
+            command: TeamCommand::RemoveMember { member_id },
+            ..
+        } = message
+        else {
+            panic!("unexpected message {:?}", message);
+        };
+        assert_eq!(correlation_id, "42");
+        assert_eq!(member_id, MemberId::new("member-1".to_string()));
+    }
+
+    #[test]
+    fn test_error_replies_carry_correlation_id_and_status() {
+        let error = Error::Aggregate(AggregateError::UserError(
+            crate::domain::error::Error::UnknownCommandType("launch-rockets".to_string()),
+        ));
+        let reply =
+            serde_json::to_value(ServerMessage::error(Some("42".to_string()), &error)).unwrap();
+
+        assert_eq!(
+            reply,
+            json!({
+                "type": "error",
+                "correlation_id": "42",
+                "status": 400,
+                "message": "Unknown command type 'launch-rockets'"
+            })
+        );
+    }
+}
@@ -0,0 +1,317 @@
+use std::{collections::HashMap, fs::File, path::PathBuf};
+
+use clap::{Parser, Subcommand};
+use uuid::Uuid;
+
+use crate::{
+    api::{
+        auth::api_key::{all_scopes, ApiKeyRepository},
+        config::Config,
+        cqrs::{projections, setup_cqrs},
+        db::get_db_pool,
+        error::Error,
+        metrics::Metrics,
+    },
+    import::{self, ImportFormat},
+    projections::{DeadLetter, ProjectionAdmin, ProjectionStatus},
+};
+
+#[derive(Parser)]
+#[command(name = "snowy-server", about = "Snowy CQRS API server")]
+pub(crate) struct Cli {
+    #[command(subcommand)]
+    pub(crate) command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub(crate) enum Command {
+    /// Run the HTTP API (default)
+    Serve,
+    /// Manage API keys
+    #[command(subcommand)]
+    ApiKey(ApiKeyCommand),
+    /// Inspect and control the asynchronous projections
+    #[command(subcommand)]
+    Projections(ProjectionCommand),
+    /// Import members from a CSV roster or forecasts from JSON lines
+    ///
+    /// The import runs as the operator: its commands carry no actor, so the
+    /// team's policies are not checked.
+    Import(ImportCommand),
+}
+
+#[derive(Subcommand)]
+pub(crate) enum ApiKeyCommand {
+    /// Issue a new API key for a team
+    Issue {
+        #[arg(long)]
+        team: String,
+        #[arg(long)]
+        name: String,
+        /// Scope to grant (`read` or a command type); may be repeated.
+        /// Defaults to every scope.
+        #[arg(long = "scope", value_name = "SCOPE")]
+        scopes: Vec<String>,
+    },
+    /// Revoke an API key
+    Revoke { id: Uuid },
+    /// List the API keys of a team
+    List {
+        #[arg(long)]
+        team: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub(crate) enum ProjectionCommand {
+    /// List every projection with its checkpoint, lag and error state
+    List,
+    /// Stop a projection after its current batch
+    Pause { name: String },
+    /// Resume a paused projection
+    Resume { name: String },
+    /// Clear a projection's view and rebuild it from the first event; the
+    /// team view, which read access is checked against, cannot be reset
+    Reset { name: String },
+    /// List the latest dead letters of a projection
+    DeadLetters {
+        name: String,
+        #[arg(long, default_value_t = 50)]
+        limit: i64,
+    },
+    /// Replay a dead letter now
+    Replay { name: String, id: i64 },
+}
+
+#[derive(clap::Args)]
+pub(crate) struct ImportCommand {
+    #[arg(long)]
+    team: String,
+    /// CSV with `email`, `member_id` and `location` columns, or JSON lines
+    /// of `{"member_id": ..., "forecast": {...}}`
+    file: PathBuf,
+    /// Defaults to `jsonl` for `.jsonl` files and `csv` otherwise
+    #[arg(long, value_enum)]
+    format: Option<ImportFormat>,
+    /// Print the commands the import would issue without executing them
+    #[arg(long)]
+    dry_run: bool,
+    /// Where to write the lines that were not imported, as CSV. Defaults to
+    /// the file with an `.errors.csv` extension.
+    #[arg(long)]
+    errors: Option<PathBuf>,
+}
+
+pub(crate) async fn run_api_key_command(
+    command: ApiKeyCommand,
+    config: &Config,
+) -> Result<(), Error> {
+    let repository = ApiKeyRepository::new(get_db_pool(&config.database_url).await?);
+
+    match command {
+        ApiKeyCommand::Issue { team, name, scopes } => {
+            let scopes = if scopes.is_empty() {
+                all_scopes()
+            } else {
+                scopes
+            };
+            let issued = repository.issue(&team, &name, scopes).await?;
+            println!("Issued API key {} for team '{}'", issued.key.id, team);
+            println!("Scopes: {}", issued.key.scopes.join(", "));
+            println!("Secret (shown only once): {}", issued.secret);
+        }
+        ApiKeyCommand::Revoke { id } => {
+            if repository.revoke(id).await? {
+                println!("Revoked API key {}", id);
+            } else {
+                return Err(Error::BadRequest(format!(
+                    "API key {} does not exist or is already revoked",
+                    id
+                )));
+            }
+        }
+        ApiKeyCommand::List { team } => {
+            for key in repository.list(&team).await? {
+                let status = match key.revoked_at {
+                    Some(revoked_at) => format!("revoked {}", revoked_at),
+                    None => "active".to_string(),
+                };
+                println!(
+                    "{}\t{}\t{}\tcreated {}\t{}",
+                    key.id,
+                    key.name,
+                    key.scopes.join(","),
+                    key.created_at,
+                    status
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn print_projection(status: &ProjectionStatus) {
+    let mut state = if status.paused { "paused" } else { "running" }.to_string();
+    if let (Some(error), Some(errored_at)) = (&status.error, status.errored_at) {
+        state = format!("{}, failing since {}: {}", state, errored_at, error);
+    }
+    println!(
+        "{}\t{}\t{} events\t{:.1}s\t{} dead letters\t{}",
+        status.projection,
+        status.global_position,
+        status.lag_events,
+        status.lag_seconds.unwrap_or(0.0),
+        status.dead_letters,
+        state
+    );
+}
+
+fn print_dead_letter(letter: &DeadLetter) {
+    println!(
+        "{}\t{}#{}\t{:?}\t{} attempts\t{}",
+        letter.id,
+        letter.aggregate_id,
+        letter.sequence,
+        letter.status,
+        letter.attempts,
+        letter.error
+    );
+}
+
+pub(crate) async fn run_projection_command(
+    command: ProjectionCommand,
+    config: &Config,
+) -> Result<(), Error> {
+    let admin = ProjectionAdmin::new(
+        get_db_pool(&config.database_url).await?,
+        projections(config.publishing.as_ref()),
+        config.projections.clone(),
+    );
+
+    let unknown = |name: &str| Error::BadRequest(format!("Projection '{}' does not exist", name));
+    let (name, status) = match command {
+        ProjectionCommand::List => {
+            for status in admin.list().await? {
+                print_projection(&status);
+            }
+            return Ok(());
+        }
+        ProjectionCommand::DeadLetters { name, limit } => {
+            let letters = admin
+                .dead_letters(&name, None, limit)
+                .await?
+                .ok_or_else(|| unknown(&name))?;
+            for letter in &letters {
+                print_dead_letter(letter);
+            }
+            return Ok(());
+        }
+        ProjectionCommand::Replay { name, id } => {
+            let letter = admin.replay(&name, id).await?.ok_or_else(|| {
+                Error::BadRequest(format!(
+                    "No dead letter {} left to replay in '{}'",
+                    id, name
+                ))
+            })?;
+            print_dead_letter(&letter);
+            return Ok(());
+        }
+        ProjectionCommand::Pause { name } => {
+            let status = admin.set_paused(&name, true).await?;
+            (name, status)
+        }
+        ProjectionCommand::Resume { name } => {
+            let status = admin.set_paused(&name, false).await?;
+            (name, status)
+        }
+        ProjectionCommand::Reset { name } => {
+            let status = admin.reset(&name).await?;
+            (name, status)
+        }
+    };
+    let status = status.ok_or_else(|| unknown(&name))?;
+    print_projection(&status);
+    Ok(())
+}
+
+/// Imports a file as the operator: commands carry no actor, so team
+/// policies do not apply.
+pub(crate) async fn run_import_command(
+    command: ImportCommand,
+    config: &Config,
+) -> Result<(), Error> {
+    let input = std::fs::read_to_string(&command.file).map_err(|e| {
+        Error::BadRequest(format!("Could not read {}: {}", command.file.display(), e))
+    })?;
+    let format = command.format.unwrap_or_else(|| {
+        match command
+            .file
+            .extension()
+            .and_then(|extension| extension.to_str())
+        {
+            Some("jsonl") => ImportFormat::JsonLines,
+            _ => ImportFormat::Csv,
+        }
+    });
+
+    let plan = import::plan(format, &input);
+    if command.dry_run {
+        for planned in &plan.commands {
+            println!(
+                "{}\t{}",
+                planned.line,
+                serde_json::to_string(&planned.command).expect("commands serialize")
+            );
+        }
+        for error in &plan.errors {
+            println!("{}\terror: {}", error.line, error.error);
+        }
+        println!(
+            "{} rows: {} commands, {} invalid",
+            plan.rows,
+            plan.commands.len(),
+            plan.errors.len()
+        );
+        return Ok(());
+    }
+
+    let metrics = Metrics::new()?;
+    let pool = get_db_pool(&config.database_url).await?;
+    let cqrs = setup_cqrs(pool, &metrics);
+    let request_id = Uuid::new_v4().to_string();
+    let team = &command.team;
+    let report = import::execute(
+        plan,
+        |commands| {
+            let metadata = HashMap::from([("request_id".to_string(), request_id.clone())]);
+            let cqrs = &cqrs;
+            async move {
+                cqrs.execute_batch(team, commands, |_| Ok(None), metadata)
+                    .await
+            }
+        },
+        |done, total| eprint!("\rImported {}/{}", done, total),
+    )
+    .await;
+    eprintln!();
+    println!(
+        "{} rows: {} imported, {} not imported",
+        report.rows,
+        report.executed,
+        report.errors.len()
+    );
+
+    if !report.errors.is_empty() {
+        let path = command
+            .errors
+            .unwrap_or_else(|| command.file.with_extension("errors.csv"));
+        let file = File::create(&path).map_err(|e| {
+            Error::BadRequest(format!("Could not create {}: {}", path.display(), e))
+        })?;
+        import::write_errors(&report.errors, file)
+            .map_err(|e| Error::BadRequest(format!("Could not write {}: {}", path.display(), e)))?;
+        println!("Wrote the lines not imported to {}", path.display());
+    }
+    Ok(())
+}
@@ -1,4 +1,7 @@
-use std::{collections::HashMap, vec};
+use std::{
+    collections::{BTreeMap, HashMap},
+    vec,
+};
 
 use async_trait::async_trait;
 use cqrs_es::Aggregate;
@@ -6,18 +9,27 @@ use serde::{Deserialize, Serialize};
 
 use snowy_model::{Member, MemberId, Team as TeamModel, WeatherForecast};
 
-use super::{commands::TeamCommand, error::Error, events::TeamEvent, services::TeamServices};
+use super::{
+    commands::{IssuedCommand, TeamCommand},
+    error::Error,
+    events::TeamEvent,
+    policies::Rule,
+    services::TeamServices,
+};
 
-#[derive(Serialize, Debug, Default, Deserialize)]
+#[derive(Serialize, Debug, Clone, Default, Deserialize)]
 pub(crate) struct Team {
     pub(crate) team: TeamModel,
     pub(crate) members: Vec<Member>,
     pub(crate) forecasts: HashMap<MemberId, WeatherForecast>,
+    /// Team-specific policy rules by command type, overriding the defaults.
+    #[serde(default)]
+    pub(crate) policies: BTreeMap<String, Rule>,
 }
 
 #[async_trait]
 impl Aggregate for Team {
-    type Command = TeamCommand;
+    type Command = IssuedCommand;
     type Event = TeamEvent;
     type Error = Error;
     type Services = TeamServices;
@@ -26,11 +38,21 @@ impl Aggregate for Team {
         "team".to_string()
     }
 
+    #[tracing::instrument(
+        name = "Team::handle",
+        skip_all,
+        fields(command = issued.command.command_type())
+    )]
     async fn handle(
         &self,
-        command: Self::Command,
+        issued: Self::Command,
         _services: &Self::Services,
     ) -> Result<Vec<Self::Event>, Self::Error> {
+        let IssuedCommand { actor, command } = issued;
+        if let Some(actor) = &actor {
+            self.authorize(actor, &command)?;
+        }
+
         match command {
             TeamCommand::AddMember { member_id, email } => {
                 if self
@@ -67,10 +89,39 @@ impl Aggregate for Team {
 
                 let forecast_tracked_event = TeamEvent::ForecastTracked {
                     forecasts: new_forecasts,
+                    member_id: Some(member_id),
                 };
 
                 Ok(vec![forecast_tracked_event])
             }
+            TeamCommand::RemoveMember { member_id } => {
+                self.member(&member_id)?;
+                Ok(vec![TeamEvent::MemberRemoved { member_id }])
+            }
+            TeamCommand::GrantRole { member_id, role } => {
+                if self.member(&member_id)?.has_role(&role) {
+                    return Err(Error::RoleAlreadyGranted(member_id, role));
+                }
+                Ok(vec![TeamEvent::RoleGranted { member_id, role }])
+            }
+            TeamCommand::RevokeRole { member_id, role } => {
+                if !self.member(&member_id)?.has_role(&role) {
+                    return Err(Error::RoleNotGranted(member_id, role));
+                }
+                Ok(vec![TeamEvent::RoleRevoked { member_id, role }])
+            }
+            TeamCommand::SetPolicy { command, rule } => {
+                if command != "*" && !TeamCommand::COMMAND_TYPES.contains(&command.as_str()) {
+                    return Err(Error::UnknownCommandType(command));
+                }
+                Ok(vec![TeamEvent::PolicySet { command, rule }])
+            }
+            TeamCommand::RemovePolicy { command } => {
+                if !self.policies.contains_key(&command) {
+                    return Err(Error::PolicyNotFound(command));
+                }
+                Ok(vec![TeamEvent::PolicyRemoved { command }])
+            }
         }
     }
 
@@ -80,16 +131,46 @@ impl Aggregate for Team {
                 let member = Member::new(member_id, email);
                 self.members.push(member);
             }
-            TeamEvent::ForecastTracked { forecasts } => {
+            TeamEvent::ForecastTracked { forecasts, .. } => {
                 self.forecasts = forecasts;
             }
+            TeamEvent::MemberRemoved { member_id } => {
+                self.members.retain(|m| m.id != member_id);
+                self.forecasts.remove(&member_id);
+            }
+            TeamEvent::RoleGranted { member_id, role } => {
+                if let Some(member) = self.members.iter_mut().find(|m| m.id == member_id) {
+                    member.roles.push(role);
+                }
+            }
+            TeamEvent::RoleRevoked { member_id, role } => {
+                if let Some(member) = self.members.iter_mut().find(|m| m.id == member_id) {
+                    member.roles.retain(|r| *r != role);
+                }
+            }
+            TeamEvent::PolicySet { command, rule } => {
+                self.policies.insert(command, rule);
+            }
+            TeamEvent::PolicyRemoved { command } => {
+                self.policies.remove(&command);
+            }
         }
     }
 }
 
+impl Team {
+    fn member(&self, member_id: &MemberId) -> Result<&Member, Error> {
+        self.members
+            .iter()
+            .find(|m| m.id == *member_id)
+            .ok_or_else(|| Error::MemberNotFoundInTeam(member_id.clone(), self.team.id.clone()))
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::domain::policies::{Actor, Denial, ADMIN_ROLE};
 
     use snowy_model::{CelsiusTemperature, Member, MemberId, WeatherCode, WeatherForecast};
 
@@ -109,7 +190,7 @@ mod test {
 
         TeamTestFramework::with(TeamServices)
             .given_no_previous_events()
-            .when(command)
+            .when(command.into())
             .then_expect_events(vec![expected_event]);
     }
 
@@ -127,7 +208,7 @@ mod test {
                 member_id: member_id.clone(),
                 email: email.clone(),
             }])
-            .when(command)
+            .when(command.into())
             .then_expect_error(Error::MemberAlreadyExists(email));
     }
 
@@ -156,14 +237,190 @@ mod test {
                 .iter()
                 .cloned()
                 .collect(),
+            member_id: Some(member_id.clone()),
         }];
 
         TestFramework::<Team>::with(TeamServices)
             .given(vec![member_added_event])
-            .when(track_forecast_command)
+            .when(track_forecast_command.into())
             .then_expect_events(expected_events);
     }
 
+    #[test]
+    fn test_remove_member() {
+        let member_id = MemberId::new(uuid::Uuid::new_v4().to_string());
+
+        TeamTestFramework::with(TeamServices)
+            .given(vec![TeamEvent::MemberAdded {
+                member_id: member_id.clone(),
+                email: "test@example.com".to_string(),
+            }])
+            .when(
+                TeamCommand::RemoveMember {
+                    member_id: member_id.clone(),
+                }
+                .into(),
+            )
+            .then_expect_events(vec![TeamEvent::MemberRemoved { member_id }]);
+    }
+
+    #[test]
+    fn test_remove_unknown_member() {
+        let member_id = MemberId::new(uuid::Uuid::new_v4().to_string());
+
+        TeamTestFramework::with(TeamServices)
+            .given_no_previous_events()
+            .when(
+                TeamCommand::RemoveMember {
+                    member_id: member_id.clone(),
+                }
+                .into(),
+            )
+            .then_expect_error(Error::MemberNotFoundInTeam(member_id, Default::default()));
+    }
+
+    #[test]
+    fn test_grant_role_twice() {
+        let member_id = MemberId::new(uuid::Uuid::new_v4().to_string());
+
+        TeamTestFramework::with(TeamServices)
+            .given(vec![
+                TeamEvent::MemberAdded {
+                    member_id: member_id.clone(),
+                    email: "test@example.com".to_string(),
+                },
+                TeamEvent::RoleGranted {
+                    member_id: member_id.clone(),
+                    role: "admin".to_string(),
+                },
+            ])
+            .when(
+                TeamCommand::GrantRole {
+                    member_id: member_id.clone(),
+                    role: "admin".to_string(),
+                }
+                .into(),
+            )
+            .then_expect_error(Error::RoleAlreadyGranted(member_id, "admin".to_string()));
+    }
+
+    #[test]
+    fn test_set_policy_for_unknown_command() {
+        TeamTestFramework::with(TeamServices)
+            .given_no_previous_events()
+            .when(
+                TeamCommand::SetPolicy {
+                    command: "launch-rockets".to_string(),
+                    rule: Rule::Deny,
+                }
+                .into(),
+            )
+            .then_expect_error(Error::UnknownCommandType("launch-rockets".to_string()));
+    }
+
+    #[test]
+    fn test_remove_unknown_policy() {
+        TeamTestFramework::with(TeamServices)
+            .given_no_previous_events()
+            .when(
+                TeamCommand::RemovePolicy {
+                    command: "add-member".to_string(),
+                }
+                .into(),
+            )
+            .then_expect_error(Error::PolicyNotFound("add-member".to_string()));
+    }
+
+    #[test]
+    fn test_policies_checked_against_current_state() {
+        let admin = MemberId::new("admin".to_string());
+        let added_admin = TeamEvent::MemberAdded {
+            member_id: admin.clone(),
+            email: "admin@example.com".to_string(),
+        };
+        let add_member = IssuedCommand {
+            actor: Some(Actor::Member(admin.clone())),
+            command: TeamCommand::AddMember {
+                member_id: MemberId::new("member".to_string()),
+                email: "member@example.com".to_string(),
+            },
+        };
+
+        TeamTestFramework::with(TeamServices)
+            .given(vec![
+                added_admin.clone(),
+                TeamEvent::RoleGranted {
+                    member_id: admin.clone(),
+                    role: ADMIN_ROLE.to_string(),
+                },
+            ])
+            .when(add_member.clone())
+            .then_expect_events(vec![TeamEvent::MemberAdded {
+                member_id: MemberId::new("member".to_string()),
+                email: "member@example.com".to_string(),
+            }]);
+
+        TeamTestFramework::with(TeamServices)
+            .given(vec![
+                added_admin,
+                TeamEvent::RoleGranted {
+                    member_id: admin.clone(),
+                    role: ADMIN_ROLE.to_string(),
+                },
+                TeamEvent::RoleRevoked {
+                    member_id: admin,
+                    role: ADMIN_ROLE.to_string(),
+                },
+            ])
+            .when(add_member)
+            .then_expect_error(Error::PolicyDenied(Denial(
+                "Only members with role admin may add-member".to_string(),
+            )));
+    }
+
+    #[test]
+    fn test_forecast_tracked_without_member_id() {
+        let event: TeamEvent =
+            serde_json::from_str(r#"{"ForecastTracked": {"forecasts": {}}}"#).unwrap();
+
+        assert_eq!(
+            event,
+            TeamEvent::ForecastTracked {
+                forecasts: HashMap::new(),
+                member_id: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_apply_member_removed() {
+        let member_id = MemberId::new(uuid::Uuid::new_v4().to_string());
+        let mut team = Team::default();
+
+        team.apply(TeamEvent::MemberAdded {
+            member_id: member_id.clone(),
+            email: "test@example.com".to_string(),
+        });
+        team.apply(TeamEvent::RoleGranted {
+            member_id: member_id.clone(),
+            role: "admin".to_string(),
+        });
+        assert!(team.members[0].has_role("admin"));
+
+        team.apply(TeamEvent::ForecastTracked {
+            forecasts: [(member_id.clone(), WeatherForecast::default())]
+                .into_iter()
+                .collect(),
+            member_id: Some(member_id.clone()),
+        });
+        team.apply(TeamEvent::MemberRemoved {
+            member_id: member_id.clone(),
+        });
+
+        assert!(team.members.is_empty());
+        assert!(team.forecasts.is_empty());
+    }
+
     #[test]
     fn test_apply_member_added() {
         let mut team = Team::default();
@@ -230,6 +487,7 @@ mod test {
                 .iter()
                 .cloned()
                 .collect(),
+            member_id: Some(member_id.clone()),
         };
 
         team.apply(event);
@@ -272,7 +530,7 @@ mod test {
             forecast: member0_forecast0.clone(),
         };
         let member0_forecast0_events = team
-            .handle(member0_forecast0_command, &service)
+            .handle(member0_forecast0_command.into(), &service)
             .await
             .unwrap();
 
@@ -296,7 +554,7 @@ mod test {
             forecast: member0_forecast1.clone(),
         };
         let member0_forecast1_events = team
-            .handle(member0_forecast1_command, &service)
+            .handle(member0_forecast1_command.into(), &service)
             .await
             .unwrap();
 
@@ -320,7 +578,7 @@ mod test {
             forecast: member1_forecast0.clone(),
         };
         let member1_forecast0_events = team
-            .handle(member1_forecast0_command, &service)
+            .handle(member1_forecast0_command.into(), &service)
             .await
             .unwrap();
         for event in member1_forecast0_events {
@@ -1,8 +1,11 @@
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 use snowy_model::{MemberId, WeatherForecast};
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+use super::policies::{Actor, Rule};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
 pub(crate) enum TeamCommand {
     AddMember {
         member_id: MemberId,
@@ -12,4 +15,139 @@ pub(crate) enum TeamCommand {
         member_id: MemberId,
         forecast: WeatherForecast,
     },
+    RemoveMember {
+        member_id: MemberId,
+    },
+    GrantRole {
+        member_id: MemberId,
+        role: String,
+    },
+    RevokeRole {
+        member_id: MemberId,
+        role: String,
+    },
+    SetPolicy {
+        command: String,
+        rule: Rule,
+    },
+    RemovePolicy {
+        command: String,
+    },
+}
+
+/// A command with the actor issuing it. The team checks its policies for
+/// the actor against the state the command runs on.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct IssuedCommand {
+    /// `None` for commands issued outside the API, e.g. by the CLI.
+    pub(crate) actor: Option<Actor>,
+    pub(crate) command: TeamCommand,
+}
+
+impl From<TeamCommand> for IssuedCommand {
+    fn from(command: TeamCommand) -> Self {
+        IssuedCommand {
+            actor: None,
+            command,
+        }
+    }
+}
+
+impl TeamCommand {
+    /// Every value returned by [`TeamCommand::command_type`].
+    pub(crate) const COMMAND_TYPES: &'static [&'static str] = &[
+        "add-member",
+        "track-member-forecast",
+        "remove-member",
+        "grant-role",
+        "revoke-role",
+        "set-policy",
+        "remove-policy",
+    ];
+
+    /// Stable name for the command, used to label metrics.
+    pub(crate) fn command_type(&self) -> &'static str {
+        match self {
+            TeamCommand::AddMember { .. } => "add-member",
+            TeamCommand::TrackMemberForecast { .. } => "track-member-forecast",
+            TeamCommand::RemoveMember { .. } => "remove-member",
+            TeamCommand::GrantRole { .. } => "grant-role",
+            TeamCommand::RevokeRole { .. } => "revoke-role",
+            TeamCommand::SetPolicy { .. } => "set-policy",
+            TeamCommand::RemovePolicy { .. } => "remove-policy",
+        }
+    }
+
+    /// The member a command acts upon, if any.
+    pub(crate) fn target_member(&self) -> Option<&MemberId> {
+        match self {
+            TeamCommand::AddMember { member_id, .. }
+            | TeamCommand::TrackMemberForecast { member_id, .. }
+            | TeamCommand::RemoveMember { member_id }
+            | TeamCommand::GrantRole { member_id, .. }
+            | TeamCommand::RevokeRole { member_id, .. } => Some(member_id),
+            TeamCommand::SetPolicy { .. } | TeamCommand::RemovePolicy { .. } => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One command of each variant. The match fails to compile when a
+    /// variant is added without being listed here.
+    fn one_of_each() -> Vec<TeamCommand> {
+        let member_id = MemberId::new("member-1".to_string());
+        let commands = vec![
+            TeamCommand::AddMember {
+                member_id: member_id.clone(),
+                email: "member-1@example.com".to_string(),
+            },
+            TeamCommand::TrackMemberForecast {
+                member_id: member_id.clone(),
+                forecast: WeatherForecast::default(),
+            },
+            TeamCommand::RemoveMember {
+                member_id: member_id.clone(),
+            },
+            TeamCommand::GrantRole {
+                member_id: member_id.clone(),
+                role: "admin".to_string(),
+            },
+            TeamCommand::RevokeRole {
+                member_id,
+                role: "admin".to_string(),
+            },
+            TeamCommand::SetPolicy {
+                command: "add-member".to_string(),
+                rule: Rule::AnyMember,
+            },
+            TeamCommand::RemovePolicy {
+                command: "add-member".to_string(),
+            },
+        ];
+        for command in &commands {
+            match command {
+                TeamCommand::AddMember { .. }
+                | TeamCommand::TrackMemberForecast { .. }
+                | TeamCommand::RemoveMember { .. }
+                | TeamCommand::GrantRole { .. }
+                | TeamCommand::RevokeRole { .. }
+                | TeamCommand::SetPolicy { .. }
+                | TeamCommand::RemovePolicy { .. } => {}
+            }
+        }
+        commands
+    }
+
+    #[test]
+    fn test_command_types_lists_every_command_type() {
+        let command_types: Vec<&str> = one_of_each()
+            .iter()
+            .map(TeamCommand::command_type)
+            .collect();
+
+        assert_eq!(command_types, TeamCommand::COMMAND_TYPES);
+    }
 }
@@ -1,9 +1,21 @@
 use snowy_model::{MemberId, TeamId};
 
+use super::policies::Denial;
+
 #[derive(Debug, thiserror::Error, PartialEq)]
 pub(crate) enum Error {
     #[error("Member with email '{0}' already exists")]
     MemberAlreadyExists(String),
     #[error("Member '{0:?}' is not in team '{1:?}'")]
     MemberNotFoundInTeam(MemberId, TeamId),
+    #[error("Member '{0:?}' already has role '{1}'")]
+    RoleAlreadyGranted(MemberId, String),
+    #[error("Member '{0:?}' does not have role '{1}'")]
+    RoleNotGranted(MemberId, String),
+    #[error("Unknown command type '{0}'")]
+    UnknownCommandType(String),
+    #[error("No policy for command type '{0}'")]
+    PolicyNotFound(String),
+    #[error("{0}")]
+    PolicyDenied(#[from] Denial),
 }
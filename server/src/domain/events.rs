@@ -5,15 +5,51 @@ use serde::{Deserialize, Serialize};
 
 use snowy_model::{MemberId, WeatherForecast};
 
+use super::policies::Rule;
+
 #[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
 pub(crate) enum TeamEvent {
     ForecastTracked {
         forecasts: HashMap<MemberId, WeatherForecast>,
+        /// The member whose forecast was tracked; absent on older events.
+        #[serde(default)]
+        member_id: Option<MemberId>,
     },
     MemberAdded {
         member_id: MemberId,
         email: String,
     },
+    MemberRemoved {
+        member_id: MemberId,
+    },
+    RoleGranted {
+        member_id: MemberId,
+        role: String,
+    },
+    RoleRevoked {
+        member_id: MemberId,
+        role: String,
+    },
+    PolicySet {
+        command: String,
+        rule: Rule,
+    },
+    PolicyRemoved {
+        command: String,
+    },
+}
+
+impl TeamEvent {
+    /// Every value returned by [`DomainEvent::event_type`].
+    pub(crate) const EVENT_TYPES: &'static [&'static str] = &[
+        "forecast-tracked",
+        "member-added",
+        "member-removed",
+        "role-granted",
+        "role-revoked",
+        "policy-set",
+        "policy-removed",
+    ];
 }
 
 impl DomainEvent for TeamEvent {
@@ -21,6 +57,11 @@ impl DomainEvent for TeamEvent {
         let event_type: &str = match self {
             TeamEvent::ForecastTracked { .. } => "forecast-tracked",
             TeamEvent::MemberAdded { .. } => "member-added",
+            TeamEvent::MemberRemoved { .. } => "member-removed",
+            TeamEvent::RoleGranted { .. } => "role-granted",
+            TeamEvent::RoleRevoked { .. } => "role-revoked",
+            TeamEvent::PolicySet { .. } => "policy-set",
+            TeamEvent::PolicyRemoved { .. } => "policy-removed",
         };
         event_type.to_string()
     }
@@ -2,4 +2,5 @@ pub(crate) mod aggregates;
 pub(crate) mod commands;
 pub(crate) mod error;
 pub(crate) mod events;
+pub(crate) mod policies;
 pub(crate) mod services;
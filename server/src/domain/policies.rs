@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use snowy_model::MemberId;
+
+use super::{aggregates::Team, commands::TeamCommand};
+
+/// Role allowed to manage a team under the default policies.
+pub(crate) const ADMIN_ROLE: &str = "admin";
+
+/// Command metadata entry holding the [`Actor`] that issued the command.
+pub(crate) const ACTOR_METADATA_KEY: &str = "actor";
+
+/// Policy key matching every command type without a policy of its own.
+const ANY_COMMAND: &str = "*";
+
+/// Who issued a command.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Actor {
+    /// A service authenticated with an API key, already limited by its scopes.
+    Service(String),
+    /// A person acting as a member of the team.
+    Member(MemberId),
+}
+
+impl Actor {
+    pub(crate) fn from_metadata(metadata: &HashMap<String, String>) -> Option<Self> {
+        let actor = metadata.get(ACTOR_METADATA_KEY)?;
+        if let Some(id) = actor.strip_prefix("api-key:") {
+            Some(Actor::Service(id.to_string()))
+        } else {
+            actor
+                .strip_prefix("member:")
+                .map(|id| Actor::Member(MemberId::new(id.to_string())))
+        }
+    }
+
+    pub(crate) fn to_metadata(&self) -> String {
+        match self {
+            Actor::Service(id) => format!("api-key:{}", id),
+            Actor::Member(id) => format!("member:{}", id.as_str()),
+        }
+    }
+}
+
+/// Who may issue a command type.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub(crate) enum Rule {
+    /// Any member of the team.
+    AnyMember,
+    /// Only members holding at least one of the roles.
+    RequireRole { roles: Vec<String> },
+    /// Only members acting on themselves, e.g. tracking their own forecast.
+    SelfOnly,
+    /// Members acting on themselves, or members holding one of the roles.
+    SelfOrRole { roles: Vec<String> },
+    /// Nobody; only services can issue the command.
+    Deny,
+}
+
+/// Why a command was not allowed.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[error("{0}")]
+pub(crate) struct Denial(pub(crate) String);
+
+/// Rules applied to teams that have not configured their own.
+pub(crate) fn default_rule(command_type: &str) -> Rule {
+    match command_type {
+        "track-member-forecast" => Rule::SelfOnly,
+        _ => Rule::RequireRole {
+            roles: vec![ADMIN_ROLE.to_string()],
+        },
+    }
+}
+
+impl Team {
+    /// The rule governing `command_type`: the team's own rule for it, then the
+    /// team's `*` rule, then the default.
+    pub(crate) fn rule_for(&self, command_type: &str) -> Rule {
+        self.policies
+            .get(command_type)
+            .or_else(|| self.policies.get(ANY_COMMAND))
+            .cloned()
+            .unwrap_or_else(|| default_rule(command_type))
+    }
+
+    /// Evaluates the team's policies for `actor` issuing `command`.
+    pub(crate) fn authorize(&self, actor: &Actor, command: &TeamCommand) -> Result<(), Denial> {
+        let member_id = match actor {
+            Actor::Service(_) => return Ok(()),
+            Actor::Member(member_id) => member_id,
+        };
+        let Some(member) = self.members.iter().find(|m| m.id == *member_id) else {
+            return Err(Denial(format!(
+                "'{}' is not a member of this team",
+                member_id.as_str()
+            )));
+        };
+
+        let command_type = command.command_type();
+        let acts_on_self = command.target_member() == Some(member_id);
+        let has_any_role = |roles: &[String]| roles.iter().any(|role| member.has_role(role));
+
+        match self.rule_for(command_type) {
+            Rule::AnyMember => Ok(()),
+            Rule::RequireRole { roles } if has_any_role(&roles) => Ok(()),
+            Rule::RequireRole { roles } => Err(Denial(format!(
+                "Only members with role {} may {}",
+                roles.join(" or "),
+                command_type
+            ))),
+            Rule::SelfOnly if acts_on_self => Ok(()),
+            Rule::SelfOnly => Err(Denial(format!(
+                "Members may only {} for themselves",
+                command_type
+            ))),
+            Rule::SelfOrRole { roles } if acts_on_self || has_any_role(&roles) => Ok(()),
+            Rule::SelfOrRole { roles } => Err(Denial(format!(
+                "Members may only {} for themselves unless they have role {}",
+                command_type,
+                roles.join(" or ")
+            ))),
+            Rule::Deny => Err(Denial(format!("Members may not {}", command_type))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use snowy_model::{Member, WeatherForecast};
+
+    use super::*;
+
+    fn member_id(id: &str) -> MemberId {
+        MemberId::new(id.to_string())
+    }
+
+    fn team() -> Team {
+        let mut lead = Member::new(member_id("alice"), "alice@example.com".to_string());
+        lead.roles.push("lead".to_string());
+        let mut admin = Member::new(member_id("bob"), "bob@example.com".to_string());
+        admin.roles.push(ADMIN_ROLE.to_string());
+        let member = Member::new(member_id("carol"), "carol@example.com".to_string());
+
+        Team {
+            members: vec![lead, admin, member],
+            ..Default::default()
+        }
+    }
+
+    fn add_member() -> TeamCommand {
+        TeamCommand::AddMember {
+            member_id: member_id("dave"),
+            email: "dave@example.com".to_string(),
+        }
+    }
+
+    fn track(id: &str) -> TeamCommand {
+        TeamCommand::TrackMemberForecast {
+            member_id: member_id(id),
+            forecast: WeatherForecast::default(),
+        }
+    }
+
+    #[test]
+    fn test_actor_metadata_round_trip() {
+        for actor in [
+            Actor::Service("key-1".to_string()),
+            Actor::Member(member_id("alice")),
+        ] {
+            let metadata = HashMap::from([(ACTOR_METADATA_KEY.to_string(), actor.to_metadata())]);
+            assert_eq!(Actor::from_metadata(&metadata), Some(actor));
+        }
+        assert_eq!(Actor::from_metadata(&HashMap::new()), None);
+    }
+
+    #[test]
+    fn test_default_policies() {
+        let team = team();
+        let admin = Actor::Member(member_id("bob"));
+        let member = Actor::Member(member_id("carol"));
+
+        assert!(team.authorize(&admin, &add_member()).is_ok());
+        assert!(team.authorize(&member, &add_member()).is_err());
+        assert!(team.authorize(&member, &track("carol")).is_ok());
+        assert!(team.authorize(&member, &track("alice")).is_err());
+        assert!(team.authorize(&admin, &track("carol")).is_err());
+    }
+
+    #[test]
+    fn test_only_lead_may_add_member() {
+        let mut team = team();
+        team.policies.insert(
+            "add-member".to_string(),
+            Rule::RequireRole {
+                roles: vec!["lead".to_string()],
+            },
+        );
+
+        assert!(team
+            .authorize(&Actor::Member(member_id("alice")), &add_member())
+            .is_ok());
+        assert_eq!(
+            team.authorize(&Actor::Member(member_id("bob")), &add_member()),
+            Err(Denial(
+                "Only members with role lead may add-member".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_wildcard_policy() {
+        let mut team = team();
+        team.policies.insert("*".to_string(), Rule::Deny);
+
+        let admin = Actor::Member(member_id("bob"));
+        assert!(team.authorize(&admin, &add_member()).is_err());
+        assert!(team.authorize(&admin, &track("bob")).is_err());
+    }
+
+    #[test]
+    fn test_services_and_strangers() {
+        let mut team = team();
+        team.policies.insert("add-member".to_string(), Rule::Deny);
+
+        assert!(team
+            .authorize(&Actor::Service("key-1".to_string()), &add_member())
+            .is_ok());
+        assert!(team
+            .authorize(&Actor::Member(member_id("mallory")), &track("mallory"))
+            .is_err());
+    }
+}
@@ -0,0 +1,450 @@
+use std::{collections::HashSet, future::Future};
+
+use rocket::FromFormField;
+use serde::{Deserialize, Serialize};
+use snowy_model::{MemberId, WeatherForecast};
+use utoipa::ToSchema;
+
+use crate::{api::cqrs::BatchFailure, domain::commands::TeamCommand};
+
+/// Commands an import commits at once.
+pub(crate) const BATCH_SIZE: usize = 100;
+
+/// Attempts at committing a batch, as other writers to the team may commit
+/// first, before its commands are reported as failed.
+const COMMIT_ATTEMPTS: usize = 3;
+
+/// Format of an import file, which determines what it holds.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, FromFormField, ToSchema, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ImportFormat {
+    /// A roster with `email`, `member_id` and `location` columns, one member
+    /// per row.
+    Csv,
+    /// One `{"member_id": ..., "forecast": {...}}` object per line.
+    #[field(value = "jsonl")]
+    #[serde(rename = "jsonl")]
+    #[value(name = "jsonl")]
+    JsonLines,
+}
+
+/// A roster row. Members have no location, so the `location` column, like
+/// any other, is ignored.
+#[derive(Deserialize)]
+struct MemberRow {
+    email: String,
+    member_id: String,
+}
+
+#[derive(Deserialize)]
+struct ForecastRow {
+    member_id: MemberId,
+    forecast: WeatherForecast,
+}
+
+/// A command an import issues, with the line of the file it comes from.
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct PlannedCommand {
+    pub(crate) line: u64,
+    pub(crate) command: TeamCommand,
+}
+
+/// Why a line of an import file was not imported.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub(crate) struct RowError {
+    pub(crate) line: u64,
+    pub(crate) error: String,
+}
+
+/// The commands an import would issue, and the lines that are invalid.
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub(crate) struct ImportPlan {
+    /// Rows read, valid or not.
+    pub(crate) rows: usize,
+    pub(crate) commands: Vec<PlannedCommand>,
+    pub(crate) errors: Vec<RowError>,
+}
+
+impl ImportPlan {
+    fn reject(&mut self, line: u64, error: impl ToString) {
+        self.errors.push(RowError {
+            line,
+            error: error.to_string(),
+        });
+    }
+}
+
+/// Outcome of an executed import.
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct ImportReport {
+    pub(crate) rows: usize,
+    /// Commands executed successfully.
+    pub(crate) executed: usize,
+    /// Invalid lines and failed commands, by line.
+    pub(crate) errors: Vec<RowError>,
+}
+
+/// Parses and validates an import file into the commands it would issue.
+pub(crate) fn plan(format: ImportFormat, input: &str) -> ImportPlan {
+    match format {
+        ImportFormat::Csv => plan_members(input),
+        ImportFormat::JsonLines => plan_forecasts(input),
+    }
+}
+
+fn plan_members(input: &str) -> ImportPlan {
+    let mut plan = ImportPlan::default();
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(input.as_bytes());
+    let headers = match reader.headers() {
+        Ok(headers) => headers.clone(),
+        Err(e) => {
+            plan.reject(1, e);
+            return plan;
+        }
+    };
+
+    let mut emails = HashSet::new();
+    let mut member_ids = HashSet::new();
+    for record in reader.records() {
+        plan.rows += 1;
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                let line = e.position().map_or(0, |position| position.line());
+                plan.reject(line, e);
+                continue;
+            }
+        };
+        let line = record.position().map_or(0, |position| position.line());
+        let row = match record.deserialize::<MemberRow>(Some(&headers)) {
+            Ok(row) => row,
+            Err(e) => {
+                plan.reject(line, e);
+                continue;
+            }
+        };
+
+        if let Err(e) = validate_email(&row.email) {
+            plan.reject(line, e);
+        } else if row.member_id.is_empty() {
+            plan.reject(line, "Missing member_id");
+        } else if !emails.insert(row.email.to_lowercase()) {
+            plan.reject(line, format!("Duplicate email '{}'", row.email));
+        } else if !member_ids.insert(row.member_id.clone()) {
+            plan.reject(line, format!("Duplicate member_id '{}'", row.member_id));
+        } else {
+            plan.commands.push(PlannedCommand {
+                line,
+                command: TeamCommand::AddMember {
+                    member_id: MemberId::new(row.member_id),
+                    email: row.email,
+                },
+            });
+        }
+    }
+    plan
+}
+
+fn validate_email(email: &str) -> Result<(), String> {
+    match email.split_once('@') {
+        Some((local, domain))
+            if !local.is_empty()
+                && !domain.is_empty()
+                && !domain.contains('@')
+                && !email.contains(char::is_whitespace) =>
+        {
+            Ok(())
+        }
+        _ => Err(format!("Invalid email '{}'", email)),
+    }
+}
+
+fn plan_forecasts(input: &str) -> ImportPlan {
+    let mut plan = ImportPlan::default();
+    for (index, text) in input.lines().enumerate() {
+        let line = index as u64 + 1;
+        if text.trim().is_empty() {
+            continue;
+        }
+        plan.rows += 1;
+        match serde_json::from_str::<ForecastRow>(text) {
+            Ok(row) if row.member_id.as_str().is_empty() => plan.reject(line, "Missing member_id"),
+            Ok(row) => plan.commands.push(PlannedCommand {
+                line,
+                command: TeamCommand::TrackMemberForecast {
+                    member_id: row.member_id,
+                    forecast: row.forecast,
+                },
+            }),
+            Err(e) => plan.reject(line, e),
+        }
+    }
+    plan
+}
+
+/// Executes the planned commands in batches of at most [`BATCH_SIZE`] with
+/// `run`, calling `progress` with the number of commands done and planned
+/// after each. A batch commits all of its commands or none, so a command
+/// that fails is reported with the invalid lines and its batch is run again
+/// without it. A batch whose commit fails is run again up to
+/// [`COMMIT_ATTEMPTS`] times in all.
+pub(crate) async fn execute<F, Fut>(
+    plan: ImportPlan,
+    mut run: F,
+    mut progress: impl FnMut(usize, usize),
+) -> ImportReport
+where
+    F: FnMut(Vec<TeamCommand>) -> Fut,
+    Fut: Future<Output = Result<Vec<usize>, BatchFailure>>,
+{
+    let total = plan.commands.len();
+    let mut errors = plan.errors;
+    let mut executed = 0;
+    let mut done = 0;
+    let mut planned = plan.commands.into_iter().peekable();
+    while planned.peek().is_some() {
+        let mut batch: Vec<PlannedCommand> = planned.by_ref().take(BATCH_SIZE).collect();
+        done += batch.len();
+        let mut commit_attempts = 0;
+        while !batch.is_empty() {
+            let commands = batch.iter().map(|p| p.command.clone()).collect();
+            match run(commands).await {
+                Ok(_) => {
+                    executed += batch.len();
+                    break;
+                }
+                Err(BatchFailure {
+                    index: Some(index),
+                    error,
+                }) => {
+                    let failed = batch.remove(index);
+                    errors.push(RowError {
+                        line: failed.line,
+                        error: error.message(),
+                    });
+                }
+                Err(BatchFailure { index: None, .. }) if commit_attempts + 1 < COMMIT_ATTEMPTS => {
+                    commit_attempts += 1;
+                }
+                Err(BatchFailure { index: None, error }) => {
+                    let error = error.message();
+                    errors.extend(batch.drain(..).map(|failed| RowError {
+                        line: failed.line,
+                        error: error.clone(),
+                    }));
+                }
+            }
+        }
+        progress(done, total);
+    }
+    errors.sort_by_key(|error| error.line);
+
+    ImportReport {
+        rows: plan.rows,
+        executed,
+        errors,
+    }
+}
+
+/// Writes `errors` as CSV with `line` and `error` columns.
+pub(crate) fn write_errors(
+    errors: &[RowError],
+    writer: impl std::io::Write,
+) -> Result<(), csv::Error> {
+    let mut writer = csv::Writer::from_writer(writer);
+    for error in errors {
+        writer.serialize(error)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use cqrs_es::AggregateError;
+    use rocket::tokio;
+
+    use super::*;
+    use crate::api::error::Error;
+
+    #[test]
+    fn test_plan_members_from_csv() {
+        let input = "email,member_id,location\n\
+                     alice@example.com,alice,Oslo\n\
+                     bob@example.com, bob ,\n\
+                     not-an-email,carol,Bergen\n\
+                     ALICE@example.com,alice2,Oslo\n\
+                     dave@example.com,,Tromsø\n";
+
+        let plan = plan(ImportFormat::Csv, input);
+
+        assert_eq!(plan.rows, 5);
+        let lines: Vec<u64> = plan.commands.iter().map(|c| c.line).collect();
+        assert_eq!(lines, vec![2, 3]);
+        let TeamCommand::AddMember { member_id, email } = &plan.commands[1].command else {
+            panic!("expected AddMember");
+        };
+        assert_eq!(member_id, &MemberId::new("bob".to_string()));
+        assert_eq!(email, "bob@example.com");
+        let invalid: Vec<u64> = plan.errors.iter().map(|e| e.line).collect();
+        assert_eq!(invalid, vec![4, 5, 6]);
+    }
+
+    #[test]
+    fn test_plan_rejects_csv_without_required_columns() {
+        let plan = plan(ImportFormat::Csv, "name,location\nAlice,Oslo\n");
+
+        assert!(plan.commands.is_empty());
+        assert_eq!(plan.errors.len(), 1);
+        assert_eq!(plan.errors[0].line, 2);
+    }
+
+    #[test]
+    fn test_plan_forecasts_from_json_lines() {
+        let input = concat!(
+            r#"{"member_id": "alice", "forecast": {"date": "2024-11-05", "minimum_temperature": -2.0, "maximum_temperature": 4.0, "minimum_apparent_temperature": null, "maximum_apparent_temperature": null, "maximum_wind_speed": null, "dominant_wind_direction": null, "weather_code": "LightSnow"}}"#,
+            "\n\n",
+            r#"{"member_id": "bob", "forecast": {"date": "tomorrow"}}"#,
+            "\n"
+        );
+
+        let plan = plan(ImportFormat::JsonLines, input);
+
+        assert_eq!(plan.rows, 2);
+        assert_eq!(plan.commands.len(), 1);
+        assert_eq!(plan.commands[0].line, 1);
+        assert_eq!(plan.errors.len(), 1);
+        assert_eq!(plan.errors[0].line, 3);
+    }
+
+    #[tokio::test]
+    async fn test_execute_reports_failed_commands_by_line() {
+        let plan = plan(
+            ImportFormat::Csv,
+            "email,member_id\na@example.com,a\nb@example.com,b\nbad,c\nd@example.com,d\n",
+        );
+        let mut batches = Vec::new();
+        let mut progress = Vec::new();
+
+        let report = execute(
+            plan,
+            |commands| {
+                let ids: Vec<String> = commands
+                    .iter()
+                    .filter_map(|command| command.target_member())
+                    .map(|id| id.as_str().to_string())
+                    .collect();
+                batches.push(ids.clone());
+                async move {
+                    match ids.iter().position(|id| id == "b") {
+                        Some(index) => Err(BatchFailure {
+                            index: Some(index),
+                            error: Error::BadRequest("rejected".to_string()),
+                        }),
+                        None => Ok(vec![1; ids.len()]),
+                    }
+                }
+            },
+            |done, total| progress.push((done, total)),
+        )
+        .await;
+
+        assert_eq!(report.executed, 2);
+        let lines: Vec<u64> = report.errors.iter().map(|e| e.line).collect();
+        assert_eq!(lines, vec![3, 4]);
+        assert_eq!(report.errors[0].error, "Bad request: rejected");
+        assert_eq!(batches, vec![vec!["a", "b", "d"], vec!["a", "d"]]);
+        assert_eq!(progress, vec![(3, 3)]);
+    }
+
+    #[tokio::test]
+    async fn test_execute_in_batches() {
+        let input: String = std::iter::once("email,member_id\n".to_string())
+            .chain((0..BATCH_SIZE + 1).map(|i| format!("m{}@example.com,m{}\n", i, i)))
+            .collect();
+        let mut sizes = Vec::new();
+        let mut progress = Vec::new();
+
+        let report = execute(
+            plan(ImportFormat::Csv, &input),
+            |commands| {
+                sizes.push(commands.len());
+                async move {
+                    if commands.len() == 1 {
+                        Err(BatchFailure {
+                            index: None,
+                            error: Error::BadRequest("conflict".to_string()),
+                        })
+                    } else {
+                        Ok(vec![1; commands.len()])
+                    }
+                }
+            },
+            |done, total| progress.push((done, total)),
+        )
+        .await;
+
+        assert_eq!(sizes, vec![BATCH_SIZE, 1, 1, 1]);
+        assert_eq!(report.executed, BATCH_SIZE);
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].line, BATCH_SIZE as u64 + 2);
+        assert_eq!(
+            progress,
+            vec![
+                (BATCH_SIZE, BATCH_SIZE + 1),
+                (BATCH_SIZE + 1, BATCH_SIZE + 1)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_execute_retries_failed_commits() {
+        let plan = plan(
+            ImportFormat::Csv,
+            "email,member_id\na@example.com,a\nb@example.com,b\n",
+        );
+        let mut attempts = 0;
+
+        let report = execute(
+            plan,
+            |commands| {
+                attempts += 1;
+                let attempt = attempts;
+                async move {
+                    if attempt < COMMIT_ATTEMPTS {
+                        Err(BatchFailure {
+                            index: None,
+                            error: Error::Aggregate(AggregateError::AggregateConflict),
+                        })
+                    } else {
+                        Ok(vec![1; commands.len()])
+                    }
+                }
+            },
+            |_, _| {},
+        )
+        .await;
+
+        assert_eq!(attempts, COMMIT_ATTEMPTS);
+        assert_eq!(report.executed, 2);
+        assert!(report.errors.is_empty());
+    }
+
+    #[test]
+    fn test_write_errors_as_csv() {
+        let mut output = Vec::new();
+        let errors = [RowError {
+            line: 4,
+            error: "Invalid email 'bad'".to_string(),
+        }];
+
+        write_errors(&errors, &mut output).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "line,error\n4,Invalid email 'bad'\n"
+        );
+    }
+}
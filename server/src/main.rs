@@ -1,19 +1,44 @@
+use clap::Parser;
 use tracing::info;
 
 mod api;
+mod cli;
 pub(crate) mod domain;
+mod import;
+mod projections;
+mod publishing;
 mod queries;
+#[cfg(test)]
+mod testing;
+mod webhooks;
 
 fn main() -> Result<(), std::io::Error> {
     dotenv::dotenv().ok();
+    let cli = cli::Cli::parse();
     let config = api::config::get_config();
     ::rocket::async_main(async move {
-        info!(config = %config, "starting up server");
-        let _res = api::server::server(config)
-            .await
-            .expect("Failed to launch server")
-            .launch()
-            .await;
+        let telemetry = api::telemetry::init_tracing(&config);
+        let result = match cli.command.unwrap_or(cli::Command::Serve) {
+            cli::Command::Serve => {
+                info!(config = %config, "starting up server");
+                let _res = api::server::server(config)
+                    .await
+                    .expect("Failed to launch server")
+                    .launch()
+                    .await;
+                Ok(())
+            }
+            cli::Command::ApiKey(command) => cli::run_api_key_command(command, &config).await,
+            cli::Command::Projections(command) => {
+                cli::run_projection_command(command, &config).await
+            }
+            cli::Command::Import(command) => cli::run_import_command(command, &config).await,
+        };
+        telemetry.shutdown().await;
+        if let Err(e) = result {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
     });
 
     Ok(())
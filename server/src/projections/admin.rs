@@ -0,0 +1,348 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{Pool, Postgres};
+use utoipa::ToSchema;
+
+use crate::api::config::ProjectionConfig;
+
+use super::{
+    dead_letters::{self, DeadLetter, DeadLetterStatus},
+    Projection, ProjectionError,
+};
+
+const STATUS_QUERY: &str = "
+    SELECT c.projection, c.global_position, c.paused, c.error, c.errored_at, c.updated_at,
+           GREATEST(head.global_position - c.global_position, 0) AS lag_events,
+           EXTRACT(EPOCH FROM now() - next.recorded_at)::float8 AS lag_seconds,
+           (SELECT count(*)
+            FROM projection_dead_letters d
+            WHERE d.projection = c.projection AND d.status <> 'replayed') AS dead_letters
+    FROM projection_checkpoints c
+    CROSS JOIN (SELECT COALESCE(max(global_position), 0) AS global_position FROM events) head
+    LEFT JOIN LATERAL (SELECT e.recorded_at
+                       FROM events e
+                       WHERE e.global_position > c.global_position
+                       ORDER BY e.global_position
+                       LIMIT 1) next ON true";
+
+/// Progress of a projection through the `events` table.
+#[derive(Debug, Serialize, sqlx::FromRow, ToSchema)]
+pub(crate) struct ProjectionStatus {
+    pub(crate) projection: String,
+    /// Global position of the last event projected.
+    pub(crate) global_position: i64,
+    /// Positions taken since then, from the latest event's; those of
+    /// rolled back transactions are counted too.
+    pub(crate) lag_events: i64,
+    /// Age of the oldest event not projected yet, in seconds; absent when
+    /// caught up.
+    pub(crate) lag_seconds: Option<f64>,
+    pub(crate) paused: bool,
+    /// Dead letters not replayed yet.
+    pub(crate) dead_letters: i64,
+    /// Error of the last failed batch, cleared by the next successful one.
+    pub(crate) error: Option<String>,
+    pub(crate) errored_at: Option<DateTime<Utc>>,
+    pub(crate) updated_at: DateTime<Utc>,
+}
+
+/// Reports on and controls the checkpoints of the [`ProjectionRunner`]s of
+/// every replica.
+///
+/// [`ProjectionRunner`]: super::ProjectionRunner
+pub(crate) struct ProjectionAdmin {
+    pool: Pool<Postgres>,
+    projections: Vec<Box<dyn Projection>>,
+    config: ProjectionConfig,
+}
+
+impl ProjectionAdmin {
+    pub(crate) fn new(
+        pool: Pool<Postgres>,
+        projections: Vec<Box<dyn Projection>>,
+        config: ProjectionConfig,
+    ) -> Self {
+        Self {
+            pool,
+            projections,
+            config,
+        }
+    }
+
+    fn projection(&self, name: &str) -> Option<&dyn Projection> {
+        self.projections
+            .iter()
+            .find(|p| p.name() == name)
+            .map(|p| p.as_ref())
+    }
+
+    pub(crate) async fn list(&self) -> Result<Vec<ProjectionStatus>, sqlx::Error> {
+        sqlx::query_as(&format!("{} ORDER BY c.projection", STATUS_QUERY))
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    pub(crate) async fn status(&self, name: &str) -> Result<Option<ProjectionStatus>, sqlx::Error> {
+        sqlx::query_as(&format!("{} WHERE c.projection = $1", STATUS_QUERY))
+            .bind(name)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    /// Pauses or resumes a projection, returning its status; `None` when it
+    /// has no checkpoint. A batch in progress is completed.
+    pub(crate) async fn set_paused(
+        &self,
+        name: &str,
+        paused: bool,
+    ) -> Result<Option<ProjectionStatus>, sqlx::Error> {
+        let updated = sqlx::query(
+            "UPDATE projection_checkpoints SET paused = $2, updated_at = now()
+             WHERE projection = $1",
+        )
+        .bind(name)
+        .bind(paused)
+        .execute(&self.pool)
+        .await?;
+        if updated.rows_affected() == 0 {
+            return Ok(None);
+        }
+        self.status(name).await
+    }
+
+    /// The latest dead letters of a projection; `None` when no such
+    /// projection is known.
+    pub(crate) async fn dead_letters(
+        &self,
+        name: &str,
+        status: Option<DeadLetterStatus>,
+        limit: i64,
+    ) -> Result<Option<Vec<DeadLetter>>, sqlx::Error> {
+        if self.projection(name).is_none() {
+            return Ok(None);
+        }
+        let mut conn = self.pool.acquire().await?;
+        Ok(Some(
+            dead_letters::list(&mut conn, name, status, limit).await?,
+        ))
+    }
+
+    /// Replays a dead letter now, whatever its next attempt and even when
+    /// it is dead or the projection is paused; `None` when there is no such
+    /// dead letter left to replay. Waits for a batch in progress on any
+    /// replica.
+    pub(crate) async fn replay(
+        &self,
+        name: &str,
+        id: i64,
+    ) -> Result<Option<DeadLetter>, sqlx::Error> {
+        let Some(projection) = self.projection(name) else {
+            return Ok(None);
+        };
+
+        let mut tx = self.pool.begin().await?;
+        let Some(position) = sqlx::query_scalar::<_, i64>(
+            "SELECT global_position FROM projection_checkpoints WHERE projection = $1 FOR UPDATE",
+        )
+        .bind(name)
+        .fetch_optional(&mut *tx)
+        .await?
+        else {
+            return Ok(None);
+        };
+        let Some(letter) = dead_letters::find_open(&mut tx, name, id).await? else {
+            return Ok(None);
+        };
+        let letter =
+            dead_letters::replay(&mut tx, projection, &letter, position, &self.config).await?;
+        tx.commit().await?;
+        Ok(Some(letter))
+    }
+
+    /// Clears a projection's view and dead letters and rewinds its checkpoint
+    /// to the first event, so that it is rebuilt unless paused; `None` when no such
+    /// projection is known. Waits for a batch in progress on any replica.
+    /// Fails with [`ProjectionError::NotResettable`], changing nothing, for
+    /// views that must not be emptied.
+    pub(crate) async fn reset(
+        &self,
+        name: &str,
+    ) -> Result<Option<ProjectionStatus>, ProjectionError> {
+        let Some(projection) = self.projection(name) else {
+            return Ok(None);
+        };
+
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "INSERT INTO projection_checkpoints (projection) VALUES ($1)
+             ON CONFLICT (projection) DO NOTHING",
+        )
+        .bind(name)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("SELECT 1 FROM projection_checkpoints WHERE projection = $1 FOR UPDATE")
+            .bind(name)
+            .execute(&mut *tx)
+            .await?;
+        projection.reset(&mut tx).await?;
+        sqlx::query("DELETE FROM projection_dead_letters WHERE projection = $1")
+            .bind(name)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            "UPDATE projection_checkpoints
+             SET global_position = 0, updated_at = now(), error = NULL, errored_at = NULL
+             WHERE projection = $1",
+        )
+        .bind(name)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(self.status(name).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rocket::tokio;
+    use snowy_model::MemberId;
+
+    use super::*;
+    use crate::{
+        api::{cqrs::setup_cqrs, metrics::Metrics},
+        domain::commands::TeamCommand,
+        queries::{
+            member_index::MemberIndexProjection, notifier::latest_sequence,
+            team::TeamViewProjection,
+        },
+        testing,
+    };
+
+    async fn admin_with_members(pool: &Pool<Postgres>) -> ProjectionAdmin {
+        let cqrs = setup_cqrs(pool.clone(), &Metrics::new().unwrap());
+        for (team_id, member) in [("team-1", "alice"), ("team-1", "bob"), ("team-2", "carol")] {
+            let command = TeamCommand::AddMember {
+                member_id: MemberId::new(member.to_string()),
+                email: format!("{}@example.com", member),
+            };
+            cqrs.execute(team_id, command, HashMap::new())
+                .await
+                .unwrap();
+        }
+        ProjectionAdmin::new(
+            pool.clone(),
+            vec![
+                Box::new(TeamViewProjection),
+                Box::new(MemberIndexProjection),
+            ],
+            ProjectionConfig::default(),
+        )
+    }
+
+    async fn set_checkpoint(pool: &Pool<Postgres>, name: &str, global_position: i64) {
+        sqlx::query("UPDATE projection_checkpoints SET global_position = $2 WHERE projection = $1")
+            .bind(name)
+            .bind(global_position)
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL, see SNOWY_TEST_DATABASE_URL"]
+    async fn test_status_reports_the_lag() {
+        let pool = testing::database().await;
+        let admin = admin_with_members(&pool).await;
+        set_checkpoint(&pool, "team", 3).await;
+        set_checkpoint(&pool, "member_index", 1).await;
+
+        let team = admin.status("team").await.unwrap().unwrap();
+        assert_eq!((team.global_position, team.lag_events), (3, 0));
+        assert_eq!(team.lag_seconds, None);
+
+        let member_index = admin.status("member_index").await.unwrap().unwrap();
+        assert_eq!(member_index.lag_events, 2);
+        assert!(member_index.lag_seconds.unwrap() >= 0.0);
+        assert!(!member_index.paused);
+
+        let names: Vec<String> = admin
+            .list()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|status| status.projection)
+            .collect();
+        assert_eq!(
+            names,
+            vec!["forecast_history", "member_index", "team", "team_summary"]
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL, see SNOWY_TEST_DATABASE_URL"]
+    async fn test_pause_and_resume() {
+        let pool = testing::database().await;
+        let admin = admin_with_members(&pool).await;
+
+        assert!(
+            admin
+                .set_paused("team", true)
+                .await
+                .unwrap()
+                .unwrap()
+                .paused
+        );
+        assert!(admin.status("team").await.unwrap().unwrap().paused);
+        assert!(
+            !admin
+                .set_paused("team", false)
+                .await
+                .unwrap()
+                .unwrap()
+                .paused
+        );
+        assert!(admin.set_paused("unknown", true).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL, see SNOWY_TEST_DATABASE_URL"]
+    async fn test_reset_rewinds_the_checkpoint() {
+        let pool = testing::database().await;
+        let admin = admin_with_members(&pool).await;
+        testing::project(&pool, &MemberIndexProjection, "team-1").await;
+        set_checkpoint(&pool, "member_index", 3).await;
+
+        let status = admin.reset("member_index").await.unwrap().unwrap();
+
+        assert_eq!((status.global_position, status.lag_events), (0, 3));
+        let indexed: i64 = sqlx::query_scalar("SELECT count(*) FROM member_index")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(indexed, 0);
+        assert!(admin.reset("unknown").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL, see SNOWY_TEST_DATABASE_URL"]
+    async fn test_team_view_cannot_be_reset() {
+        let pool = testing::database().await;
+        let admin = admin_with_members(&pool).await;
+        testing::project(&pool, &TeamViewProjection, "team-1").await;
+        set_checkpoint(&pool, "team", 3).await;
+
+        assert!(matches!(
+            admin.reset("team").await,
+            Err(ProjectionError::NotResettable("team"))
+        ));
+
+        assert_eq!(latest_sequence(&pool, "team-1").await.unwrap(), Some(2));
+        assert_eq!(
+            admin.status("team").await.unwrap().unwrap().global_position,
+            3
+        );
+    }
+}
@@ -0,0 +1,374 @@
+use chrono::{DateTime, Utc};
+use rocket::FromFormField;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{Connection, PgConnection};
+use utoipa::ToSchema;
+
+use crate::api::config::ProjectionConfig;
+
+use super::{EventRow, Projection, ProjectionError};
+
+const DEAD_LETTER_COLUMNS: &str = "id, projection, aggregate_type, aggregate_id, global_position,
+     sequence, payload, error, attempts, status, created_at, last_attempt_at, next_attempt_at,
+     resolved_at";
+
+/// State of a dead letter.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Deserialize, Serialize, FromFormField, ToSchema, sqlx::Type,
+)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+pub(crate) enum DeadLetterStatus {
+    /// Retried automatically once due.
+    Pending,
+    /// Gave up after the maximum number of attempts; only replayed on request.
+    Dead,
+    Replayed,
+}
+
+/// An event a projection failed to apply. The later events of its aggregate
+/// are held back until it is replayed.
+#[derive(Debug, Serialize, sqlx::FromRow, ToSchema)]
+pub(crate) struct DeadLetter {
+    pub(crate) id: i64,
+    pub(crate) projection: String,
+    pub(crate) aggregate_type: String,
+    pub(crate) aggregate_id: String,
+    pub(crate) global_position: i64,
+    pub(crate) sequence: i64,
+    #[schema(value_type = Object)]
+    pub(crate) payload: Value,
+    /// Error of the last attempt.
+    pub(crate) error: String,
+    pub(crate) attempts: i32,
+    pub(crate) status: DeadLetterStatus,
+    pub(crate) created_at: DateTime<Utc>,
+    pub(crate) last_attempt_at: DateTime<Utc>,
+    pub(crate) next_attempt_at: DateTime<Utc>,
+    pub(crate) resolved_at: Option<DateTime<Utc>>,
+}
+
+/// Projects `rows` in a savepoint, rolled back when the projection fails.
+async fn try_project(
+    conn: &mut PgConnection,
+    projection: &dyn Projection,
+    rows: &[EventRow],
+) -> Result<Result<(), ProjectionError>, sqlx::Error> {
+    let mut savepoint = conn.begin().await?;
+    let result = match rows
+        .iter()
+        .map(EventRow::envelope)
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(events) => {
+            projection
+                .project(&mut savepoint, &rows[0].aggregate_id, &events)
+                .await
+        }
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(()) => savepoint.commit().await?,
+        Err(_) => savepoint.rollback().await?,
+    }
+    Ok(result)
+}
+
+/// Projects events of one aggregate, one at a time should they fail
+/// together, so that the events before a failing one are still applied.
+/// Returns the first event that cannot be projected, with its error.
+pub(super) async fn project_events<'r>(
+    conn: &mut PgConnection,
+    projection: &dyn Projection,
+    rows: &'r [EventRow],
+) -> Result<Option<(&'r EventRow, ProjectionError)>, sqlx::Error> {
+    if rows.is_empty() || try_project(conn, projection, rows).await?.is_ok() {
+        return Ok(None);
+    }
+    for row in rows {
+        if let Err(e) = try_project(conn, projection, std::slice::from_ref(row)).await? {
+            return Ok(Some((row, e)));
+        }
+    }
+    Ok(None)
+}
+
+/// Records the event a projection failed to apply, to be retried after the
+/// initial backoff.
+pub(super) async fn park(
+    conn: &mut PgConnection,
+    projection: &str,
+    row: &EventRow,
+    error: &ProjectionError,
+    config: &ProjectionConfig,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO projection_dead_letters
+             (projection, aggregate_type, aggregate_id, global_position, sequence, payload, error,
+              next_attempt_at)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, now() + make_interval(secs => $8))",
+    )
+    .bind(projection)
+    .bind(&row.aggregate_type)
+    .bind(&row.aggregate_id)
+    .bind(row.global_position)
+    .bind(row.sequence)
+    .bind(&row.payload)
+    .bind(error.to_string())
+    .bind(config.backoff(1).as_secs_f64())
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// The aggregates whose events the projection currently holds back.
+pub(super) async fn blocked_aggregates(
+    conn: &mut PgConnection,
+    projection: &str,
+) -> Result<Vec<(String, String)>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT aggregate_type, aggregate_id FROM projection_dead_letters
+         WHERE projection = $1 AND status <> 'replayed'",
+    )
+    .bind(projection)
+    .fetch_all(conn)
+    .await
+}
+
+/// The pending dead letters of the projection whose retry is due.
+pub(super) async fn due(
+    conn: &mut PgConnection,
+    projection: &str,
+    limit: i64,
+) -> Result<Vec<DeadLetter>, sqlx::Error> {
+    sqlx::query_as(&format!(
+        "SELECT {} FROM projection_dead_letters
+         WHERE projection = $1 AND status = 'pending' AND next_attempt_at <= now()
+         ORDER BY next_attempt_at
+         LIMIT $2",
+        DEAD_LETTER_COLUMNS
+    ))
+    .bind(projection)
+    .bind(limit)
+    .fetch_all(conn)
+    .await
+}
+
+pub(super) async fn find_open(
+    conn: &mut PgConnection,
+    projection: &str,
+    id: i64,
+) -> Result<Option<DeadLetter>, sqlx::Error> {
+    sqlx::query_as(&format!(
+        "SELECT {} FROM projection_dead_letters
+         WHERE id = $1 AND projection = $2 AND status <> 'replayed'
+         FOR UPDATE",
+        DEAD_LETTER_COLUMNS
+    ))
+    .bind(id)
+    .bind(projection)
+    .fetch_optional(conn)
+    .await
+}
+
+pub(super) async fn list(
+    conn: &mut PgConnection,
+    projection: &str,
+    status: Option<DeadLetterStatus>,
+    limit: i64,
+) -> Result<Vec<DeadLetter>, sqlx::Error> {
+    sqlx::query_as(&format!(
+        "SELECT {} FROM projection_dead_letters
+         WHERE projection = $1 AND ($2::text IS NULL OR status = $2)
+         ORDER BY id DESC
+         LIMIT $3",
+        DEAD_LETTER_COLUMNS
+    ))
+    .bind(projection)
+    .bind(status)
+    .bind(limit)
+    .fetch_all(conn)
+    .await
+}
+
+/// Projects the dead letter's event and the held back events of its
+/// aggregate, up to the checkpoint `position`, resolving the dead letter when
+/// they all apply. Otherwise the dead letter moves on to the event now
+/// failing, or counts another attempt, and is retried after a backoff until
+/// the attempts run out.
+pub(super) async fn replay(
+    conn: &mut PgConnection,
+    projection: &dyn Projection,
+    letter: &DeadLetter,
+    position: i64,
+    config: &ProjectionConfig,
+) -> Result<DeadLetter, sqlx::Error> {
+    let rows = sqlx::query_as::<_, EventRow>(
+        "SELECT global_position, aggregate_type, aggregate_id, sequence, payload, metadata
+         FROM events
+         WHERE aggregate_type = $1 AND aggregate_id = $2
+           AND global_position >= $3 AND global_position <= $4
+         ORDER BY global_position",
+    )
+    .bind(&letter.aggregate_type)
+    .bind(&letter.aggregate_id)
+    .bind(letter.global_position)
+    .bind(position)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let Some((row, error)) = project_events(conn, projection, &rows).await? else {
+        return sqlx::query_as(&format!(
+            "UPDATE projection_dead_letters
+             SET status = 'replayed', attempts = attempts + 1, last_attempt_at = now(),
+                 resolved_at = now()
+             WHERE id = $1
+             RETURNING {}",
+            DEAD_LETTER_COLUMNS
+        ))
+        .bind(letter.id)
+        .fetch_one(conn)
+        .await;
+    };
+
+    let attempts = if row.global_position == letter.global_position {
+        letter.attempts + 1
+    } else {
+        1
+    };
+    let status = if attempts as u32 >= config.max_attempts {
+        DeadLetterStatus::Dead
+    } else {
+        DeadLetterStatus::Pending
+    };
+    sqlx::query_as(&format!(
+        "UPDATE projection_dead_letters
+         SET global_position = $2, sequence = $3, payload = $4, error = $5, attempts = $6,
+             status = $7, last_attempt_at = now(),
+             next_attempt_at = now() + make_interval(secs => $8)
+         WHERE id = $1
+         RETURNING {}",
+        DEAD_LETTER_COLUMNS
+    ))
+    .bind(letter.id)
+    .bind(row.global_position)
+    .bind(row.sequence)
+    .bind(&row.payload)
+    .bind(error.to_string())
+    .bind(attempts)
+    .bind(status)
+    .bind(config.backoff(attempts as u32).as_secs_f64())
+    .fetch_one(conn)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rocket::tokio;
+    use snowy_model::MemberId;
+    use sqlx::{Pool, Postgres};
+
+    use super::*;
+    use crate::{
+        api::{cqrs::setup_cqrs, metrics::Metrics},
+        domain::commands::TeamCommand,
+        testing::{self, FlakyProjection},
+    };
+
+    async fn events(pool: &Pool<Postgres>) -> Vec<EventRow> {
+        sqlx::query_as(
+            "SELECT global_position, aggregate_type, aggregate_id, sequence, payload, metadata
+             FROM events ORDER BY global_position",
+        )
+        .fetch_all(pool)
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL, see SNOWY_TEST_DATABASE_URL"]
+    async fn test_park_and_replay_until_resolved() {
+        let pool = testing::database().await;
+        let cqrs = setup_cqrs(pool.clone(), &Metrics::new().unwrap());
+        for member in ["alice", "bob", "carol"] {
+            let command = TeamCommand::AddMember {
+                member_id: MemberId::new(member.to_string()),
+                email: format!("{}@example.com", member),
+            };
+            cqrs.execute("team-1", command, HashMap::new())
+                .await
+                .unwrap();
+        }
+        let flaky = FlakyProjection::new(&pool).await;
+        flaky.set_failing("team-1", 1, true);
+        flaky.set_failing("team-1", 2, true);
+        let config = ProjectionConfig {
+            max_attempts: 3,
+            initial_backoff_secs: 0,
+            ..ProjectionConfig::default()
+        };
+        let mut conn = pool.acquire().await.unwrap();
+
+        let rows = events(&pool).await;
+        let (row, error) = project_events(&mut conn, &flaky, &rows)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(row.sequence, 1);
+        park(&mut conn, "flaky", row, &error, &config)
+            .await
+            .unwrap();
+        let letter = list(&mut conn, "flaky", None, 10).await.unwrap().remove(0);
+        assert_eq!((letter.sequence, letter.attempts), (1, 1));
+        assert_eq!(letter.status, DeadLetterStatus::Pending);
+        assert!(FlakyProjection::applied(&pool, "team-1").await.is_empty());
+
+        // The dead letter moves on to the event now failing.
+        flaky.set_failing("team-1", 1, false);
+        let letter = replay(&mut conn, &flaky, &letter, 3, &config)
+            .await
+            .unwrap();
+        assert_eq!((letter.sequence, letter.attempts), (2, 1));
+        assert_eq!(letter.status, DeadLetterStatus::Pending);
+        assert_eq!(FlakyProjection::applied(&pool, "team-1").await, vec![1]);
+
+        // Failing again counts attempts until they run out.
+        let letter = replay(&mut conn, &flaky, &letter, 3, &config)
+            .await
+            .unwrap();
+        assert_eq!(letter.attempts, 2);
+        assert_eq!(due(&mut conn, "flaky", 10).await.unwrap().len(), 1);
+        let letter = replay(&mut conn, &flaky, &letter, 3, &config)
+            .await
+            .unwrap();
+        assert_eq!(letter.attempts, 3);
+        assert_eq!(letter.status, DeadLetterStatus::Dead);
+        assert!(due(&mut conn, "flaky", 10).await.unwrap().is_empty());
+        assert_eq!(
+            blocked_aggregates(&mut conn, "flaky").await.unwrap(),
+            vec![("team".to_string(), "team-1".to_string())]
+        );
+
+        flaky.set_failing("team-1", 2, false);
+        let letter = replay(&mut conn, &flaky, &letter, 3, &config)
+            .await
+            .unwrap();
+        assert_eq!(letter.status, DeadLetterStatus::Replayed);
+        assert!(letter.resolved_at.is_some());
+        assert_eq!(
+            FlakyProjection::applied(&pool, "team-1").await,
+            vec![1, 2, 3]
+        );
+        assert!(find_open(&mut conn, "flaky", letter.id)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(blocked_aggregates(&mut conn, "flaky")
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,130 @@
+mod admin;
+mod dead_letters;
+mod runner;
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use cqrs_es::EventEnvelope;
+use serde_json::Value;
+use sqlx::PgConnection;
+
+use crate::domain::aggregates::Team;
+
+pub(crate) use self::{
+    admin::{ProjectionAdmin, ProjectionStatus},
+    dead_letters::{DeadLetter, DeadLetterStatus},
+    runner::ProjectionRunner,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum ProjectionError {
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("Malformed event {aggregate_id}/{sequence}: {source}")]
+    MalformedEvent {
+        aggregate_id: String,
+        sequence: i64,
+        source: serde_json::Error,
+    },
+    #[error("The '{0}' view guards read access and cannot be reset")]
+    NotResettable(&'static str),
+    #[error("Publishing error: {0}")]
+    Publish(#[from] crate::publishing::PublishError),
+}
+
+/// A read model built asynchronously from the `events` table by the
+/// [`ProjectionRunner`].
+#[async_trait]
+pub(crate) trait Projection: Send + Sync {
+    /// Key of the projection's checkpoint.
+    fn name(&self) -> &'static str;
+
+    /// Applies events of one aggregate, in sequence order. `conn` is the
+    /// transaction that advances the checkpoint, so writes made through it
+    /// are applied exactly once.
+    async fn project(
+        &self,
+        conn: &mut PgConnection,
+        aggregate_id: &str,
+        events: &[EventEnvelope<Team>],
+    ) -> Result<(), ProjectionError>;
+
+    /// Deletes everything the projection wrote, so that it can be rebuilt
+    /// from the first event, or refuses with
+    /// [`ProjectionError::NotResettable`] when the view must not be emptied.
+    async fn reset(&self, conn: &mut PgConnection) -> Result<(), ProjectionError>;
+}
+
+/// A row of the `events` table.
+#[derive(Debug, sqlx::FromRow)]
+struct EventRow {
+    global_position: i64,
+    aggregate_type: String,
+    aggregate_id: String,
+    sequence: i64,
+    payload: Value,
+    metadata: Value,
+}
+
+impl EventRow {
+    fn envelope(&self) -> Result<EventEnvelope<Team>, ProjectionError> {
+        let malformed = |source| ProjectionError::MalformedEvent {
+            aggregate_id: self.aggregate_id.clone(),
+            sequence: self.sequence,
+            source,
+        };
+        Ok(EventEnvelope {
+            aggregate_id: self.aggregate_id.clone(),
+            sequence: self.sequence as usize,
+            payload: serde_json::from_value(self.payload.clone()).map_err(malformed)?,
+            metadata: serde_json::from_value::<HashMap<String, String>>(self.metadata.clone())
+                .map_err(malformed)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use snowy_model::MemberId;
+
+    use super::*;
+    use crate::domain::events::TeamEvent;
+
+    fn row(payload: Value) -> EventRow {
+        EventRow {
+            global_position: 12,
+            aggregate_type: "team".to_string(),
+            aggregate_id: "team-1".to_string(),
+            sequence: 3,
+            payload,
+            metadata: json!({"actor": "member:alice"}),
+        }
+    }
+
+    #[test]
+    fn test_envelope_from_row() {
+        let envelope = row(json!({"MemberRemoved": {"member_id": "bob"}}))
+            .envelope()
+            .unwrap();
+
+        assert_eq!(envelope.aggregate_id, "team-1");
+        assert_eq!(envelope.sequence, 3);
+        assert_eq!(
+            envelope.payload,
+            TeamEvent::MemberRemoved {
+                member_id: MemberId::new("bob".to_string())
+            }
+        );
+        assert_eq!(envelope.metadata["actor"], "member:alice");
+    }
+
+    #[test]
+    fn test_malformed_payload() {
+        assert!(matches!(
+            row(json!({"Unknown": {}})).envelope(),
+            Err(ProjectionError::MalformedEvent { sequence: 3, .. })
+        ));
+    }
+}
@@ -0,0 +1,505 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+    time::Duration,
+};
+
+use cqrs_es::Aggregate;
+use rocket::{tokio::select, Shutdown};
+use sqlx::{postgres::PgListener, PgConnection, Pool, Postgres};
+use tracing::{error, info, info_span, warn, Instrument};
+
+use crate::{api::config::ProjectionConfig, domain::aggregates::Team};
+
+use super::{
+    dead_letters::{self, DeadLetterStatus},
+    EventRow, Projection, ProjectionError,
+};
+
+/// Channel notified by a trigger whenever events are inserted.
+const EVENTS_CHANNEL: &str = "events";
+
+/// Delay between catch-ups while a projection waits at a gap in positions.
+const GAP_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The missing positions up to `position` can no longer be filled once the
+/// transactions in progress in `snapshot`, taken after reading it, have ended.
+struct Horizon {
+    position: i64,
+    snapshot: String,
+}
+
+/// Keeps projections up to date with the `events` table.
+///
+/// Each projection reads events past its checkpoint in `global_position`
+/// order and advances the checkpoint in the same transaction. The checkpoint
+/// row is locked while a batch is projected, so with several replicas each
+/// batch is projected by exactly one of them. Positions are taken from a
+/// sequence by concurrent transactions, so they may become visible out of
+/// order: a projection stops at a gap in positions until the transactions
+/// that could fill it have ended. Paused projections are skipped,
+/// and the error of a failed batch is recorded on its checkpoint until a
+/// later batch succeeds. Events a projection fails to apply become dead
+/// letters, retried with backoff.
+pub(crate) struct ProjectionRunner {
+    pool: Pool<Postgres>,
+    projections: Vec<Box<dyn Projection>>,
+    config: ProjectionConfig,
+    /// Per projection waiting at a gap, up to where it may skip missing
+    /// positions once settled.
+    horizons: Mutex<HashMap<&'static str, Horizon>>,
+}
+
+impl ProjectionRunner {
+    pub(crate) fn new(
+        pool: Pool<Postgres>,
+        projections: Vec<Box<dyn Projection>>,
+        config: ProjectionConfig,
+    ) -> Self {
+        Self {
+            pool,
+            projections,
+            config,
+            horizons: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Catches up, then again whenever events are inserted, or at the poll
+    /// interval should a notification be missed, until the server shuts down.
+    pub(crate) async fn run(self, mut shutdown: Shutdown) {
+        let poll_interval = Duration::from_millis(self.config.poll_interval_ms);
+        if let Err(e) = self.register().await {
+            error!(error = %e, "cannot register projection checkpoints");
+        }
+        let mut listener = match listen(&self.pool).await {
+            Ok(listener) => Some(listener),
+            Err(e) => {
+                error!(error = %e, "cannot listen for events, polling instead");
+                None
+            }
+        };
+
+        loop {
+            self.catch_up().await;
+            let waiting_at_gap = !self.horizons().is_empty();
+            select! {
+                _ = &mut shutdown => break,
+                _ = tokio::time::sleep(if waiting_at_gap { GAP_POLL_INTERVAL } else { poll_interval }) => {}
+                lost = next_notification(&mut listener) => {
+                    if let Some(e) = lost {
+                        error!(error = %e, "lost the events listener connection");
+                        tokio::time::sleep(poll_interval).await;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Creates the checkpoints of new projections, starting from the first event.
+    async fn register(&self) -> Result<(), sqlx::Error> {
+        for projection in &self.projections {
+            sqlx::query(
+                "INSERT INTO projection_checkpoints (projection) VALUES ($1)
+                 ON CONFLICT (projection) DO NOTHING",
+            )
+            .bind(projection.name())
+            .execute(&self.pool)
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn catch_up(&self) {
+        for projection in &self.projections {
+            let span = info_span!("projection", projection = projection.name());
+            loop {
+                match self
+                    .step(projection.as_ref())
+                    .instrument(span.clone())
+                    .await
+                {
+                    Ok(0) => break,
+                    Ok(_) => continue,
+                    Err(e) => {
+                        self.fail(projection.name(), e).await;
+                        break;
+                    }
+                }
+            }
+            if let Err(e) = self.retry(projection.as_ref()).instrument(span).await {
+                self.fail(projection.name(), e).await;
+            }
+        }
+    }
+
+    fn horizons(&self) -> std::sync::MutexGuard<'_, HashMap<&'static str, Horizon>> {
+        self.horizons
+            .lock()
+            .expect("projection horizons lock poisoned")
+    }
+
+    /// Projects the next batch of events, returning how many were read: none
+    /// when caught up, when waiting at a gap, or when another replica holds
+    /// the checkpoint. An event that fails is recorded as a dead letter,
+    /// holding back the later events of its aggregate, and the batch carries
+    /// on with the other aggregates.
+    async fn step(&self, projection: &dyn Projection) -> Result<usize, ProjectionError> {
+        let mut tx = self.pool.begin().await?;
+        let horizon = self.horizons().remove(projection.name());
+        let Some(position) = lock_checkpoint(&mut tx, projection.name()).await? else {
+            return Ok(0);
+        };
+
+        // Checked before reading, so that events committed meanwhile are read.
+        let mut skippable = position;
+        if let Some(horizon) = horizon.filter(|horizon| horizon.position > position) {
+            if settled(&mut tx, &horizon.snapshot).await? {
+                skippable = horizon.position;
+            } else {
+                self.horizons().insert(projection.name(), horizon);
+            }
+        }
+
+        let rows = sqlx::query_as::<_, EventRow>(
+            "SELECT global_position, aggregate_type, aggregate_id, sequence, payload, metadata
+             FROM events
+             WHERE global_position > $1
+             ORDER BY global_position
+             LIMIT $2",
+        )
+        .bind(position)
+        .bind(self.config.batch_size)
+        .fetch_all(&mut *tx)
+        .await?;
+        let ready = contiguous(position, skippable, &rows);
+        if ready < rows.len() && !self.horizons().contains_key(projection.name()) {
+            let snapshot: String = sqlx::query_scalar("SELECT pg_current_snapshot()::text")
+                .fetch_one(&mut *tx)
+                .await?;
+            let horizon = Horizon {
+                position: rows[rows.len() - 1].global_position,
+                snapshot,
+            };
+            self.horizons().insert(projection.name(), horizon);
+        }
+        let rows = &rows[..ready];
+        let Some(last) = rows.last().map(|row| row.global_position) else {
+            return Ok(0);
+        };
+
+        let mut blocked: HashSet<(String, String)> =
+            dead_letters::blocked_aggregates(&mut tx, projection.name())
+                .await?
+                .into_iter()
+                .collect();
+        for run in rows.chunk_by(|a, b| {
+            a.aggregate_type == b.aggregate_type && a.aggregate_id == b.aggregate_id
+        }) {
+            let key = (run[0].aggregate_type.clone(), run[0].aggregate_id.clone());
+            if key.0 != Team::aggregate_type() || blocked.contains(&key) {
+                continue;
+            }
+            if let Some((row, e)) = dead_letters::project_events(&mut tx, projection, run).await? {
+                warn!(
+                    aggregate_id = %row.aggregate_id,
+                    sequence = row.sequence,
+                    error = %e,
+                    "recording a dead letter"
+                );
+                dead_letters::park(&mut tx, projection.name(), row, &e, &self.config).await?;
+                blocked.insert(key);
+            }
+        }
+
+        sqlx::query(
+            "UPDATE projection_checkpoints
+             SET global_position = $2, updated_at = now(), error = NULL, errored_at = NULL
+             WHERE projection = $1",
+        )
+        .bind(projection.name())
+        .bind(last)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(rows.len())
+    }
+
+    /// Replays the dead letters whose retry is due.
+    async fn retry(&self, projection: &dyn Projection) -> Result<(), ProjectionError> {
+        let mut tx = self.pool.begin().await?;
+        let Some(position) = lock_checkpoint(&mut tx, projection.name()).await? else {
+            return Ok(());
+        };
+        let due = dead_letters::due(&mut tx, projection.name(), self.config.batch_size).await?;
+        for letter in &due {
+            let letter =
+                dead_letters::replay(&mut tx, projection, letter, position, &self.config).await?;
+            if letter.status == DeadLetterStatus::Replayed {
+                info!(id = letter.id, aggregate_id = %letter.aggregate_id, "replayed a dead letter");
+            }
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Logs the error and records it on the checkpoint.
+    async fn fail(&self, name: &str, error: ProjectionError) {
+        error!(projection = name, error = %error, "projection failed");
+        let recorded = sqlx::query(
+            "UPDATE projection_checkpoints SET error = $2, errored_at = now()
+             WHERE projection = $1",
+        )
+        .bind(name)
+        .bind(error.to_string())
+        .execute(&self.pool)
+        .await;
+        if let Err(e) = recorded {
+            error!(error = %e, "cannot record the projection error");
+        }
+    }
+}
+
+/// How many of `rows`, read past `position`, follow each other without a gap,
+/// besides missing positions up to `skippable`.
+fn contiguous(position: i64, skippable: i64, rows: &[EventRow]) -> usize {
+    let mut expected = position + 1;
+    for (index, row) in rows.iter().enumerate() {
+        if row.global_position != expected && row.global_position - 1 > skippable {
+            return index;
+        }
+        expected = row.global_position + 1;
+    }
+    rows.len()
+}
+
+/// Whether every transaction in progress in `snapshot` has ended.
+async fn settled(conn: &mut PgConnection, snapshot: &str) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT NOT EXISTS (
+             SELECT 1 FROM pg_snapshot_xip($1::pg_snapshot) AS xid
+             WHERE pg_xact_status(xid) = 'in progress'
+         )",
+    )
+    .bind(snapshot)
+    .fetch_one(conn)
+    .await
+}
+
+/// Locks the checkpoint of a running projection, returning its position;
+/// `None` when paused or when another replica holds it.
+async fn lock_checkpoint(conn: &mut PgConnection, name: &str) -> Result<Option<i64>, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT global_position FROM projection_checkpoints
+         WHERE projection = $1 AND NOT paused
+         FOR UPDATE SKIP LOCKED",
+    )
+    .bind(name)
+    .fetch_optional(conn)
+    .await
+}
+
+async fn listen(pool: &Pool<Postgres>) -> Result<PgListener, sqlx::Error> {
+    let mut listener = PgListener::connect_with(pool).await?;
+    listener.listen(EVENTS_CHANNEL).await?;
+    Ok(listener)
+}
+
+/// Waits for the next notification, yielding the error if the connection is
+/// lost; it is re-established on the following call. Never completes
+/// without a listener.
+async fn next_notification(listener: &mut Option<PgListener>) -> Option<sqlx::Error> {
+    match listener {
+        Some(listener) => listener.recv().await.err(),
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rocket::tokio;
+    use serde_json::json;
+    use snowy_model::MemberId;
+    use sqlx::{Connection, Executor};
+
+    use super::*;
+    use crate::{
+        api::{cqrs::setup_cqrs, metrics::Metrics},
+        domain::commands::TeamCommand,
+        queries::{notifier::latest_sequence, team::TeamViewProjection},
+        testing::{self, FlakyProjection},
+    };
+
+    fn row(global_position: i64) -> EventRow {
+        EventRow {
+            global_position,
+            aggregate_type: "team".to_string(),
+            aggregate_id: "team-1".to_string(),
+            sequence: global_position,
+            payload: json!({}),
+            metadata: json!({}),
+        }
+    }
+
+    fn runner(pool: &Pool<Postgres>) -> ProjectionRunner {
+        ProjectionRunner::new(
+            pool.clone(),
+            vec![Box::new(TeamViewProjection)],
+            ProjectionConfig::default(),
+        )
+    }
+
+    async fn add_member(pool: &Pool<Postgres>, team_id: &str, member: &str) {
+        let command = TeamCommand::AddMember {
+            member_id: MemberId::new(member.to_string()),
+            email: format!("{}@example.com", member),
+        };
+        setup_cqrs(pool.clone(), &Metrics::new().unwrap())
+            .execute(team_id, command, HashMap::new())
+            .await
+            .unwrap();
+    }
+
+    /// Inserts the first event of `team_id` on `conn`, as a command would.
+    async fn insert_member_added(conn: &mut PgConnection, team_id: &str) {
+        sqlx::query(
+            "INSERT INTO events (aggregate_type, aggregate_id, sequence, event_type, event_version,
+                                 payload, metadata)
+             VALUES ('team', $1, 1, 'MemberAdded', '1.0', $2, '{}')",
+        )
+        .bind(team_id)
+        .bind(json!({"MemberAdded": {"member_id": "alice", "email": "alice@example.com"}}))
+        .execute(conn)
+        .await
+        .unwrap();
+    }
+
+    async fn checkpoint(pool: &Pool<Postgres>) -> i64 {
+        sqlx::query_scalar(
+            "SELECT global_position FROM projection_checkpoints WHERE projection = 'team'",
+        )
+        .fetch_one(pool)
+        .await
+        .unwrap()
+    }
+
+    /// Steps until events past a gap are projected. Transactions of other
+    /// databases on the server, such as those of concurrent tests, may be in
+    /// the snapshot taken at the gap and hold it up for a moment.
+    async fn step_past_gap(runner: &ProjectionRunner) -> usize {
+        for _ in 0..100 {
+            match runner.step(&TeamViewProjection).await.unwrap() {
+                0 => tokio::time::sleep(Duration::from_millis(20)).await,
+                projected => return projected,
+            }
+        }
+        panic!("the gap was never settled");
+    }
+
+    #[test]
+    fn test_contiguous_rows() {
+        let rows: Vec<EventRow> = [4, 5, 7, 8, 11].into_iter().map(row).collect();
+
+        assert_eq!(contiguous(3, 3, &rows), 2);
+        assert_eq!(contiguous(2, 2, &rows), 0);
+        assert_eq!(contiguous(3, 8, &rows), 4);
+        assert_eq!(contiguous(3, 11, &rows), 5);
+        assert_eq!(contiguous(3, 3, &[]), 0);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL, see SNOWY_TEST_DATABASE_URL"]
+    async fn test_step_projects_past_the_checkpoint() {
+        let pool = testing::database().await;
+        let runner = runner(&pool);
+        runner.register().await.unwrap();
+        add_member(&pool, "team-1", "alice").await;
+        add_member(&pool, "team-1", "bob").await;
+
+        assert_eq!(runner.step(&TeamViewProjection).await.unwrap(), 2);
+        assert_eq!(runner.step(&TeamViewProjection).await.unwrap(), 0);
+
+        assert_eq!(checkpoint(&pool).await, 2);
+        assert_eq!(latest_sequence(&pool, "team-1").await.unwrap(), Some(2));
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL, see SNOWY_TEST_DATABASE_URL"]
+    async fn test_step_waits_at_gaps_until_their_transactions_end() {
+        let pool = testing::database().await;
+        let runner = runner(&pool);
+        runner.register().await.unwrap();
+
+        // Takes position 1, but commits after position 2.
+        let mut late = pool.acquire().await.unwrap();
+        let mut late = late.begin().await.unwrap();
+        insert_member_added(&mut late, "team-1").await;
+        add_member(&pool, "team-2", "bob").await;
+
+        assert_eq!(runner.step(&TeamViewProjection).await.unwrap(), 0);
+        assert_eq!(runner.step(&TeamViewProjection).await.unwrap(), 0);
+        late.commit().await.unwrap();
+        assert_eq!(step_past_gap(&runner).await, 2);
+        assert_eq!(checkpoint(&pool).await, 2);
+        assert!(runner.horizons().is_empty());
+
+        // Takes position 3, then rolls back.
+        let mut aborted = pool.acquire().await.unwrap();
+        aborted.execute("BEGIN").await.unwrap();
+        insert_member_added(&mut aborted, "team-3").await;
+        add_member(&pool, "team-4", "carol").await;
+
+        assert_eq!(runner.step(&TeamViewProjection).await.unwrap(), 0);
+        aborted.execute("ROLLBACK").await.unwrap();
+        assert_eq!(step_past_gap(&runner).await, 1);
+        assert_eq!(checkpoint(&pool).await, 4);
+        assert_eq!(latest_sequence(&pool, "team-3").await.unwrap(), None);
+        assert_eq!(latest_sequence(&pool, "team-4").await.unwrap(), Some(1));
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL, see SNOWY_TEST_DATABASE_URL"]
+    async fn test_dead_letters_hold_back_their_aggregate() {
+        let pool = testing::database().await;
+        let flaky = FlakyProjection::new(&pool).await;
+        let config = ProjectionConfig {
+            initial_backoff_secs: 0,
+            ..ProjectionConfig::default()
+        };
+        let runner = ProjectionRunner::new(pool.clone(), vec![], config);
+        add_member(&pool, "team-1", "alice").await;
+        add_member(&pool, "team-1", "bob").await;
+        add_member(&pool, "team-2", "carol").await;
+        add_member(&pool, "team-1", "dave").await;
+        flaky.set_failing("team-1", 2, true);
+
+        assert_eq!(runner.step(&flaky).await.unwrap(), 4);
+        assert_eq!(FlakyProjection::applied(&pool, "team-1").await, vec![1]);
+        assert_eq!(FlakyProjection::applied(&pool, "team-2").await, vec![1]);
+
+        // Later events of the aggregate wait, the others carry on.
+        add_member(&pool, "team-1", "erin").await;
+        add_member(&pool, "team-2", "frank").await;
+        assert_eq!(runner.step(&flaky).await.unwrap(), 2);
+        assert_eq!(FlakyProjection::applied(&pool, "team-1").await, vec![1]);
+        assert_eq!(FlakyProjection::applied(&pool, "team-2").await, vec![1, 2]);
+
+        runner.retry(&flaky).await.unwrap();
+        assert_eq!(FlakyProjection::applied(&pool, "team-1").await, vec![1]);
+
+        flaky.set_failing("team-1", 2, false);
+        runner.retry(&flaky).await.unwrap();
+        assert_eq!(
+            FlakyProjection::applied(&pool, "team-1").await,
+            vec![1, 2, 3, 4]
+        );
+        let (status, attempts): (DeadLetterStatus, i32) = sqlx::query_as(
+            "SELECT status, attempts FROM projection_dead_letters WHERE projection = 'flaky'",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!((status, attempts), (DeadLetterStatus::Replayed, 3));
+    }
+}
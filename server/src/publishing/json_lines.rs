@@ -0,0 +1,87 @@
+use std::path::Path;
+
+use async_trait::async_trait;
+use tokio::{
+    fs::{File, OpenOptions},
+    io::AsyncWriteExt,
+    sync::Mutex,
+};
+
+use super::{EventPublisher, PublishError, PublishedEvent};
+
+/// Appends each event to a file as one line of JSON.
+pub(crate) struct JsonLinesPublisher {
+    file: Mutex<File>,
+}
+
+impl JsonLinesPublisher {
+    pub(crate) async fn open(path: impl AsRef<Path>) -> Result<Self, PublishError> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+}
+
+#[async_trait]
+impl EventPublisher for JsonLinesPublisher {
+    async fn publish(&self, event: &PublishedEvent) -> Result<(), PublishError> {
+        let mut line = serde_json::to_vec(event)?;
+        line.push(b'\n');
+        let mut file = self.file.lock().await;
+        file.write_all(&line).await?;
+        file.flush().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use snowy_model::MemberId;
+
+    use super::*;
+    use crate::domain::events::TeamEvent;
+
+    fn event(sequence: usize, id: &str) -> PublishedEvent {
+        PublishedEvent {
+            aggregate_type: "team".to_string(),
+            aggregate_id: "team-1".to_string(),
+            sequence,
+            event_type: "member-added".to_string(),
+            event_version: "1.0".to_string(),
+            payload: TeamEvent::MemberAdded {
+                member_id: MemberId::new(id.to_string()),
+                email: format!("{}@example.com", id),
+            },
+            metadata: HashMap::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_appends_events_as_json_lines() {
+        let path =
+            std::env::temp_dir().join(format!("snowy-events-{}.jsonl", uuid::Uuid::new_v4()));
+        let published = vec![event(1, "alice"), event(2, "bob")];
+
+        let publisher = JsonLinesPublisher::open(&path).await.unwrap();
+        publisher.publish(&published[0]).await.unwrap();
+        // Reopened files are appended to.
+        let publisher = JsonLinesPublisher::open(&path).await.unwrap();
+        publisher.publish(&published[1]).await.unwrap();
+
+        let contents = tokio::fs::read_to_string(&path).await.unwrap();
+        tokio::fs::remove_file(&path).await.unwrap();
+        let events: Vec<PublishedEvent> = contents
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(events, published);
+    }
+}
@@ -0,0 +1,219 @@
+#[cfg(feature = "amqp")]
+mod amqp;
+mod json_lines;
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use cqrs_es::{Aggregate, EventEnvelope};
+use serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
+use tokio::sync::Mutex;
+
+use crate::{
+    api::config::PublishingConfig,
+    domain::{aggregates::Team, events::TeamEvent},
+    projections::{Projection, ProjectionError},
+};
+
+#[cfg(feature = "amqp")]
+pub(crate) use self::amqp::AmqpPublisher;
+pub(crate) use self::json_lines::JsonLinesPublisher;
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum PublishError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+    #[cfg(feature = "amqp")]
+    #[error("AMQP error: {0}")]
+    Amqp(#[from] lapin::Error),
+    #[cfg(feature = "amqp")]
+    #[error("The broker did not accept event {0}")]
+    Rejected(String),
+    #[cfg(not(feature = "amqp"))]
+    #[error("{0}")]
+    Unsupported(String),
+}
+
+/// A team event as published to a sink.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub(crate) struct PublishedEvent {
+    pub(crate) aggregate_type: String,
+    pub(crate) aggregate_id: String,
+    pub(crate) sequence: usize,
+    pub(crate) event_type: String,
+    pub(crate) event_version: String,
+    pub(crate) payload: TeamEvent,
+    pub(crate) metadata: HashMap<String, String>,
+}
+
+impl From<&EventEnvelope<Team>> for PublishedEvent {
+    fn from(envelope: &EventEnvelope<Team>) -> Self {
+        use cqrs_es::DomainEvent;
+
+        Self {
+            aggregate_type: Team::aggregate_type(),
+            aggregate_id: envelope.aggregate_id.clone(),
+            sequence: envelope.sequence,
+            event_type: envelope.payload.event_type(),
+            event_version: envelope.payload.event_version(),
+            payload: envelope.payload.clone(),
+            metadata: envelope.metadata.clone(),
+        }
+    }
+}
+
+/// A sink for team events, such as a file or a message broker.
+#[async_trait]
+pub(crate) trait EventPublisher: Send + Sync {
+    /// Publishes one event. Events of an aggregate are published one at a
+    /// time, in sequence order.
+    async fn publish(&self, event: &PublishedEvent) -> Result<(), PublishError>;
+}
+
+/// Connects to the sink selected in the configuration.
+pub(crate) async fn connect(
+    config: &PublishingConfig,
+) -> Result<Box<dyn EventPublisher>, PublishError> {
+    match config {
+        PublishingConfig::JsonLines { path } => Ok(Box::new(JsonLinesPublisher::open(path).await?)),
+        #[cfg(feature = "amqp")]
+        PublishingConfig::Amqp { uri, exchange } => {
+            Ok(Box::new(AmqpPublisher::connect(uri, exchange).await?))
+        }
+        #[cfg(not(feature = "amqp"))]
+        PublishingConfig::Amqp { .. } => Err(PublishError::Unsupported(
+            "AMQP publishing requires the `amqp` feature".to_string(),
+        )),
+    }
+}
+
+/// Publishes team events through the [`EventPublisher`] selected in the
+/// configuration, connected on first use.
+///
+/// As a projection it publishes each event once the events before it were,
+/// resuming from its checkpoint after a restart. An event that cannot be
+/// published becomes a dead letter retried with backoff, holding back the
+/// later events of its team; the publisher is reconnected before the next
+/// attempt. Events are published at least once: consumers may see an event
+/// again when a retry follows a partial failure, identified by its aggregate
+/// id and sequence.
+pub(crate) struct PublishingProjection {
+    config: PublishingConfig,
+    publisher: Mutex<Option<Box<dyn EventPublisher>>>,
+}
+
+impl PublishingProjection {
+    pub(crate) fn new(config: PublishingConfig) -> Self {
+        Self {
+            config,
+            publisher: Mutex::new(None),
+        }
+    }
+}
+
+#[async_trait]
+impl Projection for PublishingProjection {
+    fn name(&self) -> &'static str {
+        "publishing"
+    }
+
+    /// Published events cannot be recalled: resetting publishes them again.
+    async fn reset(&self, _conn: &mut PgConnection) -> Result<(), ProjectionError> {
+        Ok(())
+    }
+
+    async fn project(
+        &self,
+        _conn: &mut PgConnection,
+        _aggregate_id: &str,
+        events: &[EventEnvelope<Team>],
+    ) -> Result<(), ProjectionError> {
+        let mut publisher = self.publisher.lock().await;
+        if publisher.is_none() {
+            *publisher = Some(connect(&self.config).await?);
+        }
+        for event in events {
+            let published = publisher
+                .as_ref()
+                .expect("connected above")
+                .publish(&event.into())
+                .await;
+            if let Err(e) = published {
+                // The connection may be broken; open a new one for the retry.
+                *publisher = None;
+                return Err(e.into());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use snowy_model::MemberId;
+
+    use super::*;
+
+    fn envelope(sequence: usize) -> EventEnvelope<Team> {
+        EventEnvelope {
+            aggregate_id: "team-1".to_string(),
+            sequence,
+            payload: TeamEvent::MemberRemoved {
+                member_id: MemberId::new(format!("member-{}", sequence)),
+            },
+            metadata: HashMap::from([("request_id".to_string(), "42".to_string())]),
+        }
+    }
+
+    #[test]
+    fn test_published_event_from_envelope() {
+        let event = PublishedEvent::from(&envelope(3));
+
+        assert_eq!(event.aggregate_type, "team");
+        assert_eq!(event.aggregate_id, "team-1");
+        assert_eq!(event.sequence, 3);
+        assert_eq!(event.event_type, "member-removed");
+        assert_eq!(event.metadata["request_id"], "42");
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL, see SNOWY_TEST_DATABASE_URL"]
+    async fn test_reconnects_after_a_failure() {
+        let pool = crate::testing::database().await;
+        let mut conn = pool.acquire().await.unwrap();
+        let directory = std::env::temp_dir().join(format!("snowy-{}", uuid::Uuid::new_v4()));
+        let path = directory.join("events.jsonl");
+        let projection = PublishingProjection::new(PublishingConfig::JsonLines {
+            path: path.to_string_lossy().into_owned(),
+        });
+
+        // The directory does not exist yet.
+        assert!(matches!(
+            projection
+                .project(&mut conn, "team-1", &[envelope(1)])
+                .await,
+            Err(ProjectionError::Publish(PublishError::Io(_)))
+        ));
+
+        tokio::fs::create_dir(&directory).await.unwrap();
+        projection
+            .project(&mut conn, "team-1", &[envelope(1), envelope(2)])
+            .await
+            .unwrap();
+
+        let contents = tokio::fs::read_to_string(&path).await.unwrap();
+        tokio::fs::remove_dir_all(&directory).await.unwrap();
+        let sequences: Vec<usize> = contents
+            .lines()
+            .map(|line| {
+                serde_json::from_str::<PublishedEvent>(line)
+                    .unwrap()
+                    .sequence
+            })
+            .collect();
+        assert_eq!(sequences, vec![1, 2]);
+    }
+}
@@ -0,0 +1,334 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use cqrs_es::EventEnvelope;
+use sqlx::{types::Json, PgConnection, Pool, Postgres};
+
+use snowy_model::{ForecastRecord, MemberId, WeatherForecast};
+
+use crate::{
+    domain::{aggregates::Team, events::TeamEvent},
+    projections::{Projection, ProjectionError},
+};
+
+/// Appends every tracked forecast to `forecast_history`.
+pub(crate) struct ForecastHistoryProjection;
+
+impl ForecastHistoryProjection {
+    async fn append(
+        &self,
+        conn: &mut PgConnection,
+        team_id: &str,
+        events: &[EventEnvelope<Team>],
+    ) -> Result<(), sqlx::Error> {
+        for event in events {
+            // Events recorded before `member_id` was added cannot be attributed.
+            let TeamEvent::ForecastTracked {
+                forecasts,
+                member_id: Some(member_id),
+            } = &event.payload
+            else {
+                continue;
+            };
+            let Some(forecast) = forecasts.get(member_id) else {
+                continue;
+            };
+
+            // Forecasts are tracked when their event was recorded.
+            sqlx::query(
+                "INSERT INTO forecast_history
+                     (team_id, sequence, member_id, date, minimum_temperature, maximum_temperature,
+                      minimum_apparent_temperature, maximum_apparent_temperature,
+                      maximum_wind_speed, wind_speed_unit, dominant_wind_direction, weather_code,
+                      forecast, tracked_at)
+                 SELECT $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, e.recorded_at
+                 FROM events e
+                 WHERE e.aggregate_type = 'team' AND e.aggregate_id = $1 AND e.sequence = $2
+                 ON CONFLICT (team_id, sequence) DO NOTHING",
+            )
+            .bind(team_id)
+            .bind(event.sequence as i64)
+            .bind(member_id.as_str())
+            .bind(forecast.date)
+            .bind(forecast.minimum_temperature.0)
+            .bind(forecast.maximum_temperature.0)
+            .bind(forecast.minimum_apparent_temperature.as_ref().map(|t| t.0))
+            .bind(forecast.maximum_apparent_temperature.as_ref().map(|t| t.0))
+            .bind(forecast.maximum_wind_speed.as_ref().map(|w| w.value()))
+            .bind(
+                forecast
+                    .maximum_wind_speed
+                    .as_ref()
+                    .map(|w| w.unit().as_str()),
+            )
+            .bind(
+                forecast
+                    .dominant_wind_direction
+                    .as_ref()
+                    .map(|d| format!("{:?}", d)),
+            )
+            .bind(forecast.weather_code.clone().map(|code| code as i32))
+            .bind(Json(forecast))
+            .execute(&mut *conn)
+            .await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Projection for ForecastHistoryProjection {
+    fn name(&self) -> &'static str {
+        "forecast_history"
+    }
+
+    async fn reset(&self, conn: &mut PgConnection) -> Result<(), ProjectionError> {
+        sqlx::query("DELETE FROM forecast_history")
+            .execute(&mut *conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn project(
+        &self,
+        conn: &mut PgConnection,
+        aggregate_id: &str,
+        events: &[EventEnvelope<Team>],
+    ) -> Result<(), ProjectionError> {
+        Ok(self.append(conn, aggregate_id, events).await?)
+    }
+}
+
+/// Filters for reading a team's forecast history; bounds are inclusive.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ForecastFilter {
+    pub(crate) from: Option<NaiveDate>,
+    pub(crate) to: Option<NaiveDate>,
+    pub(crate) member_id: Option<MemberId>,
+}
+
+#[derive(sqlx::FromRow)]
+struct ForecastRow {
+    team_id: String,
+    member_id: String,
+    forecast: Json<WeatherForecast>,
+    tracked_at: DateTime<Utc>,
+}
+
+impl From<ForecastRow> for ForecastRecord {
+    fn from(row: ForecastRow) -> Self {
+        Self {
+            member_id: MemberId::new(row.member_id),
+            forecast: row.forecast.0,
+            tracked_at: row.tracked_at,
+        }
+    }
+}
+
+/// Reads the `forecast_history` projection.
+pub(crate) struct ForecastHistoryRepository {
+    pool: Pool<Postgres>,
+}
+
+impl ForecastHistoryRepository {
+    pub(crate) fn new(pool: Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+
+    /// Forecasts tracked for `team_id`, ordered by forecast date then tracking time.
+    pub(crate) async fn forecasts(
+        &self,
+        team_id: &str,
+        filter: &ForecastFilter,
+    ) -> Result<Vec<ForecastRecord>, sqlx::Error> {
+        let rows = sqlx::query_as::<_, ForecastRow>(
+            "SELECT team_id, member_id, forecast, tracked_at
+             FROM forecast_history
+             WHERE team_id = $1
+               AND ($2::date IS NULL OR date >= $2)
+               AND ($3::date IS NULL OR date <= $3)
+               AND ($4::text IS NULL OR member_id = $4)
+             ORDER BY date, tracked_at",
+        )
+        .bind(team_id)
+        .bind(filter.from)
+        .bind(filter.to)
+        .bind(filter.member_id.as_ref().map(|m| m.as_str()))
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(ForecastRecord::from).collect())
+    }
+
+    /// Forecasts tracked for each of `team_ids` between the inclusive
+    /// bounds, in one query, ordered as [`Self::forecasts`].
+    pub(crate) async fn team_forecasts(
+        &self,
+        team_ids: &[String],
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    ) -> Result<HashMap<String, Vec<ForecastRecord>>, sqlx::Error> {
+        let rows = sqlx::query_as::<_, ForecastRow>(
+            "SELECT team_id, member_id, forecast, tracked_at
+             FROM forecast_history
+             WHERE team_id = ANY($1)
+               AND ($2::date IS NULL OR date >= $2)
+               AND ($3::date IS NULL OR date <= $3)
+             ORDER BY date, tracked_at",
+        )
+        .bind(team_ids)
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut forecasts: HashMap<String, Vec<ForecastRecord>> = HashMap::new();
+        for row in rows {
+            forecasts
+                .entry(row.team_id.clone())
+                .or_default()
+                .push(row.into());
+        }
+        Ok(forecasts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rocket::tokio;
+    use snowy_model::CelsiusTemperature;
+
+    use super::*;
+    use crate::{
+        api::{
+            cqrs::{setup_cqrs, CqrsPlumbing},
+            metrics::Metrics,
+        },
+        domain::commands::TeamCommand,
+        testing,
+    };
+
+    fn forecast(date: &str, minimum: f32) -> WeatherForecast {
+        WeatherForecast {
+            date: date.parse().unwrap(),
+            minimum_temperature: CelsiusTemperature(minimum),
+            ..Default::default()
+        }
+    }
+
+    async fn track_forecasts(cqrs: &CqrsPlumbing) {
+        let mut commands = Vec::new();
+        for member in ["alice", "bob"] {
+            commands.push(TeamCommand::AddMember {
+                member_id: MemberId::new(member.to_string()),
+                email: format!("{}@example.com", member),
+            });
+        }
+        for (member, date, minimum) in [
+            ("alice", "2024-11-06", -1.0),
+            ("bob", "2024-11-05", 2.0),
+            ("alice", "2024-11-05", -3.0),
+        ] {
+            commands.push(TeamCommand::TrackMemberForecast {
+                member_id: MemberId::new(member.to_string()),
+                forecast: forecast(date, minimum),
+            });
+        }
+        for command in commands {
+            cqrs.execute("team-1", command, HashMap::new())
+                .await
+                .unwrap();
+        }
+    }
+
+    fn minimums(records: &[ForecastRecord]) -> Vec<f32> {
+        records
+            .iter()
+            .map(|record| record.forecast.minimum_temperature.0)
+            .collect()
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL, see SNOWY_TEST_DATABASE_URL"]
+    async fn test_history_of_projected_forecasts() {
+        let pool = testing::database().await;
+        let cqrs = setup_cqrs(pool.clone(), &Metrics::new().unwrap());
+        track_forecasts(&cqrs).await;
+        testing::project(&pool, &ForecastHistoryProjection, "team-1").await;
+        testing::project(&pool, &ForecastHistoryProjection, "team-1").await;
+        let repository = ForecastHistoryRepository::new(pool.clone());
+
+        let all = repository
+            .forecasts("team-1", &ForecastFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(minimums(&all), vec![2.0, -3.0, -1.0]);
+
+        let recorded_at: Vec<DateTime<Utc>> = sqlx::query_scalar(
+            "SELECT recorded_at FROM events WHERE event_type = 'forecast-tracked' ORDER BY sequence",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        let tracked_at: Vec<DateTime<Utc>> = [1, 2, 0].iter().map(|&i| recorded_at[i]).collect();
+        assert_eq!(
+            all.iter()
+                .map(|record| record.tracked_at)
+                .collect::<Vec<_>>(),
+            tracked_at
+        );
+
+        let filter = ForecastFilter {
+            from: Some("2024-11-06".parse().unwrap()),
+            ..Default::default()
+        };
+        let later = repository.forecasts("team-1", &filter).await.unwrap();
+        assert_eq!(minimums(&later), vec![-1.0]);
+
+        let filter = ForecastFilter {
+            to: Some("2024-11-05".parse().unwrap()),
+            member_id: Some(MemberId::new("alice".to_string())),
+            ..Default::default()
+        };
+        let alice = repository.forecasts("team-1", &filter).await.unwrap();
+        assert_eq!(minimums(&alice), vec![-3.0]);
+
+        let teams = repository
+            .team_forecasts(
+                &["team-1".to_string(), "team-2".to_string()],
+                None,
+                Some("2024-11-05".parse().unwrap()),
+            )
+            .await
+            .unwrap();
+        assert_eq!(teams.len(), 1);
+        assert_eq!(minimums(&teams["team-1"]), vec![2.0, -3.0]);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL, see SNOWY_TEST_DATABASE_URL"]
+    async fn test_history_rebuilt_from_the_first_event() {
+        let pool = testing::database_before("0005").await;
+        let cqrs = setup_cqrs(pool.clone(), &Metrics::new().unwrap());
+        track_forecasts(&cqrs).await;
+
+        testing::apply_changesets_from(&pool, "0005").await;
+
+        let position: i64 = sqlx::query_scalar(
+            "SELECT global_position FROM projection_checkpoints WHERE projection = 'forecast_history'",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(position, 0);
+        testing::project(&pool, &ForecastHistoryProjection, "team-1").await;
+        let all = ForecastHistoryRepository::new(pool)
+            .forecasts("team-1", &ForecastFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(all.len(), 3);
+    }
+}
@@ -42,7 +42,8 @@ mod tests {
             TeamCommand::AddMember {
                 member_id: MemberId::new("member-1".to_string()),
                 email: "test@example.com".to_string(),
-            },
+            }
+            .into(),
         )
         .await
         .unwrap();
@@ -57,7 +58,8 @@ mod tests {
                     maximum_temperature: CelsiusTemperature(20.0),
                     ..Default::default()
                 },
-            },
+            }
+            .into(),
         )
         .await
         .unwrap();
@@ -67,7 +69,8 @@ mod tests {
             TeamCommand::AddMember {
                 member_id: MemberId::new("member-2".to_string()),
                 email: "test2@example.com".to_string(),
-            },
+            }
+            .into(),
         )
         .await
         .unwrap();
@@ -82,7 +85,8 @@ mod tests {
                     maximum_temperature: CelsiusTemperature(12.0),
                     ..Default::default()
                 },
-            },
+            }
+            .into(),
         )
         .await
         .unwrap();
@@ -0,0 +1,349 @@
+use async_trait::async_trait;
+use cqrs_es::EventEnvelope;
+use sqlx::{types::Json, PgConnection, Pool, Postgres};
+
+use snowy_model::{MemberId, Membership, TeamId, WeatherForecast};
+
+use crate::{
+    domain::{aggregates::Team, events::TeamEvent},
+    projections::{Projection, ProjectionError},
+};
+
+use super::team_summary::Visibility;
+
+/// Maintains `member_index`, mapping members to the teams they belong to.
+pub(crate) struct MemberIndexProjection;
+
+impl MemberIndexProjection {
+    async fn update(
+        &self,
+        conn: &mut PgConnection,
+        team_id: &str,
+        events: &[EventEnvelope<Team>],
+    ) -> Result<(), sqlx::Error> {
+        for event in events {
+            match &event.payload {
+                TeamEvent::MemberAdded { member_id, email } => {
+                    sqlx::query(
+                        "INSERT INTO member_index (member_id, team_id, email)
+                         VALUES ($1, $2, $3)
+                         ON CONFLICT (member_id, team_id)
+                         DO UPDATE SET email = EXCLUDED.email, updated_at = now()",
+                    )
+                    .bind(member_id.as_str())
+                    .bind(team_id)
+                    .bind(email)
+                    .execute(&mut *conn)
+                    .await?;
+                }
+                TeamEvent::MemberRemoved { member_id } => {
+                    sqlx::query("DELETE FROM member_index WHERE member_id = $1 AND team_id = $2")
+                        .bind(member_id.as_str())
+                        .bind(team_id)
+                        .execute(&mut *conn)
+                        .await?;
+                }
+                TeamEvent::ForecastTracked {
+                    forecasts,
+                    member_id,
+                } => {
+                    let tracked = forecasts
+                        .iter()
+                        .filter(|(id, _)| member_id.as_ref().is_none_or(|m| m == *id));
+                    for (member_id, forecast) in tracked {
+                        sqlx::query(
+                            "UPDATE member_index SET latest_forecast = $3, updated_at = now()
+                             WHERE member_id = $1 AND team_id = $2",
+                        )
+                        .bind(member_id.as_str())
+                        .bind(team_id)
+                        .bind(Json(forecast))
+                        .execute(&mut *conn)
+                        .await?;
+                    }
+                }
+                TeamEvent::RoleGranted { member_id, role } => {
+                    sqlx::query(
+                        "UPDATE member_index SET roles = array_append(roles, $3), updated_at = now()
+                         WHERE member_id = $1 AND team_id = $2 AND NOT ($3 = ANY (roles))",
+                    )
+                    .bind(member_id.as_str())
+                    .bind(team_id)
+                    .bind(role)
+                    .execute(&mut *conn)
+                    .await?;
+                }
+                TeamEvent::RoleRevoked { member_id, role } => {
+                    sqlx::query(
+                        "UPDATE member_index SET roles = array_remove(roles, $3), updated_at = now()
+                         WHERE member_id = $1 AND team_id = $2",
+                    )
+                    .bind(member_id.as_str())
+                    .bind(team_id)
+                    .bind(role)
+                    .execute(&mut *conn)
+                    .await?;
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Projection for MemberIndexProjection {
+    fn name(&self) -> &'static str {
+        "member_index"
+    }
+
+    async fn reset(&self, conn: &mut PgConnection) -> Result<(), ProjectionError> {
+        sqlx::query("DELETE FROM member_index")
+            .execute(&mut *conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn project(
+        &self,
+        conn: &mut PgConnection,
+        aggregate_id: &str,
+        events: &[EventEnvelope<Team>],
+    ) -> Result<(), ProjectionError> {
+        Ok(self.update(conn, aggregate_id, events).await?)
+    }
+}
+
+/// How to identify the member whose teams are looked up.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum MemberKey {
+    Id(MemberId),
+    Email(String),
+}
+
+#[derive(sqlx::FromRow)]
+struct MembershipRow {
+    member_id: String,
+    team_id: String,
+    team_name: String,
+    email: String,
+    roles: Vec<String>,
+    latest_forecast: Option<Json<WeatherForecast>>,
+}
+
+impl From<MembershipRow> for Membership {
+    fn from(row: MembershipRow) -> Self {
+        Self {
+            team_id: TeamId::from(row.team_id),
+            team_name: row.team_name,
+            member_id: MemberId::new(row.member_id),
+            email: row.email,
+            roles: row.roles,
+            latest_forecast: row.latest_forecast.map(|Json(forecast)| forecast),
+        }
+    }
+}
+
+/// Reads the `member_index` projection.
+pub(crate) struct MemberIndexRepository {
+    pool: Pool<Postgres>,
+}
+
+impl MemberIndexRepository {
+    pub(crate) fn new(pool: Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+
+    /// The memberships of a member in the teams the caller may see.
+    pub(crate) async fn memberships(
+        &self,
+        key: &MemberKey,
+        visibility: &Visibility,
+    ) -> Result<Vec<Membership>, sqlx::Error> {
+        let (member_id, email) = match key {
+            MemberKey::Id(member_id) => (Some(member_id.as_str()), None),
+            MemberKey::Email(email) => (None, Some(email.as_str())),
+        };
+        let (visible_team, visible_to) = match visibility {
+            Visibility::Team(team_id) => (Some(team_id.as_str()), None),
+            Visibility::Member(email) => (None, Some(email.as_str())),
+        };
+
+        let rows = sqlx::query_as::<_, MembershipRow>(
+            "SELECT i.member_id, i.team_id, coalesce(s.name, '') AS team_name, i.email, i.roles,
+                    i.latest_forecast
+             FROM member_index i
+             LEFT JOIN team_summary s ON s.team_id = i.team_id
+             WHERE ($1::text IS NULL OR i.member_id = $1)
+               AND ($2::text IS NULL OR lower(i.email) = lower($2))
+               AND ($3::text IS NULL OR i.team_id = $3)
+               AND ($4::text IS NULL OR EXISTS (
+                   SELECT 1 FROM member_index c
+                   WHERE c.team_id = i.team_id AND lower(c.email) = lower($4)))
+             ORDER BY i.team_id",
+        )
+        .bind(member_id)
+        .bind(email)
+        .bind(visible_team)
+        .bind(visible_to)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(Membership::from).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rocket::tokio;
+    use snowy_model::CelsiusTemperature;
+
+    use super::*;
+    use crate::{
+        api::{
+            cqrs::{setup_cqrs, CqrsPlumbing},
+            metrics::Metrics,
+        },
+        domain::commands::TeamCommand,
+        testing,
+    };
+
+    fn member_id(id: &str) -> MemberId {
+        MemberId::new(id.to_string())
+    }
+
+    fn forecast(minimum: f32) -> WeatherForecast {
+        WeatherForecast {
+            minimum_temperature: CelsiusTemperature(minimum),
+            ..Default::default()
+        }
+    }
+
+    /// Alice ends up as lead with her forecast; Bob is removed, then added
+    /// back without the role and forecast he had.
+    async fn record_history(cqrs: &CqrsPlumbing) {
+        let commands = vec![
+            TeamCommand::AddMember {
+                member_id: member_id("alice"),
+                email: "alice@example.com".to_string(),
+            },
+            TeamCommand::AddMember {
+                member_id: member_id("bob"),
+                email: "bob@example.com".to_string(),
+            },
+            TeamCommand::GrantRole {
+                member_id: member_id("alice"),
+                role: "admin".to_string(),
+            },
+            TeamCommand::GrantRole {
+                member_id: member_id("alice"),
+                role: "lead".to_string(),
+            },
+            TeamCommand::GrantRole {
+                member_id: member_id("bob"),
+                role: "admin".to_string(),
+            },
+            TeamCommand::RevokeRole {
+                member_id: member_id("alice"),
+                role: "admin".to_string(),
+            },
+            TeamCommand::TrackMemberForecast {
+                member_id: member_id("alice"),
+                forecast: forecast(-4.0),
+            },
+            TeamCommand::TrackMemberForecast {
+                member_id: member_id("bob"),
+                forecast: forecast(2.0),
+            },
+            TeamCommand::RemoveMember {
+                member_id: member_id("bob"),
+            },
+            TeamCommand::AddMember {
+                member_id: member_id("bob"),
+                email: "Bob@example.com".to_string(),
+            },
+        ];
+        for command in commands {
+            cqrs.execute("team-1", command, HashMap::new())
+                .await
+                .unwrap();
+        }
+        cqrs.execute(
+            "team-2",
+            TeamCommand::AddMember {
+                member_id: member_id("alice"),
+                email: "alice@example.com".to_string(),
+            },
+            HashMap::new(),
+        )
+        .await
+        .unwrap();
+    }
+
+    async fn assert_history_indexed(pool: &Pool<Postgres>) {
+        let repository = MemberIndexRepository::new(pool.clone());
+        let everyone = Visibility::Member("alice@example.com".to_string());
+
+        let alice = repository
+            .memberships(&MemberKey::Id(member_id("alice")), &everyone)
+            .await
+            .unwrap();
+        let teams: Vec<&str> = alice.iter().map(|m| m.team_id.as_str()).collect();
+        assert_eq!(teams, vec!["team-1", "team-2"]);
+        assert_eq!(alice[0].roles, vec!["lead".to_string()]);
+        assert_eq!(alice[0].latest_forecast, Some(forecast(-4.0)));
+        assert_eq!(alice[1].latest_forecast, None);
+
+        let bob = repository
+            .memberships(&MemberKey::Email("BOB@example.com".to_string()), &everyone)
+            .await
+            .unwrap();
+        assert_eq!(bob.len(), 1);
+        assert_eq!(bob[0].email, "Bob@example.com");
+        assert!(bob[0].roles.is_empty());
+        assert_eq!(bob[0].latest_forecast, None);
+
+        let outsider = Visibility::Member("bob@example.com".to_string());
+        let alice = repository
+            .memberships(&MemberKey::Id(member_id("alice")), &outsider)
+            .await
+            .unwrap();
+        assert_eq!(alice.len(), 1);
+        let alice = repository
+            .memberships(
+                &MemberKey::Id(member_id("alice")),
+                &Visibility::Team("team-2".to_string()),
+            )
+            .await
+            .unwrap();
+        assert_eq!(alice[0].team_id.as_str(), "team-2");
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL, see SNOWY_TEST_DATABASE_URL"]
+    async fn test_index_projected_memberships() {
+        let pool = testing::database().await;
+        let cqrs = setup_cqrs(pool.clone(), &Metrics::new().unwrap());
+        record_history(&cqrs).await;
+        for team_id in ["team-1", "team-2"] {
+            testing::project(&pool, &MemberIndexProjection, team_id).await;
+        }
+
+        assert_history_indexed(&pool).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL, see SNOWY_TEST_DATABASE_URL"]
+    async fn test_backfill_matches_projection() {
+        let pool = testing::database_before("0003").await;
+        let cqrs = setup_cqrs(pool.clone(), &Metrics::new().unwrap());
+        record_history(&cqrs).await;
+
+        testing::apply_changesets_from(&pool, "0003").await;
+
+        assert_history_indexed(&pool).await;
+    }
+}
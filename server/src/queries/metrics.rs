@@ -0,0 +1,78 @@
+use async_trait::async_trait;
+
+use cqrs_es::{DomainEvent, EventEnvelope, Query};
+use prometheus::IntCounterVec;
+
+use crate::domain::aggregates::Team;
+
+/// Counts committed events per event type for the `/metrics` endpoint.
+pub(crate) struct EventMetricsQuery {
+    events_total: IntCounterVec,
+}
+
+impl EventMetricsQuery {
+    pub(crate) fn new(events_total: IntCounterVec) -> Self {
+        Self { events_total }
+    }
+}
+
+#[async_trait]
+impl Query<Team> for EventMetricsQuery {
+    async fn dispatch(&self, _aggregate_id: &str, events: &[EventEnvelope<Team>]) {
+        for event in events {
+            self.events_total
+                .with_label_values(&[&event.payload.event_type()])
+                .inc();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cqrs_es::{mem_store::MemStore, CqrsFramework};
+    use prometheus::Opts;
+
+    use super::*;
+    use crate::domain::{commands::TeamCommand, services::TeamServices};
+    use snowy_model::{MemberId, WeatherForecast};
+
+    #[tokio::test]
+    async fn test_event_metrics_query() {
+        let events_total =
+            IntCounterVec::new(Opts::new("events_total", "Team events"), &["event_type"]).unwrap();
+        let query = EventMetricsQuery::new(events_total.clone());
+        let cqrs = CqrsFramework::new(
+            MemStore::<Team>::default(),
+            vec![Box::new(query)],
+            TeamServices {},
+        );
+
+        cqrs.execute(
+            "team-1",
+            TeamCommand::AddMember {
+                member_id: MemberId::new("member-1".to_string()),
+                email: "test@example.com".to_string(),
+            }
+            .into(),
+        )
+        .await
+        .unwrap();
+
+        cqrs.execute(
+            "team-1",
+            TeamCommand::TrackMemberForecast {
+                member_id: MemberId::new("member-1".to_string()),
+                forecast: WeatherForecast::default(),
+            }
+            .into(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(events_total.with_label_values(&["member-added"]).get(), 1);
+        assert_eq!(
+            events_total.with_label_values(&["forecast-tracked"]).get(),
+            1
+        );
+    }
+}
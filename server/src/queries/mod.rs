@@ -1,2 +1,8 @@
+pub(crate) mod forecast_history;
+#[cfg(test)]
 mod logging;
+pub(crate) mod member_index;
+pub(crate) mod metrics;
+pub(crate) mod notifier;
 pub(crate) mod team;
+pub(crate) mod team_summary;
@@ -0,0 +1,135 @@
+use std::time::Duration;
+
+use rocket::{tokio::select, Shutdown};
+use serde::{Deserialize, Serialize};
+use sqlx::{postgres::PgListener, PgConnection, Pool, Postgres};
+use tokio::sync::broadcast;
+use tracing::{error, warn};
+
+/// Channel on which team view updates are announced to every replica.
+const TEAM_UPDATES_CHANNEL: &str = "team_updates";
+
+/// Delay before listening again after losing the connection.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Announces that a team view reflects the events up to `sequence`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub(crate) struct TeamUpdate {
+    pub(crate) team_id: String,
+    pub(crate) sequence: u64,
+}
+
+/// Announces `update` once the transaction on `conn` commits.
+pub(crate) async fn notify(
+    conn: &mut PgConnection,
+    update: &TeamUpdate,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("SELECT pg_notify($1, $2)")
+        .bind(TEAM_UPDATES_CHANNEL)
+        .bind(serde_json::to_string(update).expect("Failed to serialize TeamUpdate"))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// Broadcasts the [`TeamUpdate`]s announced by the team view projection,
+/// whichever replica runs it, to the subscribers of this replica.
+#[derive(Clone)]
+pub(crate) struct TeamNotifier {
+    sender: broadcast::Sender<TeamUpdate>,
+}
+
+impl TeamNotifier {
+    pub(crate) fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self { sender }
+    }
+
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<TeamUpdate> {
+        self.sender.subscribe()
+    }
+
+    /// Announces `update` to the subscribers of this replica only.
+    #[cfg(test)]
+    pub(crate) fn send(&self, update: TeamUpdate) {
+        let _ = self.sender.send(update);
+    }
+
+    fn forward(&self, payload: &str) {
+        match serde_json::from_str::<TeamUpdate>(payload) {
+            // Sending only fails when nobody is listening.
+            Ok(update) => {
+                let _ = self.sender.send(update);
+            }
+            Err(e) => warn!(error = %e, payload, "ignoring malformed team update"),
+        }
+    }
+
+    /// Forwards announced updates until the server shuts down.
+    pub(crate) async fn run(self, pool: Pool<Postgres>, mut shutdown: Shutdown) {
+        loop {
+            let mut listener = match listen(&pool).await {
+                Ok(listener) => listener,
+                Err(e) => {
+                    error!(error = %e, "cannot listen for team updates");
+                    select! {
+                        _ = &mut shutdown => return,
+                        _ = tokio::time::sleep(RECONNECT_DELAY) => continue,
+                    }
+                }
+            };
+            loop {
+                select! {
+                    _ = &mut shutdown => return,
+                    notification = listener.recv() => match notification {
+                        Ok(notification) => self.forward(notification.payload()),
+                        Err(e) => {
+                            error!(error = %e, "lost the team updates listener connection");
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn listen(pool: &Pool<Postgres>) -> Result<PgListener, sqlx::Error> {
+    let mut listener = PgListener::connect_with(pool).await?;
+    listener.listen(TEAM_UPDATES_CHANNEL).await?;
+    Ok(listener)
+}
+
+/// Sequence of the latest event reflected in the view of a team.
+pub(crate) async fn latest_sequence(
+    pool: &Pool<Postgres>,
+    team_id: &str,
+) -> Result<Option<u64>, sqlx::Error> {
+    let sequence: Option<Option<i64>> =
+        sqlx::query_scalar("SELECT sequence FROM team_query WHERE view_id = $1")
+            .bind(team_id)
+            .fetch_optional(pool)
+            .await?;
+    Ok(sequence.flatten().map(|s| s as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_forwards_announced_updates() {
+        let notifier = TeamNotifier::new(16);
+        let mut updates = notifier.subscribe();
+        let update = TeamUpdate {
+            team_id: "team-1".to_string(),
+            sequence: 2,
+        };
+
+        notifier.forward("not json");
+        notifier.forward(&serde_json::to_string(&update).unwrap());
+
+        assert_eq!(updates.recv().await.unwrap(), update);
+        assert!(updates.try_recv().is_err());
+    }
+}
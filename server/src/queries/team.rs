@@ -1,58 +1,598 @@
-use std::collections::HashMap;
+use std::{cmp::Ordering, collections::HashMap};
 
+use async_trait::async_trait;
 use cqrs_es::Query;
-use cqrs_es::{persist::GenericQuery, EventEnvelope, View};
+use cqrs_es::{EventEnvelope, View};
 use postgres_es::PostgresViewRepository;
+use sqlx::{types::Json, PgConnection};
 
-use snowy_model::{CelsiusTemperature, Member, TeamView};
+use snowy_model::{
+    weather::{WindSpeed, WindSpeedUnit},
+    CelsiusTemperature, Member, MemberId, TeamView, TemperatureExtreme, WeatherForecast,
+};
 
 use crate::domain::aggregates::Team;
 use crate::domain::events::TeamEvent;
+use crate::projections::{Projection, ProjectionError};
+
+use super::notifier::{notify, TeamUpdate};
 
 pub(crate) type TeamViewRepository = PostgresViewRepository<TeamView, Team>;
-pub(crate) type TeamQuery = GenericQuery<TeamViewRepository, TeamView, Team>;
 pub(crate) type TeamQueryDyn = dyn Query<Team>;
 
+/// Maintains the `team_query` views read through [`TeamViewRepository`], announcing
+/// every update to the stream subscribers of all replicas.
+pub(crate) struct TeamViewProjection;
+
+#[async_trait]
+impl Projection for TeamViewProjection {
+    fn name(&self) -> &'static str {
+        "team"
+    }
+
+    /// Read access is checked against the members of team views, so an
+    /// emptied view would deny every member until rebuilt.
+    async fn reset(&self, _conn: &mut PgConnection) -> Result<(), ProjectionError> {
+        Err(ProjectionError::NotResettable(self.name()))
+    }
+
+    async fn project(
+        &self,
+        conn: &mut PgConnection,
+        aggregate_id: &str,
+        events: &[EventEnvelope<Team>],
+    ) -> Result<(), ProjectionError> {
+        let row: Option<(i64, Json<TeamView>)> =
+            sqlx::query_as("SELECT version, payload FROM team_query WHERE view_id = $1 FOR UPDATE")
+                .bind(aggregate_id)
+                .fetch_optional(&mut *conn)
+                .await?;
+        let (version, mut view) = row
+            .map(|(version, Json(view))| (version, view))
+            .unwrap_or_default();
+
+        for event in events {
+            view.update(event);
+        }
+
+        sqlx::query(
+            "INSERT INTO team_query (view_id, version, payload, sequence) VALUES ($1, $2, $3, $4)
+             ON CONFLICT (view_id)
+             DO UPDATE SET version = EXCLUDED.version, payload = EXCLUDED.payload,
+                           sequence = EXCLUDED.sequence",
+        )
+        .bind(aggregate_id)
+        .bind(version + 1)
+        .bind(Json(&view))
+        .bind(events.last().map(|event| event.sequence as i64))
+        .execute(&mut *conn)
+        .await?;
+
+        if let Some(last) = events.last() {
+            notify(
+                conn,
+                &TeamUpdate {
+                    team_id: aggregate_id.to_string(),
+                    sequence: last.sequence as u64,
+                },
+            )
+            .await?;
+        }
+        Ok(())
+    }
+}
+
 impl View<Team> for TeamView {
     fn update(&mut self, event: &EventEnvelope<Team>) {
+        self.id = event.aggregate_id.as_str().into();
         match &event.payload {
             TeamEvent::MemberAdded { member_id, email } => {
-                self.members.push(Member {
-                    id: member_id.clone(),
-                    email: email.clone(),
-                });
+                self.members
+                    .push(Member::new(member_id.clone(), email.clone()));
+            }
+            TeamEvent::ForecastTracked {
+                forecasts,
+                member_id,
+            } => {
+                self.total_forecasts_tracked += match member_id {
+                    Some(_) => 1,
+                    // Older events only carry the resulting forecasts, so count
+                    // the ones that differ from what the view already had.
+                    None => forecasts
+                        .iter()
+                        .filter(|(id, forecast)| self.forecasts.get(*id) != Some(*forecast))
+                        .count() as u64,
+                };
+                self.forecasts = forecasts.clone();
+                refresh_statistics(self);
+            }
+            TeamEvent::MemberRemoved { member_id } => {
+                self.members.retain(|m| m.id != *member_id);
+                if self.forecasts.remove(member_id).is_some() {
+                    refresh_statistics(self);
+                }
+            }
+            TeamEvent::RoleGranted { member_id, role } => {
+                if let Some(member) = self.members.iter_mut().find(|m| m.id == *member_id) {
+                    member.roles.push(role.clone());
+                }
+            }
+            TeamEvent::RoleRevoked { member_id, role } => {
+                if let Some(member) = self.members.iter_mut().find(|m| m.id == *member_id) {
+                    member.roles.retain(|r| r != role);
+                }
+            }
+            TeamEvent::PolicySet { .. } | TeamEvent::PolicyRemoved { .. } => {}
+        }
+    }
+}
+
+/// Recomputes the aggregated statistics from the view's current forecasts.
+/// Averages, medians and extremes are `None` when there is nothing to
+/// aggregate, never NaN.
+fn refresh_statistics(view: &mut TeamView) {
+    let forecasts = &view.forecasts;
+
+    view.avg_minimum_temperature =
+        average(forecasts.values().map(|f| f.minimum_temperature.0)).map(CelsiusTemperature);
+    view.avg_maximum_temperature =
+        average(forecasts.values().map(|f| f.maximum_temperature.0)).map(CelsiusTemperature);
+    view.median_minimum_temperature =
+        median(forecasts.values().map(|f| f.minimum_temperature.0)).map(CelsiusTemperature);
+    view.median_maximum_temperature =
+        median(forecasts.values().map(|f| f.maximum_temperature.0)).map(CelsiusTemperature);
+
+    view.lowest_minimum_temperature =
+        extreme(forecasts, |f| f.minimum_temperature.0, Ordering::Less);
+    view.highest_maximum_temperature =
+        extreme(forecasts, |f| f.maximum_temperature.0, Ordering::Greater);
+
+    view.avg_minimum_apparent_temperature = average(
+        forecasts
+            .values()
+            .filter_map(|f| f.minimum_apparent_temperature.as_ref().map(|t| t.0)),
+    )
+    .map(CelsiusTemperature);
+    view.avg_maximum_apparent_temperature = average(
+        forecasts
+            .values()
+            .filter_map(|f| f.maximum_apparent_temperature.as_ref().map(|t| t.0)),
+    )
+    .map(CelsiusTemperature);
+    view.avg_maximum_wind_speed = average(
+        forecasts
+            .values()
+            .filter_map(|f| f.maximum_wind_speed.as_ref())
+            .map(WindSpeed::to_kilometers_per_hour),
+    )
+    .map(|speed| WindSpeed::new(speed, WindSpeedUnit::KilometersPerHour));
+    view.avg_comfort_index = average(forecasts.values().map(WeatherForecast::comfort_index));
+
+    let codes = || forecasts.values().filter_map(|f| f.weather_code.as_ref());
+    view.weather_condition_distribution = codes().fold(HashMap::new(), |mut acc, code| {
+        *acc.entry(code.clone()).or_insert(0) += 1;
+        acc
+    });
+    view.weather_category_distribution = codes().fold(HashMap::new(), |mut acc, code| {
+        *acc.entry(code.category()).or_insert(0) += 1;
+        acc
+    });
+}
+
+fn average(values: impl Iterator<Item = f32>) -> Option<f32> {
+    let (sum, count) = values.fold((0.0, 0), |(sum, count), v| (sum + v, count + 1));
+    (count > 0).then(|| sum / count as f32)
+}
+
+fn median(values: impl Iterator<Item = f32>) -> Option<f32> {
+    let mut values: Vec<f32> = values.collect();
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(f32::total_cmp);
+
+    let middle = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        Some((values[middle - 1] + values[middle]) / 2.0)
+    } else {
+        Some(values[middle])
+    }
+}
+
+/// The lowest (`Ordering::Less`) or highest (`Ordering::Greater`) temperature
+/// along with the member holding it; ties go to the smallest member id.
+fn extreme(
+    forecasts: &HashMap<MemberId, WeatherForecast>,
+    temperature: impl Fn(&WeatherForecast) -> f32,
+    wanted: Ordering,
+) -> Option<TemperatureExtreme> {
+    forecasts
+        .iter()
+        .map(|(member_id, f)| (member_id, temperature(f)))
+        .reduce(|best, candidate| match candidate.1.total_cmp(&best.1) {
+            ordering if ordering == wanted => candidate,
+            Ordering::Equal if candidate.0.as_str() < best.0.as_str() => candidate,
+            _ => best,
+        })
+        .map(|(member_id, t)| TemperatureExtreme {
+            member_id: member_id.clone(),
+            temperature: CelsiusTemperature(t),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use snowy_model::{WeatherCategory, WeatherCode};
+
+    use super::*;
+
+    fn forecast(minimum: f32, maximum: f32, code: WeatherCode) -> WeatherForecast {
+        WeatherForecast {
+            minimum_temperature: CelsiusTemperature(minimum),
+            maximum_temperature: CelsiusTemperature(maximum),
+            weather_code: Some(code),
+            ..Default::default()
+        }
+    }
+
+    fn view(forecasts: Vec<(&str, WeatherForecast)>) -> TeamView {
+        let mut view = TeamView {
+            forecasts: forecasts
+                .into_iter()
+                .map(|(id, f)| (MemberId::new(id.to_string()), f))
+                .collect(),
+            ..Default::default()
+        };
+        refresh_statistics(&mut view);
+        view
+    }
+
+    #[test]
+    fn test_statistics() {
+        let mut windy = forecast(2.0, 8.0, WeatherCode::LightSnow);
+        windy.maximum_wind_speed = Some(WindSpeed::new(10.0, WindSpeedUnit::MetersPerSecond));
+        windy.minimum_apparent_temperature = Some(CelsiusTemperature(-2.0));
+        windy.maximum_apparent_temperature = Some(CelsiusTemperature(4.0));
+
+        let view = view(vec![
+            ("alice", forecast(10.0, 20.0, WeatherCode::ClearSky)),
+            ("bob", forecast(12.0, 24.0, WeatherCode::Overcast)),
+            ("carol", windy),
+        ]);
+
+        assert_eq!(
+            view.median_minimum_temperature,
+            Some(CelsiusTemperature(10.0))
+        );
+        assert_eq!(
+            view.median_maximum_temperature,
+            Some(CelsiusTemperature(20.0))
+        );
+        assert_eq!(
+            view.lowest_minimum_temperature,
+            Some(TemperatureExtreme {
+                member_id: MemberId::new("carol".to_string()),
+                temperature: CelsiusTemperature(2.0),
+            })
+        );
+        assert_eq!(
+            view.highest_maximum_temperature.unwrap().member_id,
+            MemberId::new("bob".to_string())
+        );
+        assert_eq!(
+            view.avg_minimum_apparent_temperature,
+            Some(CelsiusTemperature(-2.0))
+        );
+        let wind_speed = view.avg_maximum_wind_speed.unwrap();
+        assert_eq!(wind_speed.unit(), &WindSpeedUnit::KilometersPerHour);
+        assert!((wind_speed.value() - 36.0).abs() < 1e-4);
+        assert_eq!(
+            view.weather_category_distribution[&WeatherCategory::Snow],
+            1
+        );
+        assert_eq!(
+            view.weather_category_distribution[&WeatherCategory::Cloudy],
+            1
+        );
+        assert_eq!(
+            view.weather_condition_distribution[&WeatherCode::ClearSky],
+            1
+        );
+    }
+
+    #[test]
+    fn test_statistics_without_forecasts() {
+        let view = view(vec![]);
+
+        assert_eq!(view.avg_minimum_temperature, None);
+        assert_eq!(view.median_maximum_temperature, None);
+        assert_eq!(view.lowest_minimum_temperature, None);
+        assert_eq!(view.avg_comfort_index, None);
+        assert!(view.weather_category_distribution.is_empty());
+    }
+
+    #[test]
+    fn test_total_forecasts_tracked_for_events_without_member_id() {
+        let alice = MemberId::new("alice".to_string());
+        let bob = MemberId::new("bob".to_string());
+        let mut view = TeamView::default();
+        let mut track = |forecasts: Vec<(&MemberId, f32)>| {
+            view.update(&envelope(TeamEvent::ForecastTracked {
+                forecasts: forecasts
+                    .into_iter()
+                    .map(|(id, t)| (id.clone(), forecast(t, t, WeatherCode::ClearSky)))
+                    .collect(),
+                member_id: None,
+            }));
+        };
+
+        track(vec![(&alice, 1.0)]);
+        track(vec![(&alice, 1.0), (&bob, 2.0)]);
+        track(vec![(&alice, 3.0), (&bob, 2.0)]);
+        track(vec![(&alice, 3.0), (&bob, 2.0)]);
+
+        assert_eq!(view.total_forecasts_tracked, 3);
+    }
+
+    #[test]
+    fn test_extreme_ties_go_to_smallest_member_id() {
+        let view = view(vec![
+            ("bob", forecast(5.0, 15.0, WeatherCode::ClearSky)),
+            ("alice", forecast(5.0, 15.0, WeatherCode::ClearSky)),
+        ]);
+
+        assert_eq!(
+            view.lowest_minimum_temperature.unwrap().member_id,
+            MemberId::new("alice".to_string())
+        );
+        assert_eq!(
+            view.highest_maximum_temperature.unwrap().member_id,
+            MemberId::new("alice".to_string())
+        );
+        assert_eq!(
+            view.median_minimum_temperature,
+            Some(CelsiusTemperature(5.0))
+        );
+    }
+
+    #[rocket::tokio::test]
+    #[ignore = "requires PostgreSQL, see SNOWY_TEST_DATABASE_URL"]
+    async fn test_projected_views_are_stored_and_announced() {
+        use cqrs_es::persist::ViewRepository;
+        use sqlx::postgres::PgListener;
+
+        use crate::{
+            api::{cqrs::setup_cqrs, metrics::Metrics},
+            domain::commands::TeamCommand,
+            queries::notifier::latest_sequence,
+            testing,
+        };
+
+        let pool = testing::database().await;
+        let cqrs = setup_cqrs(pool.clone(), &Metrics::new().unwrap());
+        for command in [
+            TeamCommand::AddMember {
+                member_id: MemberId::new("alice".to_string()),
+                email: "alice@example.com".to_string(),
+            },
+            TeamCommand::AddMember {
+                member_id: MemberId::new("bob".to_string()),
+                email: "bob@example.com".to_string(),
+            },
+            TeamCommand::RemoveMember {
+                member_id: MemberId::new("bob".to_string()),
+            },
+        ] {
+            cqrs.execute("team-1", command, HashMap::new())
+                .await
+                .unwrap();
+        }
+        let mut listener = PgListener::connect_with(&pool).await.unwrap();
+        listener.listen("team_updates").await.unwrap();
+
+        testing::project(&pool, &TeamViewProjection, "team-1").await;
+
+        let view = TeamViewRepository::new("team_query", pool.clone())
+            .load("team-1")
+            .await
+            .unwrap()
+            .unwrap();
+        let members: Vec<&str> = view.members.iter().map(|m| m.email.as_str()).collect();
+        assert_eq!(members, vec!["alice@example.com"]);
+        assert_eq!(latest_sequence(&pool, "team-1").await.unwrap(), Some(3));
+        let update: TeamUpdate =
+            serde_json::from_str(listener.recv().await.unwrap().payload()).unwrap();
+        assert_eq!(
+            update,
+            TeamUpdate {
+                team_id: "team-1".to_string(),
+                sequence: 3
             }
-            TeamEvent::ForecastTracked { forecasts } => {
-                let n_forecasts = forecasts.len() as f32;
+        );
+    }
+
+    #[rocket::tokio::test]
+    #[ignore = "requires PostgreSQL, see SNOWY_TEST_DATABASE_URL"]
+    async fn test_views_stored_without_their_id_are_backfilled() {
+        use crate::testing;
+
+        let pool = testing::database_before("0011").await;
+        sqlx::query(
+            "INSERT INTO team_query (view_id, version, payload, sequence)
+             VALUES ('team-1', 1, '{\"members\": []}', 1)",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        testing::apply_changesets_from(&pool, "0011").await;
+
+        let id: Option<String> =
+            sqlx::query_scalar("SELECT payload->>'id' FROM team_query WHERE view_id = 'team-1'")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(id.as_deref(), Some("team-1"));
+    }
 
-                let avg_minimum_temperature = CelsiusTemperature(
+    fn envelope(event: TeamEvent) -> EventEnvelope<Team> {
+        EventEnvelope {
+            aggregate_id: "team-1".to_string(),
+            sequence: 0,
+            payload: event,
+            metadata: HashMap::new(),
+        }
+    }
+
+    mod properties {
+        use cqrs_es::Aggregate;
+        use proptest::{collection::vec, option, prelude::*, sample::select};
+
+        use super::*;
+        use crate::domain::{commands::TeamCommand, services::TeamServices};
+
+        const MEMBERS: u8 = 4;
+
+        fn member_id(n: u8) -> MemberId {
+            MemberId::new(format!("member-{}", n))
+        }
+
+        fn arb_forecast() -> impl Strategy<Value = WeatherForecast> {
+            (
+                -40.0f32..40.0,
+                0.0f32..20.0,
+                option::of((-45.0f32..45.0, 0.0f32..20.0)),
+                option::of(0.0f32..150.0),
+                option::of(select(vec![
+                    WeatherCode::ClearSky,
+                    WeatherCode::Overcast,
+                    WeatherCode::LightRain,
+                    WeatherCode::HeavySnow,
+                    WeatherCode::Thunderstorm,
+                ])),
+            )
+                .prop_map(|(minimum, spread, apparent, wind, code)| WeatherForecast {
+                    minimum_temperature: CelsiusTemperature(minimum),
+                    maximum_temperature: CelsiusTemperature(minimum + spread),
+                    minimum_apparent_temperature: apparent.map(|(t, _)| CelsiusTemperature(t)),
+                    maximum_apparent_temperature: apparent
+                        .map(|(t, spread)| CelsiusTemperature(t + spread)),
+                    maximum_wind_speed: wind
+                        .map(|speed| WindSpeed::new(speed, WindSpeedUnit::KilometersPerHour)),
+                    weather_code: code,
+                    ..Default::default()
+                })
+        }
+
+        fn arb_command() -> impl Strategy<Value = TeamCommand> {
+            prop_oneof![
+                1 => (0..MEMBERS).prop_map(|n| TeamCommand::AddMember {
+                    member_id: member_id(n),
+                    email: format!("member-{}@example.com", n),
+                }),
+                3 => (0..MEMBERS, arb_forecast()).prop_map(|(n, forecast)| {
+                    TeamCommand::TrackMemberForecast {
+                        member_id: member_id(n),
+                        forecast,
+                    }
+                }),
+                1 => (0..MEMBERS).prop_map(|n| TeamCommand::RemoveMember {
+                    member_id: member_id(n),
+                }),
+            ]
+        }
+
+        fn mean(values: Vec<f32>) -> Option<f32> {
+            (!values.is_empty()).then(|| values.iter().sum::<f32>() / values.len() as f32)
+        }
+
+        fn assert_close(actual: Option<f32>, expected: Option<f32>) -> Result<(), TestCaseError> {
+            match (actual, expected) {
+                (Some(actual), Some(expected)) => {
+                    prop_assert!(actual.is_finite());
+                    prop_assert!(
+                        (actual - expected).abs() <= 1e-3 * expected.abs().max(1.0),
+                        "{} != {}",
+                        actual,
+                        expected
+                    );
+                }
+                (actual, expected) => prop_assert_eq!(actual, expected),
+            }
+            Ok(())
+        }
+
+        proptest! {
+            #[test]
+            fn prop_view_matches_team_state(commands in vec(arb_command(), 0..40)) {
+                let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+                let mut team = Team::default();
+                let mut view = TeamView::default();
+                let mut tracked = 0;
+
+                for command in commands {
+                    let is_tracking = matches!(command, TeamCommand::TrackMemberForecast { .. });
+                    let Ok(events) = runtime.block_on(team.handle(command.into(), &TeamServices)) else {
+                        continue;
+                    };
+                    if is_tracking {
+                        tracked += 1;
+                    }
+                    for event in events {
+                        view.update(&envelope(event.clone()));
+                        team.apply(event);
+                    }
+                }
+
+                let forecasts: Vec<&WeatherForecast> = team.forecasts.values().collect();
+                prop_assert_eq!(&view.forecasts, &team.forecasts);
+                prop_assert_eq!(&view.members, &team.members);
+                prop_assert_eq!(view.total_forecasts_tracked, tracked);
+
+                assert_close(
+                    view.avg_minimum_temperature.as_ref().map(|t| t.0),
+                    mean(forecasts.iter().map(|f| f.minimum_temperature.0).collect()),
+                )?;
+                assert_close(
+                    view.avg_maximum_temperature.as_ref().map(|t| t.0),
+                    mean(forecasts.iter().map(|f| f.maximum_temperature.0).collect()),
+                )?;
+                assert_close(
+                    view.avg_minimum_apparent_temperature.as_ref().map(|t| t.0),
+                    mean(
+                        forecasts
+                            .iter()
+                            .filter_map(|f| f.minimum_apparent_temperature.as_ref().map(|t| t.0))
+                            .collect(),
+                    ),
+                )?;
+                assert_close(
+                    view.avg_maximum_wind_speed.as_ref().map(|w| w.value()),
+                    mean(
+                        forecasts
+                            .iter()
+                            .filter_map(|f| f.maximum_wind_speed.as_ref().map(|w| w.value()))
+                            .collect(),
+                    ),
+                )?;
+                assert_close(
+                    view.avg_comfort_index,
+                    mean(forecasts.iter().map(|f| f.comfort_index()).collect()),
+                )?;
+                prop_assert_eq!(
+                    view.lowest_minimum_temperature.as_ref().map(|e| e.temperature.0),
                     forecasts
-                        .values()
+                        .iter()
                         .map(|f| f.minimum_temperature.0)
-                        .sum::<f32>()
-                        / n_forecasts,
+                        .reduce(f32::min)
                 );
-                let avg_maximum_temperature = CelsiusTemperature(
-                    forecasts
-                        .values()
-                        .map(|f| f.maximum_temperature.0)
-                        .sum::<f32>()
-                        / n_forecasts,
+                prop_assert_eq!(
+                    view.weather_condition_distribution.values().sum::<i32>() as usize,
+                    forecasts.iter().filter(|f| f.weather_code.is_some()).count()
                 );
 
-                let weather_condition_distribution =
-                    forecasts.values().fold(HashMap::new(), |mut acc, f| {
-                        if let Some(wc) = &f.weather_code {
-                            *acc.entry(wc.clone()).or_insert(0) += 1;
-                        }
-                        acc
-                    });
-
-                self.forecasts = forecasts.clone();
-                self.avg_maximum_temperature = Some(avg_maximum_temperature);
-                self.avg_minimum_temperature = Some(avg_minimum_temperature);
-                self.weather_condition_distribution = weather_condition_distribution;
-                self.total_forecasts_tracked += 1;
+                let json = serde_json::to_string(&view).unwrap();
+                prop_assert!(serde_json::from_str::<TeamView>(&json).is_ok());
             }
         }
     }
@@ -0,0 +1,388 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use cqrs_es::EventEnvelope;
+use rocket::FromFormField;
+use serde::Deserialize;
+use sqlx::{PgConnection, Pool, Postgres};
+use utoipa::ToSchema;
+
+use snowy_model::{TeamId, TeamSummary, TeamSummaryPage};
+
+use crate::{
+    api::error::Error,
+    domain::{aggregates::Team, events::TeamEvent},
+    projections::{Projection, ProjectionError},
+};
+
+/// Maintains the `team_summary` tables backing the team listing.
+pub(crate) struct TeamSummaryProjection;
+
+impl TeamSummaryProjection {
+    async fn update(
+        &self,
+        conn: &mut PgConnection,
+        team_id: &str,
+        events: &[EventEnvelope<Team>],
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO team_summary (team_id) VALUES ($1) ON CONFLICT (team_id) DO NOTHING",
+        )
+        .bind(team_id)
+        .execute(&mut *conn)
+        .await?;
+
+        for event in events {
+            match &event.payload {
+                TeamEvent::MemberAdded { member_id, email } => {
+                    sqlx::query(
+                        "INSERT INTO team_summary_members (team_id, member_id, email)
+                         VALUES ($1, $2, $3)
+                         ON CONFLICT (team_id, member_id) DO UPDATE SET email = EXCLUDED.email",
+                    )
+                    .bind(team_id)
+                    .bind(member_id.as_str())
+                    .bind(email)
+                    .execute(&mut *conn)
+                    .await?;
+                }
+                TeamEvent::MemberRemoved { member_id } => {
+                    sqlx::query(
+                        "DELETE FROM team_summary_members WHERE team_id = $1 AND member_id = $2",
+                    )
+                    .bind(team_id)
+                    .bind(member_id.as_str())
+                    .execute(&mut *conn)
+                    .await?;
+                }
+                _ => {}
+            }
+        }
+
+        // The last activity is when the latest event was recorded, not when
+        // it is projected.
+        let sequence = events.last().map_or(0, |event| event.sequence as i64);
+        sqlx::query(
+            "UPDATE team_summary
+             SET member_count = (SELECT count(*) FROM team_summary_members m WHERE m.team_id = $1),
+                 last_activity_at = COALESCE(
+                     (SELECT recorded_at FROM events
+                      WHERE aggregate_type = 'team' AND aggregate_id = $1 AND sequence = $2),
+                     last_activity_at)
+             WHERE team_id = $1",
+        )
+        .bind(team_id)
+        .bind(sequence)
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Projection for TeamSummaryProjection {
+    fn name(&self) -> &'static str {
+        "team_summary"
+    }
+
+    async fn reset(&self, conn: &mut PgConnection) -> Result<(), ProjectionError> {
+        // Cascades to `team_summary_members`.
+        sqlx::query("DELETE FROM team_summary")
+            .execute(&mut *conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn project(
+        &self,
+        conn: &mut PgConnection,
+        aggregate_id: &str,
+        events: &[EventEnvelope<Team>],
+    ) -> Result<(), ProjectionError> {
+        Ok(self.update(conn, aggregate_id, events).await?)
+    }
+}
+
+/// Column the team listing is sorted by.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Deserialize,
+    FromFormField,
+    ToSchema,
+    async_graphql::Enum,
+)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum TeamSort {
+    #[default]
+    Name,
+    #[field(value = "member_count")]
+    MemberCount,
+    #[field(value = "last_activity")]
+    LastActivity,
+}
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Deserialize,
+    FromFormField,
+    ToSchema,
+    async_graphql::Enum,
+)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// The teams a caller may list.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Visibility {
+    /// A single team, for API keys.
+    Team(String),
+    /// Every team with a member holding this email, for people.
+    Member(String),
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct TeamSearch {
+    /// Matched against team names and member emails.
+    pub(crate) text: Option<String>,
+    pub(crate) sort: TeamSort,
+    pub(crate) order: SortOrder,
+    pub(crate) offset: u64,
+    pub(crate) limit: u64,
+    pub(crate) visibility: Visibility,
+}
+
+#[derive(sqlx::FromRow)]
+struct TeamSummaryRow {
+    team_id: String,
+    name: String,
+    member_count: i32,
+    last_activity_at: DateTime<Utc>,
+}
+
+impl From<TeamSummaryRow> for TeamSummary {
+    fn from(row: TeamSummaryRow) -> Self {
+        Self {
+            id: TeamId::from(row.team_id),
+            name: row.name,
+            member_count: row.member_count.max(0) as u32,
+            last_activity_at: row.last_activity_at,
+        }
+    }
+}
+
+const SEARCH_FILTER: &str = "
+    WHERE ($1::text IS NULL OR s.team_id = $1)
+      AND ($2::text IS NULL OR EXISTS (
+          SELECT 1 FROM team_summary_members m
+          WHERE m.team_id = s.team_id AND lower(m.email) = lower($2)))
+      AND ($3::text IS NULL OR s.name ILIKE $3 OR EXISTS (
+          SELECT 1 FROM team_summary_members m
+          WHERE m.team_id = s.team_id AND m.email ILIKE $3))";
+
+/// Reads the `team_summary` projection.
+pub(crate) struct TeamSummaryRepository {
+    pool: Pool<Postgres>,
+}
+
+impl TeamSummaryRepository {
+    pub(crate) fn new(pool: Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+
+    pub(crate) async fn search(&self, search: &TeamSearch) -> Result<TeamSummaryPage, Error> {
+        let offset = i64::try_from(search.offset)
+            .map_err(|_| Error::BadRequest(format!("Offset {} is too large", search.offset)))?;
+        let (team_id, email) = match &search.visibility {
+            Visibility::Team(team_id) => (Some(team_id.as_str()), None),
+            Visibility::Member(email) => (None, Some(email.as_str())),
+        };
+        let pattern = search.text.as_deref().map(like_pattern);
+
+        let total: i64 = sqlx::query_scalar(&format!(
+            "SELECT count(*) FROM team_summary s {}",
+            SEARCH_FILTER
+        ))
+        .bind(team_id)
+        .bind(email)
+        .bind(pattern.as_deref())
+        .fetch_one(&self.pool)
+        .await?;
+
+        let rows = sqlx::query_as::<_, TeamSummaryRow>(&format!(
+            "SELECT s.team_id, s.name, s.member_count, s.last_activity_at
+             FROM team_summary s {} {} OFFSET $4 LIMIT $5",
+            SEARCH_FILTER,
+            order_by(search.sort, search.order)
+        ))
+        .bind(team_id)
+        .bind(email)
+        .bind(pattern.as_deref())
+        .bind(offset)
+        .bind(search.limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(TeamSummaryPage {
+            teams: rows.into_iter().map(TeamSummary::from).collect(),
+            total: total as u64,
+            offset: search.offset,
+            limit: search.limit,
+        })
+    }
+}
+
+fn order_by(sort: TeamSort, order: SortOrder) -> String {
+    let column = match sort {
+        TeamSort::Name => "lower(s.name)",
+        TeamSort::MemberCount => "s.member_count",
+        TeamSort::LastActivity => "s.last_activity_at",
+    };
+    let direction = match order {
+        SortOrder::Asc => "ASC",
+        SortOrder::Desc => "DESC",
+    };
+    format!("ORDER BY {column} {direction}, s.team_id {direction}")
+}
+
+/// Case-insensitive substring pattern for `ILIKE`, with wildcards in `text` escaped.
+fn like_pattern(text: &str) -> String {
+    let escaped = text
+        .trim()
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rocket::tokio;
+    use snowy_model::MemberId;
+
+    use super::*;
+    use crate::{
+        api::{cqrs::setup_cqrs, metrics::Metrics},
+        domain::commands::TeamCommand,
+        testing,
+    };
+
+    fn search(visibility: Visibility) -> TeamSearch {
+        TeamSearch {
+            text: None,
+            sort: TeamSort::MemberCount,
+            order: SortOrder::Desc,
+            offset: 0,
+            limit: 10,
+            visibility,
+        }
+    }
+
+    fn team_ids(page: &TeamSummaryPage) -> Vec<&str> {
+        page.teams.iter().map(|team| team.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_like_pattern_escapes_wildcards() {
+        assert_eq!(like_pattern(" snow "), "%snow%");
+        assert_eq!(like_pattern("100%_done"), "%100\\%\\_done%");
+    }
+
+    #[test]
+    fn test_order_by() {
+        assert_eq!(
+            order_by(TeamSort::default(), SortOrder::default()),
+            "ORDER BY lower(s.name) ASC, s.team_id ASC"
+        );
+        assert_eq!(
+            order_by(TeamSort::LastActivity, SortOrder::Desc),
+            "ORDER BY s.last_activity_at DESC, s.team_id DESC"
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL, see SNOWY_TEST_DATABASE_URL"]
+    async fn test_search_projected_teams() {
+        let pool = testing::database().await;
+        let cqrs = setup_cqrs(pool.clone(), &Metrics::new().unwrap());
+        for (team_id, members) in [
+            ("team-1", &["alice", "bob"][..]),
+            ("team-2", &["carol"][..]),
+            ("team-3", &["alice", "dave", "erin"][..]),
+        ] {
+            for member in members {
+                let command = TeamCommand::AddMember {
+                    member_id: MemberId::new(member.to_string()),
+                    email: format!("{}@example.com", member),
+                };
+                cqrs.execute(team_id, command, HashMap::new())
+                    .await
+                    .unwrap();
+            }
+            testing::project(&pool, &TeamSummaryProjection, team_id).await;
+        }
+        let repository = TeamSummaryRepository::new(pool.clone());
+
+        let alice = Visibility::Member("ALICE@example.com".to_string());
+        let page = repository.search(&search(alice.clone())).await.unwrap();
+        assert_eq!(page.total, 2);
+        assert_eq!(team_ids(&page), vec!["team-3", "team-1"]);
+        assert_eq!(page.teams[0].member_count, 3);
+
+        let page = repository
+            .search(&TeamSearch {
+                text: Some("bob".to_string()),
+                ..search(alice.clone())
+            })
+            .await
+            .unwrap();
+        assert_eq!(team_ids(&page), vec!["team-1"]);
+
+        let page = repository
+            .search(&TeamSearch {
+                offset: 1,
+                limit: 1,
+                ..search(alice)
+            })
+            .await
+            .unwrap();
+        assert_eq!(page.total, 2);
+        assert_eq!(team_ids(&page), vec!["team-1"]);
+
+        let page = repository
+            .search(&search(Visibility::Team("team-2".to_string())))
+            .await
+            .unwrap();
+        assert_eq!(team_ids(&page), vec!["team-2"]);
+
+        let recorded_at: DateTime<Utc> =
+            sqlx::query_scalar("SELECT max(recorded_at) FROM events WHERE aggregate_id = 'team-2'")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(page.teams[0].last_activity_at, recorded_at);
+
+        let too_far = repository
+            .search(&TeamSearch {
+                offset: u64::MAX,
+                ..search(Visibility::Team("team-2".to_string()))
+            })
+            .await;
+        assert!(matches!(too_far, Err(Error::BadRequest(_))));
+    }
+}
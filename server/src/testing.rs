@@ -0,0 +1,164 @@
+use std::{collections::HashSet, path::Path, str::FromStr, sync::Mutex};
+
+use async_trait::async_trait;
+use cqrs_es::{persist::PersistedEventStore, EventEnvelope, EventStore};
+use postgres_es::PostgresEventRepository;
+use sqlx::{postgres::PgConnectOptions, Connection, Executor, PgConnection, Pool, Postgres};
+
+use crate::{
+    domain::aggregates::Team,
+    projections::{Projection, ProjectionError},
+};
+
+/// PostgreSQL server the database tests create their databases on.
+pub(crate) const DATABASE_URL_VAR: &str = "SNOWY_TEST_DATABASE_URL";
+
+/// Creates a fresh database with every Liquibase changeset applied.
+pub(crate) async fn database() -> Pool<Postgres> {
+    let pool = empty_database().await;
+    apply_changesets(&pool, |_| true).await;
+    pool
+}
+
+/// Creates a fresh database with the changesets whose file names sort
+/// before `changeset` applied, to test how a changeset migrates existing data.
+pub(crate) async fn database_before(changeset: &str) -> Pool<Postgres> {
+    let pool = empty_database().await;
+    apply_changesets(&pool, |name| name < changeset).await;
+    pool
+}
+
+/// Applies `changeset` and the changesets after it.
+pub(crate) async fn apply_changesets_from(pool: &Pool<Postgres>, changeset: &str) {
+    apply_changesets(pool, |name| name >= changeset).await;
+}
+
+async fn empty_database() -> Pool<Postgres> {
+    let url = std::env::var(DATABASE_URL_VAR)
+        .unwrap_or_else(|_| panic!("{} must name a PostgreSQL server", DATABASE_URL_VAR));
+    let name = format!("snowy_test_{}", uuid::Uuid::new_v4().simple());
+    let mut server = PgConnection::connect(&url).await.unwrap();
+    server
+        .execute(format!("CREATE DATABASE {}", name).as_str())
+        .await
+        .unwrap();
+
+    let options = PgConnectOptions::from_str(&url).unwrap().database(&name);
+    Pool::connect_with(options).await.unwrap()
+}
+
+/// Applies the changesets whose file names match, in the order of their
+/// file names.
+async fn apply_changesets(pool: &Pool<Postgres>, applies: impl Fn(&str) -> bool) {
+    let changesets = Path::new(env!("CARGO_MANIFEST_DIR")).join("db/liquibase/changesets");
+    let mut files: Vec<_> = std::fs::read_dir(changesets)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| applies(&path.file_name().unwrap().to_string_lossy()))
+        .collect();
+    files.sort();
+    for file in files {
+        let sql = std::fs::read_to_string(&file).unwrap();
+        sqlx::raw_sql(&sql)
+            .execute(pool)
+            .await
+            .unwrap_or_else(|e| panic!("{}: {}", file.display(), e));
+    }
+}
+
+/// Applies every event of `team_id` to `projection` in one transaction.
+pub(crate) async fn project(pool: &Pool<Postgres>, projection: &dyn Projection, team_id: &str) {
+    let store =
+        PersistedEventStore::<_, Team>::new_event_store(PostgresEventRepository::new(pool.clone()));
+    let events = store.load_events(team_id).await.unwrap();
+    let mut tx = pool.begin().await.unwrap();
+    projection.project(&mut tx, team_id, &events).await.unwrap();
+    tx.commit().await.unwrap();
+}
+
+/// A projection recording the sequences it applies in `flaky_projection`,
+/// which fails the events marked as failing.
+pub(crate) struct FlakyProjection {
+    failing: Mutex<HashSet<(String, usize)>>,
+}
+
+impl FlakyProjection {
+    /// Creates its table and its checkpoint at the first event.
+    pub(crate) async fn new(pool: &Pool<Postgres>) -> Self {
+        sqlx::raw_sql(
+            "CREATE TABLE flaky_projection (
+                 id bigserial, aggregate_id text NOT NULL, sequence bigint NOT NULL
+             );
+             INSERT INTO projection_checkpoints (projection) VALUES ('flaky');",
+        )
+        .execute(pool)
+        .await
+        .unwrap();
+        Self {
+            failing: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub(crate) fn set_failing(&self, aggregate_id: &str, sequence: usize, failing: bool) {
+        let event = (aggregate_id.to_string(), sequence);
+        let mut events = self.failing.lock().unwrap();
+        if failing {
+            events.insert(event);
+        } else {
+            events.remove(&event);
+        }
+    }
+
+    /// The sequences applied for `aggregate_id`, in the order they were.
+    pub(crate) async fn applied(pool: &Pool<Postgres>, aggregate_id: &str) -> Vec<i64> {
+        sqlx::query_scalar(
+            "SELECT sequence FROM flaky_projection WHERE aggregate_id = $1 ORDER BY id",
+        )
+        .bind(aggregate_id)
+        .fetch_all(pool)
+        .await
+        .unwrap()
+    }
+}
+
+#[async_trait]
+impl Projection for FlakyProjection {
+    fn name(&self) -> &'static str {
+        "flaky"
+    }
+
+    async fn project(
+        &self,
+        conn: &mut PgConnection,
+        aggregate_id: &str,
+        events: &[EventEnvelope<Team>],
+    ) -> Result<(), ProjectionError> {
+        for event in events {
+            sqlx::query("INSERT INTO flaky_projection (aggregate_id, sequence) VALUES ($1, $2)")
+                .bind(aggregate_id)
+                .bind(event.sequence as i64)
+                .execute(&mut *conn)
+                .await?;
+            let failing = self
+                .failing
+                .lock()
+                .unwrap()
+                .contains(&(aggregate_id.to_string(), event.sequence));
+            if failing {
+                return Err(sqlx::Error::Protocol(format!(
+                    "cannot apply {}/{}",
+                    aggregate_id, event.sequence
+                ))
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    async fn reset(&self, conn: &mut PgConnection) -> Result<(), ProjectionError> {
+        sqlx::query("DELETE FROM flaky_projection")
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,188 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+use reqwest::{
+    dns::{Addrs, Name, Resolve, Resolving},
+    Url,
+};
+use tokio::net::lookup_host;
+
+use crate::api::error::Error;
+
+/// Whether webhooks may be sent to `ip`: not a loopback, private, shared,
+/// link-local (which includes cloud metadata endpoints), multicast,
+/// unspecified, reserved, benchmarking or documentation address, nor one
+/// translated to such an IPv4 address.
+pub(crate) fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [first, second, third, _] = ip.octets();
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || first == 0
+                // 100.64.0.0/10, shared between carrier-grade NAT customers
+                || (first == 100 && second & 0xc0 == 64)
+                // 192.0.0.0/24, IETF protocol assignments
+                || (first == 192 && second == 0 && third == 0)
+                // 198.18.0.0/15, benchmarking
+                || (first == 198 && second & 0xfe == 18)
+                // 240.0.0.0/4, reserved, including the broadcast address
+                || first >= 240)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                let segments = ip.segments();
+                // 64:ff9b::/96, the NAT64 prefix translating to the IPv4
+                // address in the last 32 bits
+                if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+                    let [.., high, low] = segments;
+                    let embedded = Ipv4Addr::from((u32::from(high) << 16) | u32::from(low));
+                    return is_public(IpAddr::V4(embedded));
+                }
+                let first = segments[0];
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    // fc00::/7, unique local
+                    || first & 0xfe00 == 0xfc00
+                    // fe80::/10, link-local
+                    || first & 0xffc0 == 0xfe80
+                    // 2001:db8::/32, documentation
+                    || (first == 0x2001 && segments[1] == 0xdb8))
+            }
+        },
+    }
+}
+
+/// Checks that `url` is an http(s) URL whose host only resolves to public
+/// addresses, unless private ones are allowed.
+pub(crate) async fn check_url(url: &str, allow_private: bool) -> Result<(), Error> {
+    let parsed = match Url::parse(url) {
+        Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => parsed,
+        _ => {
+            return Err(Error::BadRequest(format!(
+                "'{}' is not an http(s) URL",
+                url
+            )))
+        }
+    };
+    if allow_private {
+        return Ok(());
+    }
+
+    let host = parsed
+        .host_str()
+        .ok_or_else(|| Error::BadRequest(format!("'{}' has no host", url)))?;
+    let port = parsed.port_or_known_default().unwrap_or(443);
+    // IPv6 hosts keep their brackets in URLs.
+    let literal = host.trim_start_matches('[').trim_end_matches(']');
+    let addresses: Vec<IpAddr> = match literal.parse() {
+        Ok(ip) => vec![ip],
+        Err(_) => lookup_host((host, port))
+            .await
+            .map_err(|e| Error::BadRequest(format!("Cannot resolve '{}': {}", host, e)))?
+            .map(|address| address.ip())
+            .collect(),
+    };
+    match addresses.into_iter().find(|ip| !is_public(*ip)) {
+        Some(ip) => Err(Error::BadRequest(format!(
+            "'{}' resolves to the non-public address {}",
+            url, ip
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Resolves webhook hosts to their public addresses only, so that a host
+/// cannot be pointed at an internal address once its URL was checked.
+pub(crate) struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let public: Vec<SocketAddr> = lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|address| is_public(address.ip()))
+                .collect();
+            if public.is_empty() {
+                return Err(format!("'{}' resolves to no public address", name.as_str()).into());
+            }
+            Ok(Box::new(public.into_iter()) as Addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_public() {
+        for ip in [
+            "8.8.8.8",
+            "1.1.1.1",
+            "2606:4700:4700::1111",
+            "::ffff:8.8.8.8",
+            "64:ff9b::808:808",
+        ] {
+            assert!(is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "192.0.0.8",
+            "192.0.2.1",
+            "198.18.0.1",
+            "198.19.255.254",
+            "198.51.100.7",
+            "203.0.113.7",
+            "240.0.0.1",
+            "255.255.255.255",
+            "64:ff9b::a00:1",
+            "64:ff9b::7f00:1",
+            "2001:db8::1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_url() {
+        assert!(check_url("https://8.8.8.8/hooks", false).await.is_ok());
+        for url in [
+            "http://localhost:8080",
+            "http://127.0.0.1/hooks",
+            "http://169.254.169.254/latest/meta-data",
+            "http://[::1]/hooks",
+            "http://[::ffff:10.0.0.1]/hooks",
+            "http://[64:ff9b::a9fe:a9fe]/latest/meta-data",
+        ] {
+            assert!(
+                matches!(check_url(url, false).await, Err(Error::BadRequest(_))),
+                "{}",
+                url
+            );
+        }
+        assert!(check_url("http://localhost:8080", true).await.is_ok());
+        for url in ["ftp://example.com", "not a url"] {
+            assert!(matches!(
+                check_url(url, true).await,
+                Err(Error::BadRequest(_))
+            ));
+        }
+    }
+}
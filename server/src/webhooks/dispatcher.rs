@@ -0,0 +1,105 @@
+use std::time::Duration;
+
+use rocket::{futures::future::join_all, tokio::select, Shutdown};
+use sqlx::{Pool, Postgres};
+use tracing::{debug, error, warn};
+
+use crate::api::config::WebhookConfig;
+
+use super::{
+    repository::{DeliveryStatus, PendingDelivery, WebhookRepository},
+    sender::WebhookSender,
+};
+
+/// Extra time a claimed delivery stays leased beyond the request timeout.
+const LEASE_MARGIN: Duration = Duration::from_secs(30);
+
+/// Interval between deletions of deliveries and outbox entries past retention.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Delivers team events to webhooks until the server shuts down.
+///
+/// A trigger copies every event into `outbox` in the transaction that stores
+/// it; each poll fans new entries out into one delivery per interested
+/// subscription, then sends the deliveries that are due. Finished deliveries
+/// and their entries are deleted once past the retention period.
+pub(crate) struct WebhookDispatcher {
+    repository: WebhookRepository,
+    sender: WebhookSender,
+    config: WebhookConfig,
+}
+
+impl WebhookDispatcher {
+    pub(crate) fn new(pool: Pool<Postgres>, config: WebhookConfig) -> Self {
+        Self {
+            repository: WebhookRepository::new(pool, config.allow_private_addresses),
+            sender: WebhookSender::new(
+                Duration::from_secs(config.timeout_secs),
+                config.allow_private_addresses,
+            ),
+            config,
+        }
+    }
+
+    pub(crate) async fn run(self, mut shutdown: Shutdown) {
+        let mut interval =
+            tokio::time::interval(Duration::from_millis(self.config.poll_interval_ms));
+        let mut prune_interval = tokio::time::interval(PRUNE_INTERVAL);
+        let retention = Duration::from_secs(self.config.retention_days.saturating_mul(86_400));
+        loop {
+            select! {
+                _ = &mut shutdown => break,
+                _ = interval.tick() => {
+                    if let Err(e) = self.poll().await {
+                        error!(error = %e, "webhook dispatch failed");
+                    }
+                }
+                _ = prune_interval.tick() => match self.repository.prune(retention).await {
+                    Ok(0) => {}
+                    Ok(pruned) => debug!(entries = pruned, "pruned outbox entries"),
+                    Err(e) => error!(error = %e, "pruning the outbox failed"),
+                },
+            }
+        }
+    }
+
+    async fn poll(&self) -> Result<(), sqlx::Error> {
+        let fanned_out = self.repository.fan_out(self.config.batch_size).await?;
+        if fanned_out > 0 {
+            debug!(entries = fanned_out, "fanned out outbox entries");
+        }
+
+        let lease = Duration::from_secs(self.config.timeout_secs) + LEASE_MARGIN;
+        let due = self
+            .repository
+            .claim_due(self.config.batch_size, lease)
+            .await?;
+        for result in join_all(due.iter().map(|delivery| self.deliver(delivery))).await {
+            result?;
+        }
+        Ok(())
+    }
+
+    async fn deliver(&self, delivery: &PendingDelivery) -> Result<(), sqlx::Error> {
+        let attempt = self.sender.send(delivery).await;
+        let number = attempt.attempt as u32;
+        let (status, retry_in) = match &attempt.error {
+            None => (DeliveryStatus::Delivered, Duration::ZERO),
+            Some(_) if number >= self.config.max_attempts => (DeliveryStatus::Dead, Duration::ZERO),
+            Some(_) => (DeliveryStatus::Pending, self.config.backoff(number)),
+        };
+        if let Some(e) = &attempt.error {
+            warn!(
+                delivery_id = %delivery.id,
+                url = %delivery.url,
+                attempt = number,
+                dead = status == DeliveryStatus::Dead,
+                error = %e,
+                "webhook delivery failed"
+            );
+        }
+        self.repository
+            .record_attempt(delivery, &attempt, status, retry_in)
+            .await
+    }
+}
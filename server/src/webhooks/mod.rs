@@ -0,0 +1,10 @@
+mod address;
+mod dispatcher;
+mod repository;
+mod sender;
+
+pub(crate) use dispatcher::WebhookDispatcher;
+pub(crate) use repository::{
+    DeliveryAttempt, DeliveryStatus, IssuedWebhookSubscription, WebhookDelivery, WebhookRepository,
+    WebhookSubscription,
+};
@@ -0,0 +1,604 @@
+use std::{fmt, time::Duration};
+
+use chrono::{DateTime, Utc};
+use rocket::FromFormField;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{types::Json, Pool, Postgres};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{api::error::Error, domain::events::TeamEvent};
+
+use super::address::check_url;
+
+const SECRET_PREFIX: &str = "whsec";
+
+/// A webhook registered for a team, without its secret.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow, ToSchema)]
+pub(crate) struct WebhookSubscription {
+    pub(crate) id: Uuid,
+    pub(crate) team_id: String,
+    pub(crate) url: String,
+    /// Event types delivered to the webhook; empty for every type.
+    pub(crate) event_types: Vec<String>,
+    pub(crate) created_at: DateTime<Utc>,
+    pub(crate) disabled_at: Option<DateTime<Utc>>,
+}
+
+/// A freshly registered webhook; `secret` is only ever shown at this point.
+#[derive(Serialize, ToSchema)]
+pub(crate) struct IssuedWebhookSubscription {
+    #[serde(flatten)]
+    pub(crate) subscription: WebhookSubscription,
+    /// Key of the HMAC-SHA256 signature sent with every delivery.
+    pub(crate) secret: String,
+}
+
+/// State of a webhook delivery.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Deserialize, Serialize, FromFormField, ToSchema, sqlx::Type,
+)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+pub(crate) enum DeliveryStatus {
+    /// Waiting for its first or next attempt.
+    Pending,
+    Delivered,
+    /// Gave up after the maximum number of attempts.
+    Dead,
+}
+
+/// An event to be delivered to one webhook, with its attempts so far.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow, ToSchema)]
+pub(crate) struct WebhookDelivery {
+    pub(crate) id: Uuid,
+    pub(crate) subscription_id: Uuid,
+    pub(crate) sequence: i64,
+    pub(crate) event_type: String,
+    pub(crate) status: DeliveryStatus,
+    pub(crate) attempts: i32,
+    pub(crate) next_attempt_at: DateTime<Utc>,
+    pub(crate) last_status_code: Option<i32>,
+    pub(crate) last_error: Option<String>,
+    pub(crate) created_at: DateTime<Utc>,
+    pub(crate) delivered_at: Option<DateTime<Utc>>,
+    #[sqlx(skip)]
+    pub(crate) history: Vec<DeliveryAttempt>,
+}
+
+/// One HTTP request made for a delivery.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow, ToSchema)]
+pub(crate) struct DeliveryAttempt {
+    #[serde(skip)]
+    pub(crate) delivery_id: Uuid,
+    pub(crate) attempt: i32,
+    pub(crate) attempted_at: DateTime<Utc>,
+    /// HTTP status of the response; absent when no response was received.
+    pub(crate) status_code: Option<i32>,
+    pub(crate) error: Option<String>,
+    pub(crate) duration_ms: i64,
+}
+
+/// A delivery claimed by the dispatcher, with everything needed to send it.
+#[derive(Clone, sqlx::FromRow)]
+pub(crate) struct PendingDelivery {
+    pub(crate) id: Uuid,
+    pub(crate) attempts: i32,
+    pub(crate) url: String,
+    pub(crate) secret: String,
+    pub(crate) team_id: String,
+    pub(crate) sequence: i64,
+    pub(crate) event_type: String,
+    pub(crate) payload: Json<Value>,
+    pub(crate) recorded_at: DateTime<Utc>,
+}
+
+impl fmt::Debug for IssuedWebhookSubscription {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IssuedWebhookSubscription")
+            .field("subscription", &self.subscription)
+            .field("secret", &"<redacted>")
+            .finish()
+    }
+}
+
+impl fmt::Debug for PendingDelivery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PendingDelivery")
+            .field("id", &self.id)
+            .field("attempts", &self.attempts)
+            .field("url", &self.url)
+            .field("secret", &"<redacted>")
+            .field("team_id", &self.team_id)
+            .field("sequence", &self.sequence)
+            .field("event_type", &self.event_type)
+            .field("payload", &self.payload)
+            .field("recorded_at", &self.recorded_at)
+            .finish()
+    }
+}
+
+fn generate_secret() -> String {
+    format!(
+        "{}_{}{}",
+        SECRET_PREFIX,
+        Uuid::new_v4().simple(),
+        Uuid::new_v4().simple()
+    )
+}
+
+#[derive(Clone)]
+pub(crate) struct WebhookRepository {
+    pool: Pool<Postgres>,
+    allow_private_addresses: bool,
+}
+
+impl WebhookRepository {
+    pub(crate) fn new(pool: Pool<Postgres>, allow_private_addresses: bool) -> Self {
+        Self {
+            pool,
+            allow_private_addresses,
+        }
+    }
+
+    pub(crate) async fn subscribe(
+        &self,
+        team_id: &str,
+        url: &str,
+        event_types: Vec<String>,
+    ) -> Result<IssuedWebhookSubscription, Error> {
+        check_url(url, self.allow_private_addresses).await?;
+        if let Some(unknown) = event_types
+            .iter()
+            .find(|t| !TeamEvent::EVENT_TYPES.contains(&t.as_str()))
+        {
+            return Err(Error::BadRequest(format!(
+                "Unknown event type '{}'",
+                unknown
+            )));
+        }
+
+        let secret = generate_secret();
+        let subscription = sqlx::query_as::<_, WebhookSubscription>(
+            "INSERT INTO webhook_subscriptions (id, team_id, url, secret, event_types)
+             VALUES ($1, $2, $3, $4, $5)
+             RETURNING id, team_id, url, event_types, created_at, disabled_at",
+        )
+        .bind(Uuid::new_v4())
+        .bind(team_id)
+        .bind(url)
+        .bind(&secret)
+        .bind(event_types)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(IssuedWebhookSubscription {
+            subscription,
+            secret,
+        })
+    }
+
+    pub(crate) async fn subscriptions(
+        &self,
+        team_id: &str,
+    ) -> Result<Vec<WebhookSubscription>, Error> {
+        Ok(sqlx::query_as::<_, WebhookSubscription>(
+            "SELECT id, team_id, url, event_types, created_at, disabled_at
+             FROM webhook_subscriptions WHERE team_id = $1 ORDER BY created_at",
+        )
+        .bind(team_id)
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    /// Stops deliveries to a webhook, returning `false` if it does not exist
+    /// or was already disabled. Deliveries already created are kept.
+    pub(crate) async fn disable(&self, team_id: &str, id: Uuid) -> Result<bool, Error> {
+        let result = sqlx::query(
+            "UPDATE webhook_subscriptions SET disabled_at = now()
+             WHERE id = $1 AND team_id = $2 AND disabled_at IS NULL",
+        )
+        .bind(id)
+        .bind(team_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// The latest deliveries to a webhook of `team_id`, newest first, with
+    /// their attempts; `None` if the team has no such webhook.
+    pub(crate) async fn deliveries(
+        &self,
+        team_id: &str,
+        subscription_id: Uuid,
+        status: Option<DeliveryStatus>,
+        limit: i64,
+    ) -> Result<Option<Vec<WebhookDelivery>>, Error> {
+        let exists: bool = sqlx::query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM webhook_subscriptions WHERE id = $1 AND team_id = $2)",
+        )
+        .bind(subscription_id)
+        .bind(team_id)
+        .fetch_one(&self.pool)
+        .await?;
+        if !exists {
+            return Ok(None);
+        }
+
+        let mut deliveries = sqlx::query_as::<_, WebhookDelivery>(
+            "SELECT d.id, d.subscription_id, o.sequence, o.event_type, d.status, d.attempts,
+                    d.next_attempt_at, d.last_status_code, d.last_error, d.created_at,
+                    d.delivered_at
+             FROM webhook_deliveries d JOIN outbox o ON o.id = d.outbox_id
+             WHERE d.subscription_id = $1 AND ($2::text IS NULL OR d.status = $2)
+             ORDER BY d.created_at DESC, o.sequence DESC
+             LIMIT $3",
+        )
+        .bind(subscription_id)
+        .bind(status)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let ids: Vec<Uuid> = deliveries.iter().map(|d| d.id).collect();
+        let attempts = sqlx::query_as::<_, DeliveryAttempt>(
+            "SELECT delivery_id, attempt, attempted_at, status_code, error, duration_ms
+             FROM webhook_attempts WHERE delivery_id = ANY($1) ORDER BY attempt",
+        )
+        .bind(&ids)
+        .fetch_all(&self.pool)
+        .await?;
+        for attempt in attempts {
+            if let Some(delivery) = deliveries.iter_mut().find(|d| d.id == attempt.delivery_id) {
+                delivery.history.push(attempt);
+            }
+        }
+
+        Ok(Some(deliveries))
+    }
+
+    /// Turns up to `limit` unprocessed outbox entries into one delivery per
+    /// active subscription of the team interested in the event type.
+    pub(crate) async fn fan_out(&self, limit: i64) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            "WITH batch AS (
+                 SELECT id, aggregate_id, event_type FROM outbox
+                 WHERE processed_at IS NULL AND aggregate_type = 'team'
+                 ORDER BY id LIMIT $1
+                 FOR UPDATE SKIP LOCKED
+             ),
+             created AS (
+                 INSERT INTO webhook_deliveries (subscription_id, outbox_id)
+                 SELECT s.id, b.id
+                 FROM batch b JOIN webhook_subscriptions s
+                   ON s.team_id = b.aggregate_id
+                  AND s.disabled_at IS NULL
+                  AND (cardinality(s.event_types) = 0 OR b.event_type = ANY (s.event_types))
+                 ON CONFLICT (subscription_id, outbox_id) DO NOTHING
+             )
+             UPDATE outbox SET processed_at = now() WHERE id IN (SELECT id FROM batch)",
+        )
+        .bind(limit)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Claims up to `limit` due deliveries, leasing them for `lease` so that
+    /// other dispatchers skip them while they are being sent.
+    pub(crate) async fn claim_due(
+        &self,
+        limit: i64,
+        lease: Duration,
+    ) -> Result<Vec<PendingDelivery>, sqlx::Error> {
+        sqlx::query_as::<_, PendingDelivery>(
+            "WITH claimed AS (
+                 UPDATE webhook_deliveries
+                 SET next_attempt_at = now() + make_interval(secs => $2)
+                 WHERE id IN (
+                     SELECT id FROM webhook_deliveries
+                     WHERE status = 'pending' AND next_attempt_at <= now()
+                     ORDER BY next_attempt_at LIMIT $1
+                     FOR UPDATE SKIP LOCKED
+                 )
+                 RETURNING id, subscription_id, outbox_id, attempts
+             )
+             SELECT c.id, c.attempts, s.url, s.secret, o.aggregate_id AS team_id, o.sequence,
+                    o.event_type, o.payload, o.recorded_at
+             FROM claimed c
+             JOIN webhook_subscriptions s ON s.id = c.subscription_id
+             JOIN outbox o ON o.id = c.outbox_id
+             ORDER BY o.id",
+        )
+        .bind(limit)
+        .bind(lease.as_secs_f64())
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Records an attempt and the resulting state of the delivery: delivered
+    /// when `status` says so, otherwise retried after `retry_in` or dead.
+    pub(crate) async fn record_attempt(
+        &self,
+        delivery: &PendingDelivery,
+        attempt: &DeliveryAttempt,
+        status: DeliveryStatus,
+        retry_in: Duration,
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "INSERT INTO webhook_attempts
+                 (delivery_id, attempt, attempted_at, status_code, error, duration_ms)
+             VALUES ($1, $2, $3, $4, $5, $6)
+             ON CONFLICT (delivery_id, attempt) DO NOTHING",
+        )
+        .bind(delivery.id)
+        .bind(attempt.attempt)
+        .bind(attempt.attempted_at)
+        .bind(attempt.status_code)
+        .bind(&attempt.error)
+        .bind(attempt.duration_ms)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(
+            "UPDATE webhook_deliveries
+             SET status = $2,
+                 attempts = $3,
+                 last_status_code = $4,
+                 last_error = $5,
+                 next_attempt_at = now() + make_interval(secs => $6),
+                 delivered_at = CASE WHEN $2 = 'delivered' THEN now() END
+             WHERE id = $1",
+        )
+        .bind(delivery.id)
+        .bind(status)
+        .bind(attempt.attempt)
+        .bind(attempt.status_code)
+        .bind(&attempt.error)
+        .bind(retry_in.as_secs_f64())
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await
+    }
+
+    /// Deletes the delivered and dead deliveries of outbox entries processed
+    /// more than `retention` ago, then those entries once none of their
+    /// deliveries is pending. Returns the number of entries deleted.
+    pub(crate) async fn prune(&self, retention: Duration) -> Result<u64, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "DELETE FROM webhook_deliveries d USING outbox o
+             WHERE o.id = d.outbox_id AND d.status <> 'pending'
+               AND o.processed_at < now() - make_interval(secs => $1)",
+        )
+        .bind(retention.as_secs_f64())
+        .execute(&mut *tx)
+        .await?;
+        let result = sqlx::query(
+            "DELETE FROM outbox o
+             WHERE o.processed_at < now() - make_interval(secs => $1)
+               AND NOT EXISTS (SELECT 1 FROM webhook_deliveries d WHERE d.outbox_id = o.id)",
+        )
+        .bind(retention.as_secs_f64())
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(result.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::tokio;
+
+    use super::*;
+    use crate::testing;
+
+    const URL: &str = "https://8.8.8.8/hooks";
+
+    /// Records an event of `team_id` in the outbox, as the trigger on `events` does.
+    async fn record(pool: &Pool<Postgres>, team_id: &str, sequence: i64, event_type: &str) {
+        sqlx::query(
+            "INSERT INTO outbox (aggregate_type, aggregate_id, sequence, event_type, payload, metadata)
+             VALUES ('team', $1, $2, $3, '{}', '{}')",
+        )
+        .bind(team_id)
+        .bind(sequence)
+        .bind(event_type)
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    fn attempt(delivery: &PendingDelivery, error: Option<&str>) -> DeliveryAttempt {
+        DeliveryAttempt {
+            delivery_id: delivery.id,
+            attempt: delivery.attempts + 1,
+            attempted_at: Utc::now(),
+            status_code: Some(if error.is_some() { 500 } else { 204 }),
+            error: error.map(str::to_string),
+            duration_ms: 12,
+        }
+    }
+
+    #[test]
+    fn test_generated_secrets_are_unique() {
+        let secret = generate_secret();
+
+        assert!(secret.starts_with("whsec_"));
+        assert_eq!(secret.len(), "whsec_".len() + 64);
+        assert_ne!(secret, generate_secret());
+    }
+
+    #[test]
+    fn test_debug_redacts_secrets() {
+        let delivery = PendingDelivery {
+            id: Uuid::new_v4(),
+            attempts: 0,
+            url: URL.to_string(),
+            secret: generate_secret(),
+            team_id: "team-1".to_string(),
+            sequence: 1,
+            event_type: "member-added".to_string(),
+            payload: Json(Value::Null),
+            recorded_at: Utc::now(),
+        };
+
+        let debug = format!("{:?}", delivery);
+
+        assert!(debug.contains("<redacted>"));
+        assert!(!debug.contains(&delivery.secret));
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL, see SNOWY_TEST_DATABASE_URL"]
+    async fn test_subscribe_checks_urls() {
+        let repository = WebhookRepository::new(testing::database().await, false);
+
+        for url in ["http://127.0.0.1:8080/hooks", "http://169.254.169.254/"] {
+            assert!(matches!(
+                repository.subscribe("team-1", url, vec![]).await,
+                Err(Error::BadRequest(_))
+            ));
+        }
+        assert!(matches!(
+            repository
+                .subscribe("team-1", URL, vec!["launched-rockets".to_string()])
+                .await,
+            Err(Error::BadRequest(_))
+        ));
+        let issued = repository.subscribe("team-1", URL, vec![]).await.unwrap();
+        assert_eq!(
+            repository.subscriptions("team-1").await.unwrap()[0].id,
+            issued.subscription.id
+        );
+        assert!(repository.subscriptions("team-2").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL, see SNOWY_TEST_DATABASE_URL"]
+    async fn test_deliveries_are_fanned_out_claimed_and_recorded() {
+        let pool = testing::database().await;
+        let repository = WebhookRepository::new(pool.clone(), false);
+        let all = repository.subscribe("team-1", URL, vec![]).await.unwrap();
+        let removals = repository
+            .subscribe("team-1", URL, vec!["member-removed".to_string()])
+            .await
+            .unwrap();
+        let disabled = repository.subscribe("team-1", URL, vec![]).await.unwrap();
+        repository
+            .disable("team-1", disabled.subscription.id)
+            .await
+            .unwrap();
+        let other_team = repository.subscribe("team-2", URL, vec![]).await.unwrap();
+        record(&pool, "team-1", 1, "member-added").await;
+
+        assert_eq!(repository.fan_out(10).await.unwrap(), 1);
+        assert_eq!(repository.fan_out(10).await.unwrap(), 0);
+
+        let lease = Duration::from_secs(60);
+        let due = repository.claim_due(10, lease).await.unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].secret, all.secret);
+        assert_eq!(due[0].sequence, 1);
+        // Leased to this dispatcher.
+        assert!(repository.claim_due(10, lease).await.unwrap().is_empty());
+
+        repository
+            .record_attempt(
+                &due[0],
+                &attempt(&due[0], Some("boom")),
+                DeliveryStatus::Pending,
+                Duration::ZERO,
+            )
+            .await
+            .unwrap();
+        let retried = repository.claim_due(10, lease).await.unwrap();
+        assert_eq!(retried.len(), 1);
+        assert_eq!(retried[0].attempts, 1);
+        repository
+            .record_attempt(
+                &retried[0],
+                &attempt(&retried[0], None),
+                DeliveryStatus::Delivered,
+                Duration::ZERO,
+            )
+            .await
+            .unwrap();
+        assert!(repository.claim_due(10, lease).await.unwrap().is_empty());
+
+        let deliveries = repository
+            .deliveries("team-1", all.subscription.id, None, 10)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(deliveries.len(), 1);
+        assert_eq!(deliveries[0].status, DeliveryStatus::Delivered);
+        assert_eq!(deliveries[0].attempts, 2);
+        let history: Vec<_> = deliveries[0]
+            .history
+            .iter()
+            .map(|a| (a.attempt, a.status_code))
+            .collect();
+        assert_eq!(history, vec![(1, Some(500)), (2, Some(204))]);
+
+        for subscription in [&removals, &disabled] {
+            let deliveries = repository
+                .deliveries("team-1", subscription.subscription.id, None, 10)
+                .await
+                .unwrap();
+            assert_eq!(deliveries.unwrap().len(), 0);
+        }
+        assert!(repository
+            .deliveries("team-1", other_team.subscription.id, None, 10)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    #[ignore = "requires PostgreSQL, see SNOWY_TEST_DATABASE_URL"]
+    async fn test_prune_keeps_pending_deliveries() {
+        let pool = testing::database().await;
+        let repository = WebhookRepository::new(pool.clone(), false);
+        repository.subscribe("team-1", URL, vec![]).await.unwrap();
+        record(&pool, "team-1", 1, "member-added").await;
+        record(&pool, "team-2", 1, "member-added").await;
+        repository.fan_out(10).await.unwrap();
+        let due = repository
+            .claim_due(10, Duration::from_secs(60))
+            .await
+            .unwrap();
+        repository
+            .record_attempt(
+                &due[0],
+                &attempt(&due[0], None),
+                DeliveryStatus::Delivered,
+                Duration::ZERO,
+            )
+            .await
+            .unwrap();
+        record(&pool, "team-1", 2, "member-removed").await;
+        repository.fan_out(10).await.unwrap();
+        record(&pool, "team-1", 3, "member-added").await;
+
+        assert_eq!(
+            repository.prune(Duration::from_secs(3600)).await.unwrap(),
+            0
+        );
+        // The delivered entry and the one nobody subscribed to, but neither
+        // the pending one nor the one not processed yet.
+        assert_eq!(repository.prune(Duration::ZERO).await.unwrap(), 2);
+
+        let remaining: Vec<(String, i64)> =
+            sqlx::query_as("SELECT aggregate_id, sequence FROM outbox ORDER BY id")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        assert_eq!(
+            remaining,
+            vec![("team-1".to_string(), 2), ("team-1".to_string(), 3)]
+        );
+    }
+}
@@ -0,0 +1,296 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use reqwest::{header::CONTENT_TYPE, redirect::Policy, Client};
+use serde::Serialize;
+use serde_json::Value;
+use sha2::Sha256;
+use uuid::Uuid;
+
+use super::{
+    address::{check_url, PublicResolver},
+    repository::{DeliveryAttempt, PendingDelivery},
+};
+
+/// `sha256=` followed by the hex HMAC-SHA256 of `<timestamp>.<body>`, keyed
+/// with the webhook secret.
+pub(crate) const SIGNATURE_HEADER: &str = "X-Snowy-Signature";
+/// Unix time at which the request was signed.
+pub(crate) const TIMESTAMP_HEADER: &str = "X-Snowy-Timestamp";
+pub(crate) const EVENT_HEADER: &str = "X-Snowy-Event";
+/// Identifier of the delivery, the same across retries.
+pub(crate) const DELIVERY_HEADER: &str = "X-Snowy-Delivery";
+
+/// Body POSTed to webhooks.
+#[derive(Serialize)]
+struct WebhookPayload<'a> {
+    id: Uuid,
+    team_id: &'a str,
+    sequence: i64,
+    event_type: &'a str,
+    recorded_at: DateTime<Utc>,
+    event: &'a Value,
+}
+
+/// Signs a webhook body sent at `timestamp`.
+pub(crate) fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+pub(crate) struct WebhookSender {
+    client: Client,
+    allow_private_addresses: bool,
+}
+
+impl WebhookSender {
+    /// A sender whose requests time out after `timeout`. Redirects are not
+    /// followed, and unless `allow_private_addresses`, hosts are only
+    /// connected to at their public addresses.
+    pub(crate) fn new(timeout: Duration, allow_private_addresses: bool) -> Self {
+        let mut builder = Client::builder()
+            .timeout(timeout)
+            .redirect(Policy::none())
+            .user_agent(concat!("snowy-webhooks/", env!("CARGO_PKG_VERSION")));
+        if !allow_private_addresses {
+            builder = builder.dns_resolver(Arc::new(PublicResolver));
+        }
+        let client = builder
+            .build()
+            .expect("Failed to build the webhook HTTP client");
+        Self {
+            client,
+            allow_private_addresses,
+        }
+    }
+
+    /// POSTs the delivery once. Any response other than 2xx, and any
+    /// transport error, is recorded as the attempt's error.
+    pub(crate) async fn send(&self, delivery: &PendingDelivery) -> DeliveryAttempt {
+        let body = serde_json::to_vec(&WebhookPayload {
+            id: delivery.id,
+            team_id: &delivery.team_id,
+            sequence: delivery.sequence,
+            event_type: &delivery.event_type,
+            recorded_at: delivery.recorded_at,
+            event: &delivery.payload.0,
+        })
+        .expect("Failed to serialize webhook payload");
+        let attempted_at = Utc::now();
+        let timestamp = attempted_at.timestamp();
+        let started = Instant::now();
+
+        // Checked again: the host may resolve elsewhere since it was registered.
+        let (status_code, error) =
+            match check_url(&delivery.url, self.allow_private_addresses).await {
+                Err(e) => (None, Some(e.message())),
+                Ok(()) => {
+                    let response = self
+                        .client
+                        .post(&delivery.url)
+                        .header(CONTENT_TYPE, "application/json")
+                        .header(SIGNATURE_HEADER, sign(&delivery.secret, timestamp, &body))
+                        .header(TIMESTAMP_HEADER, timestamp.to_string())
+                        .header(EVENT_HEADER, &delivery.event_type)
+                        .header(DELIVERY_HEADER, delivery.id.to_string())
+                        .body(body)
+                        .send()
+                        .await;
+                    match response {
+                        Ok(response) if response.status().is_success() => {
+                            (Some(response.status().as_u16() as i32), None)
+                        }
+                        Ok(response) => (
+                            Some(response.status().as_u16() as i32),
+                            Some(format!("Unexpected response status {}", response.status())),
+                        ),
+                        Err(e) => (None, Some(e.to_string())),
+                    }
+                }
+            };
+
+        DeliveryAttempt {
+            delivery_id: delivery.id,
+            attempt: delivery.attempts + 1,
+            attempted_at,
+            status_code,
+            error,
+            duration_ms: started.elapsed().as_millis() as i64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use sqlx::types::Json;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+        task::JoinHandle,
+    };
+
+    use super::*;
+
+    /// A request captured by [`receiver`].
+    struct Received {
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+    }
+
+    impl Received {
+        fn header(&self, name: &str) -> &str {
+            self.headers
+                .iter()
+                .find(|(n, _)| n.eq_ignore_ascii_case(name))
+                .map(|(_, v)| v.as_str())
+                .unwrap_or_else(|| panic!("missing header {}", name))
+        }
+    }
+
+    /// A local HTTP receiver answering a single request with `status`.
+    async fn receiver(status: &'static str) -> (String, JoinHandle<Received>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hooks", listener.local_addr().unwrap());
+        let handle = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buffer = Vec::new();
+            let mut chunk = [0u8; 4096];
+            let header_end = loop {
+                let n = socket.read(&mut chunk).await.unwrap();
+                buffer.extend_from_slice(&chunk[..n]);
+                if let Some(i) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+                    break i + 4;
+                }
+            };
+            let head = String::from_utf8(buffer[..header_end].to_vec()).unwrap();
+            let headers: Vec<(String, String)> = head
+                .lines()
+                .skip(1)
+                .filter_map(|line| line.split_once(": "))
+                .map(|(n, v)| (n.to_string(), v.to_string()))
+                .collect();
+            let length: usize = headers
+                .iter()
+                .find(|(n, _)| n.eq_ignore_ascii_case("content-length"))
+                .map(|(_, v)| v.parse().unwrap())
+                .unwrap_or(0);
+            while buffer.len() < header_end + length {
+                let n = socket.read(&mut chunk).await.unwrap();
+                buffer.extend_from_slice(&chunk[..n]);
+            }
+            let response = format!(
+                "HTTP/1.1 {}\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                status
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+            Received {
+                headers,
+                body: buffer[header_end..header_end + length].to_vec(),
+            }
+        });
+        (url, handle)
+    }
+
+    fn delivery(url: String) -> PendingDelivery {
+        PendingDelivery {
+            id: Uuid::new_v4(),
+            attempts: 2,
+            url,
+            secret: "whsec_test".to_string(),
+            team_id: "team-1".to_string(),
+            sequence: 7,
+            event_type: "member-added".to_string(),
+            payload: Json(
+                json!({"MemberAdded": {"member_id": "alice", "email": "alice@example.com"}}),
+            ),
+            recorded_at: Utc::now(),
+        }
+    }
+
+    /// A sender to the local receivers of these tests.
+    fn sender() -> WebhookSender {
+        WebhookSender::new(Duration::from_secs(5), true)
+    }
+
+    #[test]
+    fn test_sign() {
+        let signature = sign("secret", 1_700_000_000, b"{}");
+
+        assert!(signature.starts_with("sha256="));
+        assert_eq!(signature.len(), "sha256=".len() + 64);
+        assert_eq!(signature, sign("secret", 1_700_000_000, b"{}"));
+        assert_ne!(signature, sign("other", 1_700_000_000, b"{}"));
+        assert_ne!(signature, sign("secret", 1_700_000_001, b"{}"));
+        assert_ne!(signature, sign("secret", 1_700_000_000, b"[]"));
+    }
+
+    #[tokio::test]
+    async fn test_delivers_signed_payload() {
+        let (url, received) = receiver("204 No Content").await;
+        let delivery = delivery(url);
+
+        let attempt = sender().send(&delivery).await;
+        let received = received.await.unwrap();
+
+        assert_eq!(attempt.attempt, 3);
+        assert_eq!(attempt.status_code, Some(204));
+        assert_eq!(attempt.error, None);
+
+        let timestamp: i64 = received.header(TIMESTAMP_HEADER).parse().unwrap();
+        assert_eq!(
+            received.header(SIGNATURE_HEADER),
+            sign("whsec_test", timestamp, &received.body)
+        );
+        assert_eq!(received.header(EVENT_HEADER), "member-added");
+        assert_eq!(received.header(DELIVERY_HEADER), delivery.id.to_string());
+
+        let body: Value = serde_json::from_slice(&received.body).unwrap();
+        assert_eq!(body["team_id"], "team-1");
+        assert_eq!(body["sequence"], 7);
+        assert_eq!(body["event"]["MemberAdded"]["member_id"], "alice");
+    }
+
+    #[tokio::test]
+    async fn test_records_error_responses() {
+        let (url, received) = receiver("503 Service Unavailable").await;
+
+        let attempt = sender().send(&delivery(url)).await;
+        received.await.unwrap();
+
+        assert_eq!(attempt.status_code, Some(503));
+        assert!(attempt.error.unwrap().contains("503"));
+    }
+
+    #[tokio::test]
+    async fn test_records_connection_errors() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hooks", listener.local_addr().unwrap());
+        drop(listener);
+
+        let attempt = sender().send(&delivery(url)).await;
+
+        assert_eq!(attempt.status_code, None);
+        assert!(attempt.error.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_refuses_private_addresses() {
+        let (url, _received) = receiver("204 No Content").await;
+
+        let attempt = WebhookSender::new(Duration::from_secs(5), false)
+            .send(&delivery(url))
+            .await;
+
+        assert_eq!(attempt.status_code, None);
+        assert!(attempt.error.unwrap().contains("non-public address"));
+    }
+}